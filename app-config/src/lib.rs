@@ -0,0 +1,243 @@
+//! Layered configuration for the samples
+//!
+//! Settings are merged from the following sources. Later sources override earlier ones:
+//!
+//! 1. Defaults (see `AppConfig::default`)
+//! 2. Config file (TOML, `app.toml` or the file given with `--config`)
+//! 3. Environment variables with prefix `APP_` (e.g. `APP_BIND_ADDRESS`)
+//! 4. Command-line args (e.g. `--bind-address`)
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use figment::{
+    providers::{Env, Format, Serialized, Toml},
+    Figment,
+};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the environment variables
+pub const ENV_PREFIX: &str = "APP_";
+
+/// Config file that is used if no file is given on the command line; optional
+pub const DEFAULT_CONFIG_FILE: &str = "app.toml";
+
+/// Settings of an app
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AppConfig {
+    /// Address the HTTP server listens on
+    pub bind_address: SocketAddr,
+
+    /// File to which todo items are persisted
+    pub persist_path: PathBuf,
+
+    /// Directory to which the todo items of all tenants are persisted (one file per tenant)
+    pub persist_dir: PathBuf,
+
+    /// Connection string of the database (e.g. postgres://...)
+    pub database_url: Option<String>,
+
+    /// Key for authenticating requests (e.g. API key or JWT secret)
+    pub auth_key: Option<String>,
+
+    /// PEM file with the TLS certificate chain; HTTPS is used if set (together with tls_key)
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM file with the private key of the TLS certificate
+    pub tls_key: Option<PathBuf>,
+
+    /// Offer HTTP/2 in addition to HTTP/1.1
+    ///
+    /// With TLS, clients choose the protocol during the handshake (ALPN). Without
+    /// TLS, clients have to start with HTTP/2 right away (h2c with prior knowledge).
+    pub http2: bool,
+
+    /// Seconds between saving changed todo items to disk
+    pub autosave_interval: u64,
+
+    /// URL to which changes of todo items are posted (see webhook.rs of todo-axum)
+    pub webhook_url: Option<String>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            persist_path: PathBuf::from("todo_store.json"),
+            persist_dir: PathBuf::from("."),
+            database_url: None,
+            auth_key: None,
+            tls_cert: None,
+            tls_key: None,
+            http2: true,
+            autosave_interval: 30,
+            webhook_url: None,
+        }
+    }
+}
+
+/// Command-line args overriding the settings of all other sources
+///
+/// Args that are not given leave the settings from other sources unchanged.
+#[derive(Parser, Serialize, Debug, Default)]
+#[command(version)]
+pub struct CliArgs {
+    /// Config file (TOML); defaults to app.toml if it exists
+    #[arg(long)]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Address the HTTP server listens on
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<SocketAddr>,
+
+    /// File to which todo items are persisted
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persist_path: Option<PathBuf>,
+
+    /// Directory to which the todo items of all tenants are persisted
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persist_dir: Option<PathBuf>,
+
+    /// Connection string of the database
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database_url: Option<String>,
+
+    /// Key for authenticating requests
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_key: Option<String>,
+
+    /// PEM file with the TLS certificate chain
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM file with the private key of the TLS certificate
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Offer HTTP/2 in addition to HTTP/1.1 (e.g. --http2 false)
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2: Option<bool>,
+
+    /// Seconds between saving changed todo items to disk
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autosave_interval: Option<u64>,
+
+    /// URL to which changes of todo items are posted
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+}
+
+impl AppConfig {
+    /// Builds the figment merging all configuration sources
+    pub fn figment(args: &CliArgs) -> Figment {
+        // An explicitly given config file has to exist, the default one is optional
+        let file = match &args.config {
+            Some(path) => Toml::file_exact(path),
+            None => Toml::file(Path::new(DEFAULT_CONFIG_FILE)),
+        };
+
+        Figment::from(Serialized::defaults(AppConfig::default()))
+            .merge(file)
+            .merge(Env::prefixed(ENV_PREFIX))
+            .merge(Serialized::defaults(args))
+    }
+
+    /// Loads the configuration from all sources
+    ///
+    /// Errors (e.g. invalid TOML or values of the wrong type) name the source
+    /// and the setting that caused them.
+    pub fn load(args: &CliArgs) -> Result<AppConfig, Box<figment::Error>> {
+        Ok(Self::figment(args).extract()?)
+    }
+
+    /// Loads the configuration using the command-line args of the process
+    pub fn load_from_args() -> Result<AppConfig, Box<figment::Error>> {
+        Self::load(&CliArgs::parse())
+    }
+}
+
+// Jail closures have to return figment's (large) error type
+#[cfg(test)]
+#[allow(clippy::result_large_err)]
+mod tests {
+    use super::*;
+    use figment::Jail;
+
+    #[test]
+    fn defaults() {
+        Jail::expect_with(|_| {
+            assert_eq!(AppConfig::load(&CliArgs::default()).unwrap(), AppConfig::default());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn precedence() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                DEFAULT_CONFIG_FILE,
+                r#"
+                bind_address = "127.0.0.1:4000"
+                persist_path = "from_file.json"
+                database_url = "postgres://file"
+                "#,
+            )?;
+            jail.set_env("APP_PERSIST_PATH", "from_env.json");
+            jail.set_env("APP_DATABASE_URL", "postgres://env");
+
+            let args = CliArgs::parse_from(["app", "--database-url", "postgres://cli"]);
+            let config = AppConfig::load(&args).unwrap();
+            assert_eq!(config.bind_address, "127.0.0.1:4000".parse().unwrap());
+            assert_eq!(config.persist_path, PathBuf::from("from_env.json"));
+            assert_eq!(config.database_url.as_deref(), Some("postgres://cli"));
+            assert_eq!(config.auth_key, None);
+            assert!(config.http2);
+
+            let args = CliArgs::parse_from(["app", "--http2", "false"]);
+            assert!(!AppConfig::load(&args).unwrap().http2);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn explicit_config_file() {
+        Jail::expect_with(|jail| {
+            jail.create_file("custom.toml", r#"auth_key = "secret""#)?;
+            let args = CliArgs::parse_from(["app", "--config", "custom.toml"]);
+            assert_eq!(AppConfig::load(&args).unwrap().auth_key.as_deref(), Some("secret"));
+
+            let args = CliArgs::parse_from(["app", "--config", "missing.toml"]);
+            assert!(AppConfig::load(&args).is_err());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn invalid_config_file() {
+        Jail::expect_with(|jail| {
+            jail.create_file(DEFAULT_CONFIG_FILE, r#"bind_address = "not an address""#)?;
+            let error = AppConfig::load(&CliArgs::default()).unwrap_err();
+            let message = error.to_string();
+            assert!(message.contains("bind_address"), "{message}");
+            assert!(message.contains(DEFAULT_CONFIG_FILE), "{message}");
+
+            jail.create_file(DEFAULT_CONFIG_FILE, "bind_address = ")?;
+            assert!(AppConfig::load(&CliArgs::default()).is_err());
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,272 @@
+//! Bearer token (JWT) authentication for the axum samples
+//!
+//! JwtValidator checks signature, issuer, audience, and expiration of a token.
+//! On top of that, there are two ways of protecting routes:
+//!
+//! * The Authenticated extractor for protecting single handlers. The validator has to
+//!   be part of the app state (FromRef).
+//! * AuthLayer, a tower middleware for protecting whole routers. Handlers get the
+//!   claims with the Extension extractor.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+/// Claims of a valid token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Claims {
+    /// Subject, i.e. the authenticated user
+    pub sub: String,
+    pub iss: String,
+    pub aud: String,
+    /// Expiration as seconds since the Unix epoch
+    pub exp: u64,
+}
+
+/// Error when a request is not authenticated
+///
+/// Turns into 401 with a WWW-Authenticate header. Details about invalid tokens
+/// are not sent to the client.
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("missing bearer token")]
+    MissingToken,
+    #[error("invalid token")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let challenge = match self {
+            AuthError::MissingToken => "Bearer",
+            AuthError::InvalidToken(_) => r#"Bearer error="invalid_token""#,
+        };
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, challenge)],
+            self.to_string(),
+        )
+            .into_response()
+    }
+}
+
+/// Validates JWTs signed with a shared secret (HS256)
+pub struct JwtValidator {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtValidator {
+    /// Creates a validator accepting tokens of the given issuer for the given audience
+    pub fn hs256(secret: &[u8], issuer: &str, audience: &str) -> Self {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[issuer]);
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        JwtValidator {
+            key: DecodingKey::from_secret(secret),
+            validation,
+        }
+    }
+
+    /// Checks signature, issuer, audience, and expiration of a token
+    pub fn validate(&self, token: &str) -> Result<Claims, AuthError> {
+        Ok(decode::<Claims>(token, &self.key, &self.validation)?.claims)
+    }
+
+    /// Validates the bearer token in the Authorization header
+    pub fn validate_headers(&self, headers: &HeaderMap) -> Result<Claims, AuthError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AuthError::MissingToken)?;
+        self.validate(token.trim())
+    }
+}
+
+/// Type for sharing a validator between handlers
+pub type SharedValidator = Arc<JwtValidator>;
+
+/// Extractor for the claims of an authenticated request
+///
+/// Rejects requests without valid bearer token with 401.
+pub struct Authenticated(pub Claims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Authenticated
+where
+    SharedValidator: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let validator = SharedValidator::from_ref(state);
+        Ok(Authenticated(validator.validate_headers(&parts.headers)?))
+    }
+}
+
+/// Tower layer rejecting requests without valid bearer token
+///
+/// The claims of authenticated requests are added to the request extensions.
+#[derive(Clone)]
+pub struct AuthLayer {
+    validator: SharedValidator,
+}
+
+impl AuthLayer {
+    pub fn new(validator: SharedValidator) -> Self {
+        AuthLayer { validator }
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            validator: self.validator.clone(),
+        }
+    }
+}
+
+/// Service created by AuthLayer
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    validator: SharedValidator,
+}
+
+impl<S> Service<Request> for AuthService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        match self.validator.validate_headers(req.headers()) {
+            Ok(claims) => {
+                req.extensions_mut().insert(claims);
+                Box::pin(self.inner.call(req))
+            },
+            Err(e) => Box::pin(async move { Ok(e.into_response()) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Extension, Router};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tower::ServiceExt;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn validator() -> SharedValidator {
+        Arc::new(JwtValidator::hs256(SECRET, "https://issuer.example.com", "todo-api"))
+    }
+
+    /// Creates a token expiring in the given number of seconds (negative = expired)
+    fn token(audience: &str, expires_in: i64) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let claims = Claims {
+            sub: "rainer".to_string(),
+            iss: "https://issuer.example.com".to_string(),
+            aud: audience.to_string(),
+            exp: (now + expires_in) as u64,
+        };
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[test]
+    fn valid_token() {
+        let claims = validator().validate(&token("todo-api", 300)).unwrap();
+        assert_eq!(claims.sub, "rainer");
+    }
+
+    #[test]
+    fn expired_token() {
+        // Larger than the default leeway of 60 seconds
+        let result = validator().validate(&token("todo-api", -300));
+        assert!(matches!(result, Err(AuthError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn wrong_audience() {
+        let result = validator().validate(&token("hero-api", 300));
+        assert!(matches!(result, Err(AuthError::InvalidToken(_))));
+    }
+
+    fn request(token: Option<&str>) -> Request {
+        let builder = Request::builder().uri("/");
+        match token {
+            Some(token) => builder.header(header::AUTHORIZATION, format!("Bearer {token}")),
+            None => builder,
+        }
+        .body(Body::empty())
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        async fn handler(Authenticated(claims): Authenticated) -> String {
+            claims.sub
+        }
+        let app = Router::new().route("/", get(handler)).with_state(validator());
+
+        let response = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let response = app.oneshot(request(Some(&token("todo-api", 300)))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        async fn handler(Extension(claims): Extension<Claims>) -> String {
+            claims.sub
+        }
+        let app = Router::new()
+            .route("/", get(handler))
+            .layer(AuthLayer::new(validator()));
+
+        let response = app
+            .clone()
+            .oneshot(request(Some(&token("todo-api", -300))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            r#"Bearer error="invalid_token""#
+        );
+
+        let response = app.oneshot(request(Some(&token("todo-api", 300)))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,302 @@
+//! Optimistic concurrency with ETag and If-Match (RFC 9110)
+//!
+//! Responses carry the version of an entity in the ETag header. Clients send it
+//! back in If-Match when changing the entity. If the entity has been changed in
+//! the meantime, the change is rejected (typically with 412 Precondition Failed).
+//!
+//! The helpers only work on header values (strings). Therefore, they can be used
+//! with any web framework (axum, actix-web, Rocket, warp, ...).
+
+use std::{fmt, str::FromStr};
+
+/// Error for header values that are no valid entity tags
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid entity tag")]
+pub struct ParseError;
+
+/// Result of evaluating an If-Match header that does not allow the change
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PreconditionError {
+    /// Header cannot be parsed; typically answered with 400
+    #[error("invalid If-Match header")]
+    Invalid(#[from] ParseError),
+    /// Entity does not match the header; typically answered with 412
+    #[error("precondition in If-Match header failed")]
+    Failed,
+    /// Header cannot be expressed as a single expected version (see IfMatch::expected_version)
+    #[error("If-Match header has to contain a single entity version")]
+    Unsupported,
+}
+
+/// Entity tag, i.e. the value of an ETag header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag {
+    weak: bool,
+    tag: String,
+}
+
+impl ETag {
+    /// Creates a strong ETag; the tag must not contain double quotes
+    pub fn strong(tag: impl Into<String>) -> ETag {
+        ETag {
+            weak: false,
+            tag: tag.into(),
+        }
+    }
+
+    /// Creates a weak ETag (W/"..."); the tag must not contain double quotes
+    pub fn weak(tag: impl Into<String>) -> ETag {
+        ETag {
+            weak: true,
+            tag: tag.into(),
+        }
+    }
+
+    /// Creates a strong ETag for the version of an entity (e.g. `"3"`)
+    pub fn from_version(version: impl fmt::Display) -> ETag {
+        ETag::strong(version.to_string())
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Tag without W/ prefix and quotes
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Strong comparison: both ETags are strong and have the same tag
+    ///
+    /// Used for If-Match because changes must be based on exactly the current entity.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: both ETags have the same tag, no matter whether they are weak
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parses an ETag at the start of the value; returns the ETag and the rest of the value
+    fn parse_prefix(value: &str) -> Result<(ETag, &str), ParseError> {
+        let (weak, rest) = match value.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let rest = rest.strip_prefix('"').ok_or(ParseError)?;
+        let end = rest.find('"').ok_or(ParseError)?;
+        let tag = &rest[..end];
+        if !tag.chars().all(is_etag_char) {
+            return Err(ParseError);
+        }
+
+        Ok((
+            ETag {
+                weak,
+                tag: tag.to_string(),
+            },
+            &rest[end + 1..],
+        ))
+    }
+}
+
+/// Characters allowed in entity tags (etagc in RFC 9110)
+fn is_etag_char(c: char) -> bool {
+    c == '!' || ('#'..='~').contains(&c) || !c.is_ascii()
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+impl FromStr for ETag {
+    type Err = ParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match ETag::parse_prefix(value.trim())? {
+            (etag, "") => Ok(etag),
+            _ => Err(ParseError),
+        }
+    }
+}
+
+/// Value of an If-Match header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfMatch {
+    /// `*`: the entity has to exist, its version does not matter
+    Any,
+    /// The entity has to match one of the ETags
+    ETags(Vec<ETag>),
+}
+
+impl IfMatch {
+    /// Checks whether the current ETag of an entity fulfills the precondition
+    ///
+    /// `current` is None if the entity does not exist. Uses strong comparison, so
+    /// weak ETags never match.
+    pub fn matches(&self, current: Option<&ETag>) -> bool {
+        match (self, current) {
+            (_, None) => false,
+            (IfMatch::Any, Some(_)) => true,
+            (IfMatch::ETags(etags), Some(current)) => etags.iter().any(|etag| etag.strong_eq(current)),
+        }
+    }
+
+    /// Version the entity must have for the precondition to hold
+    ///
+    /// For stores that compare versions themselves (e.g. in the WHERE clause of an
+    /// UPDATE statement) instead of loading the current entity first. None if any
+    /// version is fine (`*`). Weak ETags never match (Failed). Lists of multiple
+    /// ETags and tags that are no versions are Unsupported.
+    pub fn expected_version<T: FromStr>(&self) -> Result<Option<T>, PreconditionError> {
+        match self {
+            IfMatch::Any => Ok(None),
+            IfMatch::ETags(etags) if etags.iter().all(ETag::is_weak) => Err(PreconditionError::Failed),
+            IfMatch::ETags(etags) => match etags.as_slice() {
+                [etag] => etag.tag().parse().map(Some).map_err(|_| PreconditionError::Unsupported),
+                _ => Err(PreconditionError::Unsupported),
+            },
+        }
+    }
+}
+
+impl FromStr for IfMatch {
+    type Err = ParseError;
+
+    /// Parses `*` or a comma-separated list of ETags
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        const WHITESPACE: [char; 2] = [' ', '\t'];
+
+        if value.trim_matches(WHITESPACE) == "*" {
+            return Ok(IfMatch::Any);
+        }
+
+        // Tags may contain commas, so we cannot simply split the value
+        let mut etags = Vec::new();
+        let mut rest = value;
+        loop {
+            // Lists may contain empty elements
+            rest = rest.trim_start_matches([' ', '\t', ',']);
+            if rest.is_empty() {
+                break;
+            }
+
+            let (etag, remainder) = ETag::parse_prefix(rest)?;
+            etags.push(etag);
+            rest = remainder.trim_start_matches(WHITESPACE);
+            if !rest.is_empty() && !rest.starts_with(',') {
+                return Err(ParseError);
+            }
+        }
+
+        if etags.is_empty() {
+            return Err(ParseError);
+        }
+        Ok(IfMatch::ETags(etags))
+    }
+}
+
+/// Evaluates the If-Match header of a request against the current ETag of an entity
+///
+/// `if_match` is None if the request has no If-Match header; such requests are
+/// unconditional. `current` is None if the entity does not exist.
+pub fn check_if_match(if_match: Option<&str>, current: Option<&ETag>) -> Result<(), PreconditionError> {
+    let Some(if_match) = if_match else {
+        return Ok(());
+    };
+
+    if if_match.parse::<IfMatch>()?.matches(current) {
+        Ok(())
+    } else {
+        Err(PreconditionError::Failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_etags() {
+        assert_eq!(ETag::from_version(3).to_string(), r#""3""#);
+        assert_eq!(ETag::weak("abc").to_string(), r#"W/"abc""#);
+    }
+
+    #[test]
+    fn parse_etags() {
+        assert_eq!(r#""3""#.parse(), Ok(ETag::strong("3")));
+        assert_eq!(r#" W/"abc" "#.parse(), Ok(ETag::weak("abc")));
+        assert_eq!(r#""""#.parse(), Ok(ETag::strong("")));
+
+        for invalid in ["", "3", r#""3"#, r#"w/"3""#, r#"W/ "3""#, r#""a b""#, r#""3" "4""#] {
+            assert_eq!(invalid.parse::<ETag>(), Err(ParseError), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_if_match() {
+        assert_eq!(" * ".parse(), Ok(IfMatch::Any));
+        assert_eq!(
+            r#""1", W/"2",,"a,b""#.parse(),
+            Ok(IfMatch::ETags(vec![
+                ETag::strong("1"),
+                ETag::weak("2"),
+                ETag::strong("a,b")
+            ]))
+        );
+
+        for invalid in ["", " , ", "*, \"1\"", "1", r#""1" "2""#, r#""1";"2""#] {
+            assert_eq!(invalid.parse::<IfMatch>(), Err(ParseError), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn comparison() {
+        assert!(ETag::strong("1").strong_eq(&ETag::strong("1")));
+        assert!(!ETag::strong("1").strong_eq(&ETag::strong("2")));
+        assert!(!ETag::weak("1").strong_eq(&ETag::strong("1")));
+        assert!(!ETag::weak("1").strong_eq(&ETag::weak("1")));
+        assert!(ETag::weak("1").weak_eq(&ETag::strong("1")));
+        assert!(!ETag::weak("1").weak_eq(&ETag::weak("2")));
+    }
+
+    #[test]
+    fn check_preconditions() {
+        let current = ETag::from_version(2);
+        assert_eq!(check_if_match(None, Some(&current)), Ok(()));
+        assert_eq!(check_if_match(None, None), Ok(()));
+        assert_eq!(check_if_match(Some(r#""1", "2""#), Some(&current)), Ok(()));
+        assert_eq!(check_if_match(Some("*"), Some(&current)), Ok(()));
+
+        assert_eq!(
+            check_if_match(Some(r#""1""#), Some(&current)),
+            Err(PreconditionError::Failed)
+        );
+        // If-Match uses strong comparison
+        assert_eq!(
+            check_if_match(Some(r#"W/"2""#), Some(&current)),
+            Err(PreconditionError::Failed)
+        );
+        assert_eq!(check_if_match(Some("*"), None), Err(PreconditionError::Failed));
+        assert_eq!(
+            check_if_match(Some("2"), Some(&current)),
+            Err(PreconditionError::Invalid(ParseError))
+        );
+    }
+
+    #[test]
+    fn expected_versions() {
+        let expected = |value: &str| value.parse::<IfMatch>().unwrap().expected_version::<i32>();
+        assert_eq!(expected(r#""3""#), Ok(Some(3)));
+        assert_eq!(expected("*"), Ok(None));
+        assert_eq!(expected(r#"W/"3""#), Err(PreconditionError::Failed));
+        assert_eq!(expected(r#""3", "4""#), Err(PreconditionError::Unsupported));
+        assert_eq!(expected(r#""abc""#), Err(PreconditionError::Unsupported));
+    }
+}
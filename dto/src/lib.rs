@@ -0,0 +1,298 @@
+//! Request and response types shared by the samples
+//!
+//! All samples (REST, gRPC, GraphQL, Spin, CLI) send and receive the same todo
+//! items. Defining them (including their serde configuration) in a single place
+//! makes sure that the JSON representation does not drift between the samples.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Represents a single todo item
+#[derive(Serialize, Deserialize, Validate, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject, async_graphql::InputObject))]
+#[cfg_attr(feature = "graphql", graphql(input_name = "TodoItemInput"))]
+pub struct TodoItem {
+    #[validate(length(min = 1, max = 200))]
+    pub title: String,
+    #[validate(length(max = 2000))]
+    pub notes: String,
+    pub assigned_to: String,
+    pub completed: bool,
+}
+
+/// DTO for patching a todo item
+#[derive(Serialize, Deserialize, Validate, Debug, Clone)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+pub struct UpdateTodoItem {
+    #[validate(length(min = 1, max = 200))]
+    pub title: Option<String>,
+    #[validate(length(max = 2000))]
+    pub notes: Option<String>,
+    pub assigned_to: Option<String>,
+    pub completed: Option<bool>,
+}
+
+impl UpdateTodoItem {
+    /// Sets the given fields of a todo item; fields that are None stay unchanged
+    ///
+    /// Returns whether the todo item changed, i.e. whether any given field had a different value.
+    pub fn apply_to(self, item: &mut TodoItem) -> bool {
+        let original = item.clone();
+        if let Some(title) = self.title {
+            item.title = title;
+        }
+        if let Some(notes) = self.notes {
+            item.notes = notes;
+        }
+        if let Some(assigned_to) = self.assigned_to {
+            item.assigned_to = assigned_to;
+        }
+        if let Some(completed) = self.completed {
+            item.completed = completed;
+        }
+        *item != original
+    }
+}
+
+/// Represents a todo item with an id
+///
+/// The timestamps are serialized in camelCase (`createdAt`, `updatedAt`). Todo items
+/// stored without timestamps (e.g. by older versions) get the current time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct IdentifyableTodoItem {
+    pub id: usize,
+
+    #[serde(flatten)]
+    #[cfg_attr(feature = "graphql", graphql(flatten))]
+    pub item: TodoItem,
+
+    /// Time at which the todo item has been created
+    #[serde(rename = "createdAt", default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+
+    /// Time at which the todo item has been changed the last time
+    ///
+    /// Equals created_at if the todo item has never been changed. Updates that do
+    /// not change any field (e.g. setting completed to its current value) keep it.
+    #[serde(rename = "updatedAt", default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl IdentifyableTodoItem {
+    /// Creates a todo item that has been created just now
+    pub fn new(id: usize, item: TodoItem) -> IdentifyableTodoItem {
+        let now = Utc::now();
+        IdentifyableTodoItem {
+            id,
+            item,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Parameters for pagination
+///
+/// Used to demonstrate handling of query parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Pagination {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+impl Pagination {
+    /// Ceiling for the page size that is used if no other one is configured
+    pub const DEFAULT_MAX_LIMIT: usize = 100;
+
+    pub fn new(offset: Option<usize>, limit: Option<usize>) -> Pagination {
+        Pagination { offset, limit }
+    }
+
+    /// Reduces the limit to the given ceiling
+    ///
+    /// Without limit, the ceiling is used. With that, clients cannot fetch all
+    /// items (e.g. millions of them) at once.
+    pub fn clamp(&self, max_limit: usize) -> Pagination {
+        Pagination {
+            offset: self.offset,
+            limit: Some(self.limit.map_or(max_limit, |limit| limit.min(max_limit))),
+        }
+    }
+}
+
+/// Filters for lists of todo items
+///
+/// Sent as query parameters (e.g. `?q=milk&assigned_to=rainer&completed=false`).
+/// Todo items have to match all given filters.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TodoFilter {
+    /// Text that the title or notes contain (case-insensitive)
+    pub q: Option<String>,
+    /// Person the todo item is assigned to (case-insensitive)
+    pub assigned_to: Option<String>,
+    pub completed: Option<bool>,
+}
+
+/// Serde functions for lists that are sent as comma-separated strings
+///
+/// Use with `#[serde(with = "dto::comma_separated", default)]` on fields of type
+/// `Option<Vec<String>>`. Lists are serialized as a single string (e.g.
+/// `"flying, strength"`). For deserializing, clients may send a comma-separated
+/// string or a JSON array. Entries are trimmed in both cases.
+pub mod comma_separated {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Lists as they can be sent by clients
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Input {
+        /// Comma-separated string (e.g. "flying, strength")
+        Concatenated(String),
+        /// JSON array (e.g. ["flying", "strength"])
+        List(Vec<String>),
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let input = Option::<Input>::deserialize(deserializer)?;
+        Ok(input.map(|input| match input {
+            Input::Concatenated(list) => list.split(',').map(|s| s.trim().to_string()).collect(),
+            Input::List(list) => list.into_iter().map(|s| s.trim().to_string()).collect(),
+        }))
+    }
+
+    pub fn serialize<S>(list: &Option<Vec<String>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        list.as_ref().map(|list| list.join(", ")).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // The JSON representation is part of the public API of all samples.
+    // These tests make sure that it does not change by accident.
+
+    use super::*;
+    use serde_json::json;
+
+    fn sample_todo() -> TodoItem {
+        TodoItem {
+            title: "Buy milk".to_string(),
+            notes: "Low fat".to_string(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[test]
+    fn identifyable_todo_item_is_flat() {
+        let todo = IdentifyableTodoItem {
+            created_at: "2024-01-02T03:04:05Z".parse().unwrap(),
+            updated_at: "2024-01-03T00:00:00Z".parse().unwrap(),
+            ..IdentifyableTodoItem::new(42, sample_todo())
+        };
+        assert_eq!(
+            serde_json::to_value(&todo).unwrap(),
+            json!({
+                "id": 42, "title": "Buy milk", "notes": "Low fat", "assigned_to": "Rainer", "completed": false,
+                "createdAt": "2024-01-02T03:04:05Z", "updatedAt": "2024-01-03T00:00:00Z"
+            })
+        );
+
+        let roundtrip: IdentifyableTodoItem = serde_json::from_value(serde_json::to_value(&todo).unwrap()).unwrap();
+        assert_eq!(roundtrip, todo);
+    }
+
+    #[test]
+    fn timestamps() {
+        let todo = IdentifyableTodoItem::new(42, sample_todo());
+        assert_eq!(todo.updated_at, todo.created_at);
+
+        // Todo items without timestamps get the current time
+        let before = Utc::now();
+        let todo: IdentifyableTodoItem = serde_json::from_value(
+            json!({ "id": 1, "title": "Buy milk", "notes": "", "assigned_to": "", "completed": false }),
+        )
+        .unwrap();
+        assert!(todo.created_at >= before);
+    }
+
+    #[test]
+    fn update_todo_item_fields_are_optional() {
+        let update: UpdateTodoItem = serde_json::from_value(json!({ "completed": true })).unwrap();
+        let mut todo = sample_todo();
+        assert!(update.clone().apply_to(&mut todo));
+        assert!(todo.completed);
+        assert_eq!(todo.title, "Buy milk");
+        // Setting fields to their current values is no change
+        assert!(!update.apply_to(&mut todo));
+
+        assert!(serde_json::from_value::<TodoItem>(json!({ "title": "Buy milk" })).is_err());
+    }
+
+    #[test]
+    fn todo_item_validation() {
+        assert!(sample_todo().validate().is_ok());
+
+        let empty_title = TodoItem {
+            title: String::new(),
+            ..sample_todo()
+        };
+        assert!(empty_title.validate().unwrap_err().field_errors().contains_key("title"));
+        let long_title = TodoItem {
+            title: "x".repeat(201),
+            ..sample_todo()
+        };
+        assert!(long_title.validate().unwrap_err().field_errors().contains_key("title"));
+        let long_notes = TodoItem {
+            notes: "x".repeat(2001),
+            ..sample_todo()
+        };
+        assert!(long_notes.validate().unwrap_err().field_errors().contains_key("notes"));
+
+        let update: UpdateTodoItem = serde_json::from_value(json!({ "title": "x".repeat(201) })).unwrap();
+        assert!(update.validate().is_err());
+    }
+
+    #[test]
+    fn pagination() {
+        let pagination: Pagination = serde_json::from_value(json!({ "limit": 10 })).unwrap();
+        assert_eq!((pagination.offset, pagination.limit), (None, Some(10)));
+        assert_eq!(
+            serde_json::to_value(Pagination::new(Some(5), None)).unwrap(),
+            json!({ "offset": 5, "limit": null })
+        );
+    }
+
+    #[test]
+    fn pagination_clamp() {
+        let max = Pagination::DEFAULT_MAX_LIMIT;
+        let clamped = Pagination::new(Some(5), Some(10_000)).clamp(max);
+        assert_eq!((clamped.offset, clamped.limit), (Some(5), Some(max)));
+        assert_eq!(Pagination::new(None, None).clamp(max).limit, Some(max));
+        assert_eq!(Pagination::new(None, Some(10)).clamp(max).limit, Some(10));
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct List {
+        #[serde(with = "comma_separated", default)]
+        entries: Option<Vec<String>>,
+    }
+
+    #[test]
+    fn comma_separated() {
+        let list: List = serde_json::from_value(json!({ "entries": "a, b" })).unwrap();
+        assert_eq!(list.entries.as_deref(), Some(&["a".to_string(), "b".to_string()][..]));
+        let list: List = serde_json::from_value(json!({ "entries": ["a", " b"] })).unwrap();
+        assert_eq!(serde_json::to_value(&list).unwrap(), json!({ "entries": "a, b" }));
+
+        let list: List = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(serde_json::to_value(&list).unwrap(), json!({ "entries": null }));
+        assert!(serde_json::from_value::<List>(json!({ "entries": 42 })).is_err());
+    }
+}
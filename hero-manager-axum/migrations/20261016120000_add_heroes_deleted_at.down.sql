@@ -0,0 +1,4 @@
+DELETE FROM heroes WHERE deleted_at IS NOT NULL;
+DROP INDEX IF EXISTS IX_name;
+CREATE UNIQUE INDEX IX_name ON heroes (name);
+ALTER TABLE heroes DROP COLUMN deleted_at;
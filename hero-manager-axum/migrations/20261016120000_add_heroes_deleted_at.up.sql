@@ -0,0 +1,4 @@
+ALTER TABLE heroes ADD COLUMN deleted_at timestamptz(0) NULL;
+-- Names of deleted heroes can be reused
+DROP INDEX IF EXISTS IX_name;
+CREATE UNIQUE INDEX IX_name ON heroes (name) WHERE deleted_at IS NULL;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hero_audit;
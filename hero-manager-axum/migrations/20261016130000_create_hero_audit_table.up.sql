@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS hero_audit (
+    id bigserial PRIMARY KEY,
+    hero_id bigint NOT NULL,
+    action text NOT NULL CHECK (action IN ('insert', 'update', 'delete')),
+    changed_at timestamptz NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IX_hero_audit_hero_id ON hero_audit (hero_id);
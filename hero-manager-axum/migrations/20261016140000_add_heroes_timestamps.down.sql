@@ -0,0 +1,2 @@
+ALTER TABLE heroes DROP COLUMN updated_at;
+ALTER TABLE heroes DROP COLUMN created_at;
@@ -0,0 +1,2 @@
+ALTER TABLE heroes ADD COLUMN created_at timestamptz NOT NULL DEFAULT NOW();
+ALTER TABLE heroes ADD COLUMN updated_at timestamptz NOT NULL DEFAULT NOW();
@@ -0,0 +1,160 @@
+// Helpers for Axum
+//
+// This module contains extractors for
+// * checking out a database connection from the connection pool for the duration
+//   of a request and
+// * deserializing and validating JSON request bodies.
+
+use crate::{data::log_error, error};
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::{FromRef, FromRequest, FromRequestParts},
+    http::{request::Parts, Request},
+    BoxError, Json,
+};
+use serde::de::DeserializeOwned;
+use sqlx::{pool::PoolConnection, PgPool, Postgres};
+use validator::Validate;
+
+/// Database connection checked out from the pool for a single request
+///
+/// The connection goes back to the pool when the extractor is dropped
+/// at the end of the request.
+pub struct DatabaseConnection(pub PoolConnection<Postgres>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for DatabaseConnection
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = error::Error;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let pool = PgPool::from_ref(state);
+        let conn = pool.acquire().await.map_err(log_error)?;
+        Ok(Self(conn))
+    }
+}
+
+/// JSON request body that has been validated
+///
+/// Works like axum's Json extractor, but additionally validates the body. Invalid
+/// bodies are rejected with 422 (Error::InvalidEntity), malformed JSON with
+/// Error::InvalidJson. Both are sent as problem+json.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = error::Error;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        value.validate()?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, StatusCode},
+        routing::post,
+        Router,
+    };
+    use serde::Deserialize;
+    use sqlx::postgres::PgPoolOptions;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    #[derive(Deserialize, Validate)]
+    struct Named {
+        #[validate(length(min = 1))]
+        name: String,
+    }
+
+    async fn post_json(body: &'static str) -> (StatusCode, serde_json::Value) {
+        async fn handler(ValidatedJson(named): ValidatedJson<Named>) -> String {
+            named.name
+        }
+
+        let app = Router::new().route("/", post(handler));
+        let request = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+    }
+
+    #[tokio::test]
+    async fn valid_json() {
+        assert_eq!(post_json(r#"{ "name": "Batman" }"#).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn invalid_entity() {
+        let (status, problem) = post_json(r#"{ "name": "" }"#).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["title"], "Unprocessable entity in request body");
+    }
+
+    #[tokio::test]
+    async fn malformed_json() {
+        let (status, problem) = post_json(r#"{ "name": "#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(problem["title"], "Invalid JSON in request body");
+
+        // Syntactically valid, but not a Named
+        let (status, _) = post_json(r#"{ "name": 42 }"#).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn rejects_without_connection() {
+        // The only connection of the pool can never be established, so the pool is
+        // exhausted for every request.
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://postgres@127.0.0.1:1/heroes")
+            .unwrap();
+
+        let (mut parts, _) = Request::new(()).into_parts();
+        let result = DatabaseConnection::from_request_parts(&mut parts, &pool).await;
+        assert!(matches!(result, Err(error::Error::Sqlx(_))));
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn rejects_when_pool_exhausted(
+        pool_options: PgPoolOptions,
+        connect_options: sqlx::postgres::PgConnectOptions,
+    ) {
+        let pool = pool_options
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_with(connect_options)
+            .await
+            .unwrap();
+
+        // Keep the only connection busy
+        let _conn = pool.acquire().await.unwrap();
+
+        let (mut parts, _) = Request::new(()).into_parts();
+        let result = DatabaseConnection::from_request_parts(&mut parts, &pool).await;
+        assert!(matches!(result, Err(error::Error::Sqlx(sqlx::Error::PoolTimedOut))));
+    }
+}
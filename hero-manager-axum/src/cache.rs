@@ -0,0 +1,237 @@
+// Read-through cache for hero listings
+//
+// Clients often repeat the same listing queries (e.g. when users page back and
+// forth). CachedHeroesRepository wraps another repository (decorator) and keeps
+// results of get_by_name in memory for a short time. All changes of heroes clear
+// the cache. As the repository trait does not change, handlers are unaware of
+// the cache.
+//
+// Note that the cache is local to the process. If multiple instances of the
+// API are running, changes made through one instance become visible in the
+// others only after the TTL has elapsed.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axum::async_trait;
+use futures::stream::BoxStream;
+use moka::future::Cache;
+
+use crate::{
+    data::{HeroPkVersion, HeroesRepositoryTrait},
+    heroes::DynHeroesRepository,
+    model::{GetHeroFilter, Hero, HeroOrdering, IdentifyableHero, Pagination, UpdateHero},
+};
+
+/// Maximum number of cached listings
+const MAX_CACHED_LISTINGS: u64 = 1_000;
+
+/// Arguments of get_by_name identifying a cached listing
+type ListingKey = (GetHeroFilter, HeroOrdering, Pagination);
+
+/// Repository caching hero listings of another repository
+pub struct CachedHeroesRepository {
+    inner: DynHeroesRepository,
+    listings: Cache<ListingKey, Vec<IdentifyableHero>>,
+    /// Incremented whenever the cache is cleared
+    ///
+    /// Prevents caching results of queries that ran concurrently with a change.
+    generation: AtomicU64,
+}
+
+impl CachedHeroesRepository {
+    /// Wraps a repository; listings are cached for the given time
+    pub fn new(inner: DynHeroesRepository, ttl: Duration) -> Self {
+        CachedHeroesRepository {
+            inner,
+            listings: Cache::builder()
+                .max_capacity(MAX_CACHED_LISTINGS)
+                .time_to_live(ttl)
+                .build(),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Clears the cache after heroes have been changed
+    fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.listings.invalidate_all();
+    }
+}
+
+#[async_trait]
+impl HeroesRepositoryTrait for CachedHeroesRepository {
+    async fn cleanup(&self, filter: &GetHeroFilter) -> Result<Vec<i64>, sqlx::error::Error> {
+        let result = self.inner.cleanup(filter).await;
+        self.invalidate();
+        result
+    }
+
+    async fn delete(&self, id: i64) -> Result<bool, sqlx::error::Error> {
+        let result = self.inner.delete(id).await;
+        self.invalidate();
+        result
+    }
+
+    async fn get_by_name(
+        &self,
+        filter: &GetHeroFilter,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        let key = (filter.clone(), ordering, pagination);
+        if let Some(heroes) = self.listings.get(&key).await {
+            return Ok(heroes);
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let heroes = self.inner.get_by_name(filter, ordering, pagination).await?;
+        if generation == self.generation.load(Ordering::SeqCst) {
+            self.listings.insert(key.clone(), heroes.clone()).await;
+
+            // A change may have cleared the cache between the check above and the
+            // insert. In that case, the inserted listing might be stale.
+            if generation != self.generation.load(Ordering::SeqCst) {
+                self.listings.invalidate(&key).await;
+            }
+        }
+        Ok(heroes)
+    }
+
+    async fn get_after(&self, after_id: i64, limit: i64) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.get_after(after_id, limit).await
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.search(term, ordering, pagination).await
+    }
+
+    fn stream_by_name(
+        &self,
+        filter: GetHeroFilter,
+        ordering: HeroOrdering,
+    ) -> BoxStream<'static, Result<IdentifyableHero, sqlx::error::Error>> {
+        self.inner.stream_by_name(filter, ordering)
+    }
+
+    async fn count(&self, filter: &GetHeroFilter) -> Result<i64, sqlx::error::Error> {
+        self.inner.count(filter).await
+    }
+
+    async fn count_search(&self, term: &str) -> Result<i64, sqlx::error::Error> {
+        self.inner.count_search(term).await
+    }
+
+    async fn exists_by_name(&self, name: &str) -> Result<bool, sqlx::error::Error> {
+        self.inner.exists_by_name(name).await
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.get_by_id(id).await
+    }
+
+    async fn insert(&self, hero: &Hero) -> Result<HeroPkVersion, sqlx::error::Error> {
+        let result = self.inner.insert(hero).await;
+        self.invalidate();
+        result
+    }
+
+    async fn update(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &UpdateHero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        let result = self.inner.update(id, expected_version, hero).await;
+        self.invalidate();
+        result
+    }
+
+    async fn replace(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &Hero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        let result = self.inner.replace(id, expected_version, hero).await;
+        self.invalidate();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::MockHeroesRepositoryTrait;
+    use std::sync::Arc;
+
+    fn filter(name: &str) -> GetHeroFilter {
+        GetHeroFilter {
+            name_filter: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn heroes() -> Vec<IdentifyableHero> {
+        vec![IdentifyableHero {
+            id: 1,
+            ..Default::default()
+        }]
+    }
+
+    async fn get(repo: &CachedHeroesRepository, name: &str) -> Vec<IdentifyableHero> {
+        repo.get_by_name(&filter(name), HeroOrdering::default(), Pagination::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn identical_queries_are_cached() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_get_by_name()
+            .withf(|filter, _, _| filter.name_filter.as_deref() == Some("Super%"))
+            .times(1)
+            .returning(|_, _, _| Ok(heroes()));
+        repo_mock
+            .expect_get_by_name()
+            .withf(|filter, _, _| filter.name_filter.as_deref() == Some("Bat%"))
+            .times(1)
+            .returning(|_, _, _| Ok(vec![]));
+
+        let repo = CachedHeroesRepository::new(Arc::new(repo_mock), Duration::from_secs(60));
+        assert_eq!(get(&repo, "Super%").await.len(), 1);
+        assert_eq!(get(&repo, "Super%").await.len(), 1);
+        // Different filters are cached separately
+        assert!(get(&repo, "Bat%").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn changes_invalidate_cache() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_get_by_name()
+            .times(3)
+            .returning(|_, _, _| Ok(heroes()));
+        repo_mock.expect_delete().times(1).returning(|_| Ok(true));
+        repo_mock
+            .expect_insert()
+            .times(1)
+            .returning(|_| Ok(HeroPkVersion::default()));
+
+        let repo = CachedHeroesRepository::new(Arc::new(repo_mock), Duration::from_secs(60));
+        get(&repo, "Super%").await;
+        get(&repo, "Super%").await;
+        repo.delete(1).await.unwrap();
+        get(&repo, "Super%").await;
+        repo.insert(&Hero::default()).await.unwrap();
+        get(&repo, "Super%").await;
+    }
+}
@@ -4,21 +4,29 @@
 // A core idea of this sample implementation is the use of a trait with automock.
 // With that, web API handler functions can be unit-tested.
 //
-// Note that this sample focusses on web APIs and Axum. Therefore, no integration
-// tests have been developed with sqlx (read more about that topic at
-// https://docs.rs/sqlx/latest/sqlx/attr.test.html).
+// In addition to that, the repository itself is covered by integration tests using
+// sqlx::test (read more about that topic at https://docs.rs/sqlx/latest/sqlx/attr.test.html).
+// They need a running Postgres server and are therefore only compiled with the
+// integration-tests feature.
 
-use crate::model::{Hero, IdentifyableHero};
+use crate::model::{GetHeroFilter, Hero, HeroOrdering, IdentifyableHero, Pagination, UpdateHero};
 use axum::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{stream::BoxStream, StreamExt};
 #[cfg(test)]
 use mockall::automock;
-use sqlx::PgPool;
-use tracing::error;
+use sqlx::{FromRow, PgConnection, PgPool, Postgres, QueryBuilder};
+use std::{future::Future, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{error, instrument, warn};
 
-/// Represents primary key and version data for a hero
+/// Represents primary key, version, and timestamps of a newly created hero
+#[derive(FromRow, Default)]
 pub struct HeroPkVersion {
     pub id: i64,
     pub version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 /// Logs an sqlx error
@@ -27,54 +35,795 @@ pub fn log_error(e: sqlx::Error) -> sqlx::Error {
     e
 }
 
+/// Maximum number of attempts for idempotent reads
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry; doubled for every further retry
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Checks whether an error is worth retrying
+///
+/// Connection problems and pool timeouts typically go away after a short while.
+/// Other errors (e.g. constraint violations) will not and must not be retried.
+fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // SQLSTATE class 08 = connection exception
+        sqlx::Error::Database(db_error) => db_error.code().is_some_and(|code| code.starts_with("08")),
+        _ => false,
+    }
+}
+
+/// Runs an idempotent database operation, retrying transient errors with exponential backoff
+async fn retry<T, F, Fut>(mut operation: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                warn!("Transient database error in attempt {}, retrying: {:?}", attempt, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
 /// Repository for maintaining heroes in the DB
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait HeroesRepositoryTrait {
-    /// Deletes all heroes from the DB
-    async fn cleanup(&self) -> Result<(), sqlx::error::Error>;
+    /// Deletes all heroes from the DB matching the given filter
+    ///
+    /// Heroes are soft-deleted, i.e. they stay in the DB with deleted_at set.
+    /// An empty filter deletes all heroes. Returns the ids of the deleted heroes.
+    async fn cleanup(&self, filter: &GetHeroFilter) -> Result<Vec<i64>, sqlx::error::Error>;
+
+    /// Deletes a single hero from the DB
+    ///
+    /// The hero is soft-deleted. Returns whether a hero has been deleted.
+    async fn delete(&self, id: i64) -> Result<bool, sqlx::error::Error>;
+
+    /// Gets a sorted page of heroes from the DB filted by name and other criteria
+    async fn get_by_name(
+        &self,
+        filter: &GetHeroFilter,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error>;
 
-    /// Gets a list of heroes from the DB filted by name
-    async fn get_by_name(&self, name: &str) -> Result<Vec<IdentifyableHero>, sqlx::error::Error>;
+    /// Gets the heroes with an id greater than the given one, ordered by id
+    async fn get_after(&self, after_id: i64, limit: i64) -> Result<Vec<IdentifyableHero>, sqlx::error::Error>;
+
+    /// Gets a sorted page of heroes whose name or realname contains the given text
+    ///
+    /// The comparison is case-insensitive.
+    async fn search(
+        &self,
+        term: &str,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error>;
+
+    /// Streams all heroes from the DB filtered by name and other criteria
+    ///
+    /// In contrast to get_by_name, rows are not buffered. They are returned one by one
+    /// while they are read from the DB.
+    fn stream_by_name(
+        &self,
+        filter: GetHeroFilter,
+        ordering: HeroOrdering,
+    ) -> BoxStream<'static, Result<IdentifyableHero, sqlx::error::Error>>;
+
+    /// Counts the heroes in the DB matching the given filter
+    async fn count(&self, filter: &GetHeroFilter) -> Result<i64, sqlx::error::Error>;
+
+    /// Counts the heroes whose name or realname contains the given text (see search)
+    async fn count_search(&self, term: &str) -> Result<i64, sqlx::error::Error>;
+
+    /// Checks whether a (not deleted) hero with exactly the given name exists
+    async fn exists_by_name(&self, name: &str) -> Result<bool, sqlx::error::Error>;
+
+    /// Gets a single hero from the DB by id
+    async fn get_by_id(&self, id: i64) -> Result<Option<IdentifyableHero>, sqlx::error::Error>;
 
     /// Insert a new hero in the DB
     async fn insert(&self, hero: &Hero) -> Result<HeroPkVersion, sqlx::error::Error>;
+
+    /// Updates the given fields of a hero in the DB
+    ///
+    /// If an expected version is given, the hero is only updated if its version matches
+    /// (optimistic concurrency). Returns None if no row has been updated.
+    async fn update(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &UpdateHero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error>;
+
+    /// Replaces all fields of a hero in the DB
+    ///
+    /// Optimistic concurrency works like in update. Returns None if no row has been updated.
+    async fn replace(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &Hero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error>;
+}
+
+/// Adds the WHERE clause for the given filter to a query
+///
+/// Optional filter criteria lead to additional predicates. Therefore, we use
+/// sqlx's QueryBuilder to build the statement dynamically.
+fn push_filter<'a>(query: &mut QueryBuilder<'a, Postgres>, filter: &'a GetHeroFilter) {
+    // ILIKE so that the name filter is case-insensitive
+    query.push(" WHERE name ILIKE ");
+    query.push_bind(filter.name_filter.as_deref().unwrap_or("%"));
+    if let Some(can_fly) = filter.can_fly {
+        query.push(" AND can_fly = ").push_bind(can_fly);
+    }
+    if let Some(ability) = &filter.ability {
+        // Abilities are stored as a Postgres array
+        query.push(" AND ").push_bind(ability).push(" = ANY(abilities)");
+    }
+    if !filter.include_deleted {
+        query.push(" AND deleted_at IS NULL");
+    }
+}
+
+/// Builds the SELECT statement for all filtered heroes, sorted
+fn select_all_heroes(filter: &GetHeroFilter, ordering: HeroOrdering) -> QueryBuilder<'_, Postgres> {
+    let mut query = QueryBuilder::new("SELECT * FROM heroes");
+    push_filter(&mut query, filter);
+
+    // Note that ORDER BY cannot use bind parameters. HeroOrdering only
+    // produces whitelisted column names, so adding it to the SQL is safe.
+    query.push(" ").push(ordering.to_sql());
+    query
+}
+
+/// Builds the SELECT statement for a filtered, sorted page of heroes
+pub fn select_heroes<'a>(
+    filter: &'a GetHeroFilter,
+    ordering: HeroOrdering,
+    pagination: Pagination,
+) -> QueryBuilder<'a, Postgres> {
+    let mut query = select_all_heroes(filter, ordering);
+    query.push(" LIMIT ").push_bind(pagination.limit);
+    query.push(" OFFSET ").push_bind(pagination.offset);
+    query
+}
+
+/// Builds the SELECT statement for a sorted page of heroes matching a search term
+fn search_heroes<'a>(term: &'a str, ordering: HeroOrdering, pagination: Pagination) -> QueryBuilder<'a, Postgres> {
+    let mut query = QueryBuilder::new("SELECT * FROM heroes");
+    push_search(&mut query, term);
+    query.push(" ").push(ordering.to_sql());
+    query.push(" LIMIT ").push_bind(pagination.limit);
+    query.push(" OFFSET ").push_bind(pagination.offset);
+    query
+}
+
+/// Appends the WHERE clause matching heroes whose name or realname contains the term
+fn push_search<'a>(query: &mut QueryBuilder<'a, Postgres>, term: &'a str) {
+    query.push(" WHERE (name ILIKE '%' || ");
+    query.push_bind(term);
+    query
+        .push(" || '%' OR realname ILIKE '%' || ")
+        .push_bind(term)
+        .push(" || '%')");
+    query.push(" AND deleted_at IS NULL");
+}
+
+/// Writes an audit record for a change of a hero
+///
+/// Has to be called with the transaction of the change so that the audit
+/// record is only written if the change is committed.
+async fn audit(conn: &mut PgConnection, action: &str, hero_id: i64) -> Result<(), sqlx::error::Error> {
+    sqlx::query("INSERT INTO hero_audit (hero_id, action) VALUES ($1, $2)")
+        .bind(hero_id)
+        .bind(action)
+        .execute(conn)
+        .await?;
+    Ok(())
 }
 
 /// Implementation of the heroes repository
+///
+/// Every DB operation runs in its own tracing span. With that, DB queries are
+/// visible in traces exported to an OpenTelemetry collector.
 pub struct HeroesRepository(pub PgPool);
 
 #[async_trait]
 impl HeroesRepositoryTrait for HeroesRepository {
-    async fn cleanup(&self) -> Result<(), sqlx::error::Error> {
-        sqlx::query("DELETE FROM heroes").execute(&self.0).await?;
-        Ok(())
+    #[instrument(skip(self), err)]
+    async fn cleanup(&self, filter: &GetHeroFilter) -> Result<Vec<i64>, sqlx::error::Error> {
+        // Heroes that have already been deleted must keep their deletion timestamp
+        let filter = GetHeroFilter {
+            include_deleted: false,
+            ..filter.clone()
+        };
+
+        // Audit records for all deleted heroes are written in the same statement.
+        // Therefore, the inserted audit records reference exactly the deleted heroes.
+        let mut query = QueryBuilder::new("WITH deleted AS (UPDATE heroes SET deleted_at = NOW()");
+        push_filter(&mut query, &filter);
+        query.push(" RETURNING id) INSERT INTO hero_audit (hero_id, action) SELECT id, 'delete' FROM deleted");
+        query.push(" RETURNING hero_id");
+        query.build_query_scalar().fetch_all(&self.0).await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn delete(&self, id: i64) -> Result<bool, sqlx::error::Error> {
+        let mut tx = self.0.begin().await?;
+        let result = sqlx::query("UPDATE heroes SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        audit(&mut tx, "delete", id).await?;
+        tx.commit().await?;
+        Ok(true)
     }
 
-    async fn get_by_name(&self, name: &str) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
-        sqlx::query_as::<_, IdentifyableHero>("SELECT * FROM heroes WHERE name LIKE $1")
-            .bind(name)
+    #[instrument(skip(self), err)]
+    async fn get_by_name(
+        &self,
+        filter: &GetHeroFilter,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        retry(|| async {
+            select_heroes(filter, ordering, pagination)
+                .build_query_as::<IdentifyableHero>()
+                .fetch_all(&self.0)
+                .await
+        })
+        .await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn get_after(&self, after_id: i64, limit: i64) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        retry(|| {
+            sqlx::query_as::<_, IdentifyableHero>(
+                "SELECT * FROM heroes WHERE id > $1 AND deleted_at IS NULL ORDER BY id LIMIT $2",
+            )
+            .bind(after_id)
+            .bind(limit)
             .fetch_all(&self.0)
-            .await
+        })
+        .await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn search(
+        &self,
+        term: &str,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        retry(|| async {
+            search_heroes(term, ordering, pagination)
+                .build_query_as::<IdentifyableHero>()
+                .fetch_all(&self.0)
+                .await
+        })
+        .await
+    }
+
+    fn stream_by_name(
+        &self,
+        filter: GetHeroFilter,
+        ordering: HeroOrdering,
+    ) -> BoxStream<'static, Result<IdentifyableHero, sqlx::error::Error>> {
+        // The row stream of sqlx borrows the query. Therefore, we read the rows in
+        // a separate task and hand them over through a bounded channel. The channel
+        // provides backpressure if the client reads slower than the DB delivers.
+        let (tx, rx) = mpsc::channel(32);
+        let pool = self.0.clone();
+        tokio::spawn(async move {
+            let mut query = select_all_heroes(&filter, ordering);
+            let mut rows = query.build_query_as::<IdentifyableHero>().fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+
+                // Stop reading if the client has gone away or after an error
+                if tx.send(row).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|row| (row, rx)) }).boxed()
+    }
+
+    #[instrument(skip(self), err)]
+    async fn count(&self, filter: &GetHeroFilter) -> Result<i64, sqlx::error::Error> {
+        retry(|| async {
+            let mut query = QueryBuilder::new("SELECT COUNT(*) FROM heroes");
+            push_filter(&mut query, filter);
+            query.build_query_scalar().fetch_one(&self.0).await
+        })
+        .await
     }
 
+    #[instrument(skip(self), err)]
+    async fn count_search(&self, term: &str) -> Result<i64, sqlx::error::Error> {
+        retry(|| async {
+            let mut query = QueryBuilder::new("SELECT COUNT(*) FROM heroes");
+            push_search(&mut query, term);
+            query.build_query_scalar().fetch_one(&self.0).await
+        })
+        .await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn exists_by_name(&self, name: &str) -> Result<bool, sqlx::error::Error> {
+        retry(|| {
+            sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM heroes WHERE name = $1 AND deleted_at IS NULL)")
+                .bind(name)
+                .fetch_one(&self.0)
+        })
+        .await
+    }
+
+    #[instrument(skip(self), err)]
+    async fn get_by_id(&self, id: i64) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        retry(|| {
+            sqlx::query_as::<_, IdentifyableHero>("SELECT * FROM heroes WHERE id = $1 AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_optional(&self.0)
+        })
+        .await
+    }
+
+    #[instrument(skip(self, hero), err)]
     async fn insert(&self, hero: &Hero) -> Result<HeroPkVersion, sqlx::error::Error> {
-        let pk: (i64, i32) = sqlx::query_as(
+        let mut tx = self.0.begin().await?;
+        let pk = sqlx::query_as::<_, HeroPkVersion>(
             r#"
             INSERT INTO heroes (first_seen, name, can_fly, realname, abilities)
             VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, version"#,
+            RETURNING id, version, created_at, updated_at"#,
+        )
+        .bind(hero.first_seen)
+        .bind(&hero.name)
+        .bind(hero.can_fly)
+        .bind(&hero.realname)
+        .bind(&hero.abilities)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit(&mut tx, "insert", pk.id).await?;
+        tx.commit().await?;
+        Ok(pk)
+    }
+
+    #[instrument(skip(self, hero), err)]
+    async fn update(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &UpdateHero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        let mut tx = self.0.begin().await?;
+        let updated = sqlx::query_as::<_, IdentifyableHero>(
+            r#"
+            UPDATE heroes SET
+                first_seen = COALESCE($3, first_seen),
+                name = COALESCE($4, name),
+                can_fly = COALESCE($5, can_fly),
+                realname = COALESCE($6, realname),
+                abilities = COALESCE($7, abilities),
+                version = version + 1,
+                updated_at = NOW()
+            WHERE id = $1 AND deleted_at IS NULL AND ($2::integer IS NULL OR version = $2)
+            RETURNING *"#,
+        )
+        .bind(id)
+        .bind(expected_version)
+        .bind(hero.first_seen)
+        .bind(&hero.name)
+        .bind(hero.can_fly)
+        .bind(&hero.realname)
+        .bind(&hero.abilities)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(hero) = &updated {
+            audit(&mut tx, "update", hero.id).await?;
+            tx.commit().await?;
+        }
+        Ok(updated)
+    }
+
+    #[instrument(skip(self, hero), err)]
+    async fn replace(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &Hero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        let mut tx = self.0.begin().await?;
+        let replaced = sqlx::query_as::<_, IdentifyableHero>(
+            r#"
+            UPDATE heroes SET
+                first_seen = $3,
+                name = $4,
+                can_fly = $5,
+                realname = $6,
+                abilities = $7,
+                version = version + 1,
+                updated_at = NOW()
+            WHERE id = $1 AND deleted_at IS NULL AND ($2::integer IS NULL OR version = $2)
+            RETURNING *"#,
         )
+        .bind(id)
+        .bind(expected_version)
         .bind(hero.first_seen)
         .bind(&hero.name)
         .bind(hero.can_fly)
         .bind(&hero.realname)
         .bind(&hero.abilities)
-        .fetch_one(&self.0)
+        .fetch_optional(&mut *tx)
         .await?;
-        Ok(HeroPkVersion {
-            id: pk.0,
-            version: pk.1,
+
+        if let Some(hero) = &replaced {
+            audit(&mut tx, "update", hero.id).await?;
+            tx.commit().await?;
+        }
+        Ok(replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        None,
+        "SELECT * FROM heroes WHERE name ILIKE $1 AND deleted_at IS NULL ORDER BY id ASC LIMIT $2 OFFSET $3"
+    )]
+    #[case(
+        Some(true),
+        "SELECT * FROM heroes WHERE name ILIKE $1 AND can_fly = $2 AND deleted_at IS NULL \
+         ORDER BY id ASC LIMIT $3 OFFSET $4"
+    )]
+    #[case(
+        Some(false),
+        "SELECT * FROM heroes WHERE name ILIKE $1 AND can_fly = $2 AND deleted_at IS NULL \
+         ORDER BY id ASC LIMIT $3 OFFSET $4"
+    )]
+    fn select_heroes_can_fly(#[case] can_fly: Option<bool>, #[case] expected: &str) {
+        let filter = GetHeroFilter {
+            can_fly,
+            ..Default::default()
+        };
+        let query = select_heroes(&filter, HeroOrdering::default(), Pagination::default());
+        assert_eq!(expected, query.sql());
+    }
+
+    #[test]
+    fn select_heroes_ability() {
+        let filter = GetHeroFilter {
+            can_fly: Some(true),
+            ability: Some("strength".to_string()),
+            ..Default::default()
+        };
+        let query = select_heroes(&filter, HeroOrdering::default(), Pagination::default());
+        assert_eq!(
+            "SELECT * FROM heroes WHERE name ILIKE $1 AND can_fly = $2 AND $3 = ANY(abilities) \
+             AND deleted_at IS NULL ORDER BY id ASC LIMIT $4 OFFSET $5",
+            query.sql()
+        );
+    }
+
+    #[test]
+    fn select_heroes_include_deleted() {
+        let filter = GetHeroFilter {
+            include_deleted: true,
+            ..Default::default()
+        };
+        let query = select_heroes(&filter, HeroOrdering::default(), Pagination::default());
+        assert_eq!(
+            "SELECT * FROM heroes WHERE name ILIKE $1 ORDER BY id ASC LIMIT $2 OFFSET $3",
+            query.sql()
+        );
+    }
+
+    #[test]
+    fn search_heroes_sql() {
+        let query = search_heroes("man", HeroOrdering::default(), Pagination::default());
+        assert_eq!(
+            "SELECT * FROM heroes WHERE (name ILIKE '%' || $1 || '%' OR realname ILIKE '%' || $2 || '%') \
+             AND deleted_at IS NULL ORDER BY id ASC LIMIT $3 OFFSET $4",
+            query.sql()
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_transient_error() {
+        // Fails twice with a transient error, then succeeds
+        let mut calls = 0;
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_get_by_id().times(3).returning(move |_| {
+            calls += 1;
+            if calls < 3 {
+                Err(sqlx::Error::PoolTimedOut)
+            } else {
+                Ok(None)
+            }
+        });
+
+        let result = retry(|| repo_mock.get_by_id(1)).await;
+        assert!(matches!(result, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_get_by_id()
+            .times(MAX_ATTEMPTS as usize)
+            .returning(|_| Err(sqlx::Error::PoolTimedOut));
+
+        let result = retry(|| repo_mock.get_by_id(1)).await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+    }
+
+    #[tokio::test]
+    async fn no_retry_for_permanent_error() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_get_by_id()
+            .times(1)
+            .returning(|_| Err(sqlx::Error::RowNotFound));
+
+        let result = retry(|| repo_mock.get_by_id(1)).await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+    }
+
+    #[cfg(feature = "integration-tests")]
+    fn hero(name: &str, abilities: &[&str]) -> Hero {
+        Hero {
+            name: name.to_string(),
+            can_fly: true,
+            abilities: Some(abilities.iter().map(|a| a.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn insert_and_get_by_id(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        let pk = repo
+            .insert(&hero("Superman", &["flying", "x-ray vision"]))
+            .await
+            .unwrap();
+        assert_eq!(1, pk.version);
+
+        let stored = repo.get_by_id(pk.id).await.unwrap().unwrap();
+        assert_eq!("Superman", stored.inner_hero.name);
+        assert!(stored.inner_hero.can_fly);
+        assert_eq!(
+            Some(vec!["flying".to_string(), "x-ray vision".to_string()]),
+            stored.inner_hero.abilities
+        );
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn insert_writes_audit_record(pool: PgPool) {
+        let repo = HeroesRepository(pool.clone());
+        let pk = repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM hero_audit WHERE hero_id = $1")
+            .bind(pk.id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(vec!["insert"], actions);
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn timestamps(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        let pk = repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+        assert!(pk.created_at > DateTime::<Utc>::default());
+        assert_eq!(pk.created_at, pk.updated_at);
+
+        let update = UpdateHero {
+            can_fly: Some(false),
+            ..Default::default()
+        };
+        let updated = repo.update(pk.id, None, &update).await.unwrap().unwrap();
+        assert_eq!(pk.created_at, updated.created_at);
+        assert!(updated.updated_at > pk.updated_at);
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn get_by_name(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+        repo.insert(&hero("Spiderman", &["web shooting"])).await.unwrap();
+        repo.insert(&hero("Batman", &["martial arts"])).await.unwrap();
+
+        let filter = GetHeroFilter {
+            name_filter: Some("S%".to_string()),
+            ..Default::default()
+        };
+        let heroes = repo
+            .get_by_name(&filter, HeroOrdering::default(), Pagination::default())
+            .await
+            .unwrap();
+        let names: Vec<_> = heroes.iter().map(|h| h.inner_hero.name.as_str()).collect();
+        assert_eq!(vec!["Superman", "Spiderman"], names);
+        assert_eq!(Some(vec!["web shooting".to_string()]), heroes[1].inner_hero.abilities);
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn get_by_name_case_insensitive(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+
+        let filter = GetHeroFilter {
+            name_filter: Some("super%".to_string()),
+            ..Default::default()
+        };
+        let heroes = repo
+            .get_by_name(&filter, HeroOrdering::default(), Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(1, heroes.len());
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn stream_by_name(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+        repo.insert(&hero("Batman", &["martial arts"])).await.unwrap();
+
+        let heroes: Vec<_> = repo
+            .stream_by_name(GetHeroFilter::default(), HeroOrdering::default())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        let names: Vec<_> = heroes.iter().map(|h| h.inner_hero.name.as_str()).collect();
+        assert_eq!(vec!["Superman", "Batman"], names);
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn exists_by_name(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        let pk = repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+
+        assert!(repo.exists_by_name("Superman").await.unwrap());
+        assert!(!repo.exists_by_name("Batman").await.unwrap());
+
+        repo.delete(pk.id).await.unwrap();
+        assert!(!repo.exists_by_name("Superman").await.unwrap());
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn get_after(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        let first = repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+        repo.insert(&hero("Spiderman", &["web shooting"])).await.unwrap();
+        repo.insert(&hero("Batman", &["martial arts"])).await.unwrap();
+
+        let heroes = repo.get_after(first.id, 1).await.unwrap();
+        assert_eq!(1, heroes.len());
+        assert_eq!("Spiderman", heroes[0].inner_hero.name);
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn search(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        repo.insert(&Hero {
+            realname: Some("Bruce Wayne".to_string()),
+            ..hero("Batman", &["martial arts"])
         })
+        .await
+        .unwrap();
+        repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+        repo.insert(&hero("Wonder Woman", &["strength"])).await.unwrap();
+
+        let heroes = repo
+            .search("WAYNE", HeroOrdering::default(), Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(1, heroes.len());
+        assert_eq!("Batman", heroes[0].inner_hero.name);
+
+        let heroes = repo
+            .search("man", HeroOrdering::default(), Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(3, heroes.len());
+        assert_eq!(3, repo.count_search("man").await.unwrap());
+        assert_eq!(1, repo.count_search("WAYNE").await.unwrap());
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn cleanup(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        let superman = repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+
+        assert_eq!(
+            vec![superman.id],
+            repo.cleanup(&GetHeroFilter::default()).await.unwrap()
+        );
+
+        assert_eq!(0, repo.count(&GetHeroFilter::default()).await.unwrap());
+
+        // Heroes are only soft-deleted
+        let filter = GetHeroFilter {
+            include_deleted: true,
+            ..Default::default()
+        };
+        let heroes = repo
+            .get_by_name(&filter, HeroOrdering::default(), Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(1, heroes.len());
+        assert!(heroes[0].deleted_at.is_some());
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn cleanup_filtered(pool: PgPool) {
+        let repo = HeroesRepository(pool);
+        repo.insert(&hero("Superman", &["flying"])).await.unwrap();
+        repo.insert(&Hero {
+            can_fly: false,
+            ..hero("Batman", &["martial arts"])
+        })
+        .await
+        .unwrap();
+
+        let filter = GetHeroFilter {
+            can_fly: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(1, repo.cleanup(&filter).await.unwrap().len());
+
+        // Already deleted heroes are not deleted again, even if they are included
+        let filter = GetHeroFilter {
+            include_deleted: true,
+            ..filter
+        };
+        assert!(repo.cleanup(&filter).await.unwrap().is_empty());
+
+        let heroes = repo
+            .get_by_name(
+                &GetHeroFilter::default(),
+                HeroOrdering::default(),
+                Pagination::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(1, heroes.len());
+        assert_eq!("Batman", heroes[0].inner_hero.name);
     }
 }
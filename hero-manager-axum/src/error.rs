@@ -2,9 +2,9 @@
 
 use axum::{
     body::Body,
+    extract::rejection::JsonRejection,
     http::{header, StatusCode},
     response::{IntoResponse, Response},
-    Json,
 };
 use http_api_problem::HttpApiProblem;
 use std::any::Any;
@@ -14,13 +14,44 @@ use validator::ValidationErrors;
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("an internal database error occurred")]
-    Sqlx(#[from] sqlx::Error),
+    Sqlx(#[source] sqlx::Error),
 
     #[error("an internal server error occurred")]
     Anyhow(#[from] anyhow::Error),
 
     #[error("validation error in request body")]
     InvalidEntity(#[from] ValidationErrors),
+
+    /// The request body is no JSON or does not match the expected type
+    #[error("invalid JSON in request body")]
+    InvalidJson(#[from] JsonRejection),
+
+    /// The request is not valid (e.g. missing or contradicting query parameters)
+    #[error("bad request: {0}")]
+    BadRequest(&'static str),
+
+    #[error("conflict: {0}")]
+    Conflict(String),
+
+    /// The requested entity (e.g. "Hero") does not exist
+    #[error("{0} not found")]
+    NotFound(&'static str),
+}
+
+/// Converts database errors into application-level errors
+///
+/// Unique constraint violations (Postgres SQLSTATE 23505) are caused by the client
+/// (e.g. duplicate hero name). Therefore, they become conflicts instead of internal errors.
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(db_error) if db_error.is_unique_violation() => Self::Conflict(format!(
+                "unique constraint {} violated",
+                db_error.constraint().unwrap_or("<unknown>")
+            )),
+            e => Self::Sqlx(e),
+        }
+    }
 }
 
 /// Type alias for Results that use our application-level error enum
@@ -33,11 +64,27 @@ impl IntoResponse for Error {
                 .type_url("https://example.com/errors/unprocessable-entity")
                 .title("Unprocessable entity in request body")
                 .detail(errors.to_string()),
+            Self::InvalidJson(rejection) => HttpApiProblem::new(rejection.status())
+                .type_url("https://example.com/errors/invalid-json")
+                .title("Invalid JSON in request body")
+                .detail(rejection.body_text()),
+            Self::BadRequest(detail) => HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .type_url("https://example.com/errors/bad-request")
+                .title("Bad Request")
+                .detail(detail),
+            Self::Conflict(detail) => HttpApiProblem::new(StatusCode::CONFLICT)
+                .type_url("https://example.com/errors/conflict")
+                .title("Conflict")
+                .detail(detail),
+            Self::NotFound(entity) => HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .type_url("https://example.com/errors/not-found")
+                .title(format!("{entity} not found")),
             _ => HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
                 .type_url("https://example.com/errors/internal-error")
                 .title("Internal Server Error"),
         };
-        (payload.status.unwrap(), Json(payload)).into_response()
+        // Sends the problem with status code and content type application/problem+json
+        payload.into_response()
     }
 }
 
@@ -60,3 +107,99 @@ pub fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
         .body(Body::from(serde_json::to_string(&problem).unwrap()))
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use sqlx::error::{DatabaseError, ErrorKind};
+    use std::{borrow::Cow, fmt};
+
+    /// Simulates the error Postgres reports for duplicate values in a unique index
+    #[derive(Debug)]
+    struct UniqueViolation;
+
+    impl fmt::Display for UniqueViolation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "duplicate key value violates unique constraint \"ix_name\"")
+        }
+    }
+
+    impl std::error::Error for UniqueViolation {}
+
+    impl DatabaseError for UniqueViolation {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint \"ix_name\""
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed("23505"))
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            Some("ix_name")
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::UniqueViolation
+        }
+    }
+
+    #[tokio::test]
+    async fn unique_violation_is_conflict() {
+        let error = Error::from(sqlx::Error::Database(Box::new(UniqueViolation)));
+        assert!(matches!(error, Error::Conflict(_)));
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["title"], "Conflict");
+        assert_eq!(problem["detail"], "unique constraint ix_name violated");
+    }
+
+    #[test]
+    fn other_database_errors_are_internal() {
+        let response = Error::from(sqlx::Error::RowNotFound).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[rstest]
+    #[case(
+        Error::InvalidEntity(ValidationErrors::new()),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Unprocessable entity in request body"
+    )]
+    #[case(Error::BadRequest("missing parameter"), StatusCode::BAD_REQUEST, "Bad Request")]
+    #[case(Error::Conflict("version mismatch".to_string()), StatusCode::CONFLICT, "Conflict")]
+    #[case(Error::NotFound("Hero"), StatusCode::NOT_FOUND, "Hero not found")]
+    #[case(
+        Error::Sqlx(sqlx::Error::PoolTimedOut),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal Server Error"
+    )]
+    #[case(Error::Anyhow(anyhow::anyhow!("boom")), StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")]
+    #[tokio::test]
+    async fn error_to_problem(#[case] error: Error, #[case] status_code: StatusCode, #[case] title: &str) {
+        let response = error.into_response();
+        assert_eq!(response.status(), status_code);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["status"], status_code.as_u16());
+        assert_eq!(problem["title"], title);
+    }
+}
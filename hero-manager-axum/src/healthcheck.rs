@@ -1,9 +1,9 @@
-/// Healtheck routes and handlers
-///
-/// This part of the sample demonstrates various ways for how to
-/// build web responses based on a healthcheck endpoint.
-/// We also use the healthcheck endpoints to demonstrate some
-/// principles about testing handlers.
+// Healtheck routes and handlers
+//
+// This part of the sample demonstrates various ways for how to
+// build web responses based on a healthcheck endpoint.
+// We also use the healthcheck endpoints to demonstrate some
+// principles about testing handlers.
 
 use axum::{
     body::{Bytes, Full},
@@ -13,9 +13,15 @@ use axum::{
     routing::get,
     Json, Router,
 };
+use http_api_problem::HttpApiProblem;
 use serde::Serialize;
 use serde_json::{json, Value};
-use std::{convert::Infallible, sync::Arc};
+use sqlx::PgPool;
+use std::{
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{AppConfiguration, Environment, error};
 
@@ -88,6 +94,71 @@ pub async fn healthcheck_handler_4(State(state): State<Arc<AppConfiguration>>) -
     })
 }
 
+/// Setup routes for probes that check the health of dependencies
+///
+/// Unlike the routes above, these routes need access to the DB connection pool.
+/// /livez and /readyz are meant for Kubernetes liveness and readiness probes.
+pub fn probe_routes(pool: PgPool) -> Router {
+    Router::new()
+        .route("/livez", get(liveness))
+        .route("/readyz", get(readiness))
+        .route("/health/db", get(db_healthcheck))
+        .with_state(pool)
+}
+
+/// Runs a trivial query against the DB and measures the round-trip time
+async fn probe_db(pool: &PgPool) -> Result<Duration, sqlx::Error> {
+    let start = Instant::now();
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(start.elapsed())
+}
+
+/// Database healthcheck handler
+///
+/// Returns 503 if the DB cannot be reached so that e.g. load balancers
+/// stop sending traffic to this instance.
+pub async fn db_healthcheck(State(pool): State<PgPool>) -> Response {
+    match probe_db(&pool).await {
+        Ok(latency) => Json(json!({
+            "status": "ok",
+            "latencyMs": latency.as_secs_f64() * 1000.0,
+        }))
+        .into_response(),
+        Err(e) => db_unavailable(e),
+    }
+}
+
+/// Liveness probe
+///
+/// Succeeds as long as the process is able to handle requests. Dependencies
+/// are intentionally not checked, Kubernetes would restart the pod otherwise.
+pub async fn liveness() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe
+///
+/// Succeeds only if the DB can be reached.
+pub async fn readiness(State(pool): State<PgPool>) -> Response {
+    match probe_db(&pool).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => db_unavailable(e),
+    }
+}
+
+/// Builds a problem+json response for an unreachable DB
+///
+/// The error is only logged. Probes are not authenticated, so the response must
+/// not reveal details about the DB.
+fn db_unavailable(e: sqlx::Error) -> Response {
+    tracing::error!("Database healthcheck failed: {:?}", e);
+    HttpApiProblem::new(StatusCode::SERVICE_UNAVAILABLE)
+        .type_url("https://example.com/errors/service-unavailable")
+        .title("Database not available")
+        .detail("The database cannot be reached")
+        .into_response()
+}
+
 pub async fn failing_healthcheck_1() -> error::Result<()> {
     Err(error::Error::Anyhow(anyhow::anyhow!("Something bad happened")))
 }
@@ -103,6 +174,7 @@ mod tests {
     use super::*;
     use axum::http::Request;
     use rstest::rstest;
+    use sqlx::postgres::PgPoolOptions;
     use tower::ServiceExt;
 
     #[rstest]
@@ -175,4 +247,56 @@ mod tests {
 
         assert_eq!(body, json!({ "version": "1.0.0", "env": "Development" }));
     }
+
+    /// Creates a pool pointing to a port where no DB is listening
+    fn unreachable_pool() -> PgPool {
+        PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(500))
+            .connect_lazy("postgres://postgres@127.0.0.1:1/heroes")
+            .unwrap()
+    }
+
+    #[rstest]
+    #[case("/livez", StatusCode::OK)]
+    #[case("/readyz", StatusCode::SERVICE_UNAVAILABLE)]
+    #[tokio::test]
+    async fn probes_with_unreachable_db(#[case] uri: &str, #[case] status_code: StatusCode) {
+        let app = probe_routes(unreachable_pool());
+        let response = app
+            .oneshot(Request::builder().uri(uri).body(hyper::Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test]
+    async fn readiness_with_db(pool: PgPool) {
+        let app = probe_routes(pool);
+        let response = app
+            .oneshot(Request::builder().uri("/readyz").body(hyper::Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn db_healthcheck_failing() {
+        let app = probe_routes(unreachable_pool());
+        let response = app
+            .oneshot(Request::builder().uri("/health/db").body(hyper::Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["title"], "Database not available");
+        assert_eq!(body["detail"], "The database cannot be reached");
+    }
 }
@@ -4,20 +4,35 @@
 /// injection with a trait. Our goal is to unit-test our handlers using
 /// mocked versions of our data access layer.
 use crate::{
+    axum_helpers::ValidatedJson,
     data::{log_error, HeroesRepositoryTrait},
-    model::{Hero, IdentifyableHero}, error,
+    error,
+    model::{
+        CleanupQuery, Cursor, GetHeroFilter, Hero, HeroChange, HeroChangeKind, HeroExistsQuery, HeroOrdering, HeroPage,
+        HeroSearch, IdentifyableHero, Pagination, UpdateHero,
+    },
 };
 use axum::{
-    extract::{Query, State},
-    http::{header::LOCATION, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    routing::post,
+    body::{Bytes, StreamBody},
+    extract::{FromRef, OriginalUri, Path, Query, State},
+    http::{
+        header::{CONTENT_TYPE, ETAG, HOST, IF_MATCH, LOCATION},
+        HeaderMap, StatusCode,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
     Json, Router,
 };
-use serde::Deserialize;
+use concurrency::{ETag, IfMatch, PreconditionError};
+use futures::{stream, Stream, StreamExt};
+use serde_json::{json, Value};
 use tokio::time::sleep;
 use std::{sync::Arc, time::Duration};
-use validator::Validate;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
 
 /// Type alias for our shared state
 ///
@@ -25,51 +40,353 @@ use validator::Validate;
 /// with a mock object.
 pub type DynHeroesRepository = Arc<dyn HeroesRepositoryTrait + Send + Sync>;
 
+/// Type alias for the channel through which hero changes are published
+pub type HeroChanges = broadcast::Sender<HeroChange>;
+
+/// Header containing the total number of heroes matching the filter of a list request
+const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+/// Number of hero changes buffered for subscribers of the event feed
+///
+/// Subscribers that fall behind by more than that miss changes.
+const CHANGES_CAPACITY: usize = 100;
+
+/// Shared state of the hero management API routes
+#[derive(Clone)]
+pub struct HeroesState {
+    repo: DynHeroesRepository,
+    changes: HeroChanges,
+}
+
+impl FromRef<HeroesState> for DynHeroesRepository {
+    fn from_ref(state: &HeroesState) -> Self {
+        state.repo.clone()
+    }
+}
+
+impl FromRef<HeroesState> for HeroChanges {
+    fn from_ref(state: &HeroesState) -> Self {
+        state.changes.clone()
+    }
+}
+
 /// Setup hero management API routes
 pub fn heroes_routes(repo: DynHeroesRepository) -> Router {
+    let (changes, _) = broadcast::channel(CHANGES_CAPACITY);
     Router::new()
-        .route("/", post(insert_hero).get(get_heroes))
+        .route("/", post(insert_hero).get(get_heroes).head(hero_exists))
+        .route("/count", get(count_heroes))
+        .route("/events", get(hero_events))
+        .route("/stream", get(stream_heroes))
+        .route("/page", get(get_heroes_page))
         .route("/cleanup", post(cleanup_heroes))
         .route("/slow", post(do_something_slow))
         .route("/panic", post(panic))
-        .with_state(repo)
+        .route(
+            "/:id",
+            get(get_hero_by_id)
+                .put(replace_hero)
+                .patch(update_hero)
+                .delete(delete_hero),
+        )
+        .with_state(HeroesState { repo, changes })
 }
 
-#[derive(Deserialize)]
-pub struct GetHeroFilter {
-    #[serde(rename = "name")]
-    name_filter: Option<String>,
-    // In practice, add additional query parameters here
+/// Publishes a change of a hero to all subscribers of the event feed
+fn notify(changes: &HeroChanges, kind: HeroChangeKind, id: i64) {
+    // Sending only fails if nobody is subscribed at the moment
+    let _ = changes.send(HeroChange { kind, id });
+}
+
+/// Streams changes of heroes as Server-Sent Events
+///
+/// Subscribers that read too slowly miss changes. Instead of them, they get a
+/// lagged event containing the number of missed changes so that they can reload.
+/// If a client disconnects, the stream and its subscription are dropped.
+pub async fn hero_events(State(changes): State<HeroChanges>) -> Sse<impl Stream<Item = serde_json::Result<Event>>> {
+    let events = stream::unfold(changes.subscribe(), |mut rx| async move {
+        let event = match rx.recv().await {
+            Ok(change) => Event::default().event("change").json_data(change),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Event subscriber lagged behind, {} changes missed", missed);
+                Ok(Event::default().event("lagged").data(missed.to_string()))
+            },
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event, rx))
+    });
+
+    // Keep-alive comments prevent proxies from closing idle connections
+    Sse::new(events).keep_alive(KeepAlive::default())
 }
 
 pub async fn get_heroes(
     State(repo): State<DynHeroesRepository>,
-    filter: Query<GetHeroFilter>,
-) -> error::Result<Json<Vec<IdentifyableHero>>> {
-    let heroes = repo
-        .get_by_name(filter.name_filter.as_deref().unwrap_or("%"))
-        .await
-        .map_err(log_error)?;
-    Ok(Json(heroes))
+    Query(filter): Query<GetHeroFilter>,
+    Query(search): Query<HeroSearch>,
+    Query(ordering): Query<HeroOrdering>,
+    Query(pagination): Query<Pagination>,
+) -> error::Result<Response> {
+    // A search term takes precedence over the other filter criteria.
+    // The total number of matching heroes is sent so that clients can build pagers.
+    let (heroes, total_count) = match search.search {
+        Some(term) => (
+            repo.search(&term, ordering, pagination.bounded())
+                .await
+                .map_err(log_error)?,
+            repo.count_search(&term).await.map_err(log_error)?,
+        ),
+        None => (
+            repo.get_by_name(&filter, ordering, pagination.bounded())
+                .await
+                .map_err(log_error)?,
+            repo.count(&filter).await.map_err(log_error)?,
+        ),
+    };
+    Ok(([(TOTAL_COUNT_HEADER, total_count.to_string())], Json(heroes)).into_response())
+}
+
+/// Streams all heroes matching the filter as a JSON array
+///
+/// Heroes are serialized one by one while they are read from the DB. With that,
+/// memory consumption does not grow with the number of heroes. An error before
+/// the first hero leads to a regular error response. Later errors abort the
+/// response so that the client receives an incomplete (i.e. invalid) JSON array.
+pub async fn stream_heroes(
+    State(repo): State<DynHeroesRepository>,
+    Query(filter): Query<GetHeroFilter>,
+    Query(ordering): Query<HeroOrdering>,
+) -> error::Result<Response> {
+    let mut heroes = repo.stream_by_name(filter, ordering);
+    let first = heroes.next().await.transpose().map_err(log_error)?;
+    let heroes = stream::iter(first.map(Ok)).chain(heroes);
+    let body = StreamBody::new(json_array_chunks(heroes));
+    Ok(([(CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+/// Serializes a stream of heroes into the chunks of a JSON array
+fn json_array_chunks(
+    heroes: impl Stream<Item = Result<IdentifyableHero, sqlx::Error>>,
+) -> impl Stream<Item = Result<Bytes, sqlx::Error>> {
+    let items = heroes.enumerate().map(|(index, hero)| {
+        let hero = hero.map_err(log_error)?;
+        let mut chunk = if index == 0 { Vec::new() } else { b",".to_vec() };
+        serde_json::to_writer(&mut chunk, &hero).expect("Serializing a hero should never fail");
+        Ok(Bytes::from(chunk))
+    });
+
+    stream::once(async { Ok(Bytes::from_static(b"[")) })
+        .chain(items)
+        .chain(stream::once(async { Ok(Bytes::from_static(b"]")) }))
+}
+
+/// Gets a page of heroes using cursor-based pagination
+///
+/// Clients pass the returned nextCursor in the after query parameter to get the next page.
+pub async fn get_heroes_page(
+    State(repo): State<DynHeroesRepository>,
+    Query(cursor): Query<Cursor>,
+) -> error::Result<Json<HeroPage>> {
+    let cursor = cursor.bounded();
+    let heroes = repo.get_after(cursor.after, cursor.limit).await.map_err(log_error)?;
+
+    // A full page indicates that there might be more heroes
+    let next_cursor = match heroes.last() {
+        Some(last) if heroes.len() as i64 == cursor.limit => Some(last.id),
+        _ => None,
+    };
+    Ok(Json(HeroPage { heroes, next_cursor }))
+}
+
+/// Checks whether a hero with the given name exists
+///
+/// Returns 200 if the hero exists, otherwise 404 (without body as it is a HEAD request).
+/// Clients can use it to check for duplicates before inserting a hero.
+pub async fn hero_exists(
+    State(repo): State<DynHeroesRepository>,
+    Query(query): Query<HeroExistsQuery>,
+) -> error::Result<StatusCode> {
+    let exists = repo.exists_by_name(&query.name).await.map_err(log_error)?;
+    Ok(if exists { StatusCode::OK } else { StatusCode::NOT_FOUND })
 }
 
-pub async fn cleanup_heroes(State(repo): State<DynHeroesRepository>) -> error::Result<impl IntoResponse> {
-    repo.cleanup().await.map_err(log_error)?;
+pub async fn count_heroes(
+    State(repo): State<DynHeroesRepository>,
+    Query(filter): Query<GetHeroFilter>,
+) -> error::Result<Json<Value>> {
+    let count = repo.count(&filter).await.map_err(log_error)?;
+    Ok(Json(json!({ "count": count })))
+}
+
+pub async fn get_hero_by_id(State(repo): State<DynHeroesRepository>, Path(id): Path<i64>) -> error::Result<Response> {
+    let hero = repo.get_by_id(id).await.map_err(log_error)?;
+    match hero {
+        // The version is sent as ETag so that clients can use it in If-Match when updating
+        Some(hero) => Ok(([(ETAG, ETag::from_version(hero.version).to_string())], Json(hero)).into_response()),
+        None => Err(hero_not_found()),
+    }
+}
+
+/// Update a hero
+///
+/// Clients can send the version they have based their changes on in the If-Match
+/// header (optimistic concurrency). If the hero has been changed in the meantime,
+/// the update is rejected with 409. Without If-Match, the update is unconditional.
+pub async fn update_hero(
+    State(repo): State<DynHeroesRepository>,
+    State(changes): State<HeroChanges>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    ValidatedJson(hero): ValidatedJson<UpdateHero>,
+) -> error::Result<Response> {
+    let expected_version = parse_if_match(&headers)?;
+    let updated = repo.update(id, expected_version, &hero).await.map_err(log_error)?;
+    updated_hero_response(&repo, &changes, id, expected_version, updated).await
+}
+
+/// Replace all fields of a hero
+///
+/// In contrast to update_hero, the request body must contain a complete hero.
+/// Optimistic concurrency with If-Match works the same way.
+pub async fn replace_hero(
+    State(repo): State<DynHeroesRepository>,
+    State(changes): State<HeroChanges>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    ValidatedJson(hero): ValidatedJson<Hero>,
+) -> error::Result<Response> {
+    let expected_version = parse_if_match(&headers)?;
+    let replaced = repo.replace(id, expected_version, &hero).await.map_err(log_error)?;
+    updated_hero_response(&repo, &changes, id, expected_version, replaced).await
+}
+
+/// Builds the response for an update of a hero
+///
+/// If no hero has been updated, we have to find out whether the hero does not exist
+/// or whether its version does not match.
+async fn updated_hero_response(
+    repo: &DynHeroesRepository,
+    changes: &HeroChanges,
+    id: i64,
+    expected_version: Option<i32>,
+    updated: Option<IdentifyableHero>,
+) -> error::Result<Response> {
+    match updated {
+        Some(hero) => {
+            notify(changes, HeroChangeKind::Updated, id);
+            Ok(([(ETAG, ETag::from_version(hero.version).to_string())], Json(hero)).into_response())
+        },
+        None if expected_version.is_some() && repo.get_by_id(id).await.map_err(log_error)?.is_some() => Err(
+            error::Error::Conflict("Hero has been changed since the version given in If-Match".to_string()),
+        ),
+        None => Err(hero_not_found()),
+    }
+}
+
+pub async fn delete_hero(
+    State(repo): State<DynHeroesRepository>,
+    State(changes): State<HeroChanges>,
+    Path(id): Path<i64>,
+) -> error::Result<Response> {
+    if repo.delete(id).await.map_err(log_error)? {
+        notify(&changes, HeroChangeKind::Deleted, id);
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(hero_not_found())
+    }
+}
+
+/// Gets the expected hero version from the If-Match header
+///
+/// The version is checked by the DB when updating. Therefore, If-Match has to contain
+/// a single strong ETag or `*` (any version). Malformed headers and headers that cannot
+/// be checked by the DB are rejected with 400, weak ETags never match (409).
+fn parse_if_match(headers: &HeaderMap) -> error::Result<Option<i32>> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let invalid = || error::Error::BadRequest("If-Match has to contain a single hero version");
+    let if_match = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.parse::<IfMatch>().ok())
+        .ok_or_else(invalid)?;
+    match if_match.expected_version() {
+        Ok(version) => Ok(version),
+        Err(PreconditionError::Failed) => Err(error::Error::Conflict(
+            "Hero has been changed since the version given in If-Match".to_string(),
+        )),
+        Err(PreconditionError::Invalid(_) | PreconditionError::Unsupported) => Err(invalid()),
+    }
+}
+
+/// Error for a hero that does not exist
+fn hero_not_found() -> error::Error {
+    error::Error::NotFound("Hero")
+}
+
+/// Deletes the heroes matching the given filter
+///
+/// Deleting all heroes is dangerous. Therefore, a request without filter
+/// has to confirm it with confirm=all. A change event is published for
+/// every deleted hero.
+pub async fn cleanup_heroes(
+    State(repo): State<DynHeroesRepository>,
+    State(changes): State<HeroChanges>,
+    Query(filter): Query<GetHeroFilter>,
+    Query(cleanup): Query<CleanupQuery>,
+) -> error::Result<impl IntoResponse> {
+    if !filter.has_criteria() && cleanup.confirm.as_deref() != Some("all") {
+        return Err(error::Error::BadRequest(
+            "Specify a filter or confirm=all to delete all heroes",
+        ));
+    }
+
+    for id in repo.cleanup(&filter).await.map_err(log_error)? {
+        notify(&changes, HeroChangeKind::Deleted, id);
+    }
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Gets the base URL (scheme and host) under which the client reached us
+///
+/// If we run behind a reverse proxy, the X-Forwarded-* headers contain the
+/// values of the original request. Returns None if the host is unknown.
+fn base_url(headers: &HeaderMap) -> Option<String> {
+    // Proxies can add multiple comma-separated values, the first one is the original
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+
+    let host = header_value("X-Forwarded-Host").or_else(|| header_value(HOST.as_str()))?;
+    let scheme = header_value("X-Forwarded-Proto").unwrap_or("http");
+    Some(format!("{scheme}://{host}"))
+}
+
 pub async fn insert_hero(
     State(repo): State<DynHeroesRepository>,
-    Json(hero): Json<Hero>,
+    State(changes): State<HeroChanges>,
+    OriginalUri(uri): OriginalUri,
+    request_headers: HeaderMap,
+    ValidatedJson(hero): ValidatedJson<Hero>,
 ) -> error::Result<impl IntoResponse> {
-    hero.validate()?;
-
     let hero_pk = repo.insert(&hero).await.map_err(log_error)?;
+    notify(&changes, HeroChangeKind::Created, hero_pk.id);
 
+    // Fall back to a relative URL if we do not know the host. The path contains
+    // the prefixes of all nesting routers (e.g. /v1/heroes).
+    let base_url = base_url(&request_headers).unwrap_or_default();
+    let path = uri.path().trim_end_matches('/');
     let mut headers = HeaderMap::new();
     headers.insert(
         LOCATION,
-        format!("/heroes/{}", hero_pk.id)
+        format!("{base_url}{path}/{}", hero_pk.id)
             .parse()
             .expect("Parsing location header should never fail"),
     );
@@ -80,6 +397,9 @@ pub async fn insert_hero(
             id: hero_pk.id,
             inner_hero: hero,
             version: hero_pk.version,
+            created_at: hero_pk.created_at,
+            updated_at: hero_pk.updated_at,
+            deleted_at: None,
         }),
     )
         .into_response())
@@ -97,24 +417,29 @@ pub async fn panic() -> error::Result<Response> {
 
 #[cfg(test)]
 mod tests {
-    use crate::data::MockHeroesRepositoryTrait;
+    use crate::{
+        data::{HeroPkVersion, MockHeroesRepositoryTrait},
+        model::HeroSortColumn,
+    };
 
     use super::*;
     use axum::http::Request;
-    use hyper::Body;
+    use hyper::{body::HttpBody, Body};
     use mockall::predicate::*;
     use rstest::rstest;
-    use serde_json::Value;
     use sqlx::Error;
     use tower::ServiceExt;
 
     #[rstest]
-    #[case(Ok(()), StatusCode::NO_CONTENT)]
+    #[case(Ok(vec![1]), StatusCode::NO_CONTENT)]
     #[case(Err(Error::WorkerCrashed), StatusCode::INTERNAL_SERVER_ERROR)]
     #[tokio::test]
-    async fn cleanup(#[case] result: Result<(), sqlx::error::Error>, #[case] status_code: StatusCode) {
+    async fn cleanup(#[case] result: Result<Vec<i64>, sqlx::error::Error>, #[case] status_code: StatusCode) {
         let mut repo_mock = MockHeroesRepositoryTrait::new();
-        repo_mock.expect_cleanup().return_once(|| result);
+        repo_mock
+            .expect_cleanup()
+            .with(eq(GetHeroFilter::default()))
+            .return_once(|_| result);
 
         let repo = Arc::new(repo_mock) as DynHeroesRepository;
 
@@ -122,7 +447,7 @@ mod tests {
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri("/cleanup")
+                    .uri("/cleanup?confirm=all")
                     .method("POST")
                     .body(hyper::Body::empty())
                     .unwrap(),
@@ -133,12 +458,71 @@ mod tests {
         assert_eq!(response.status(), status_code);
     }
 
+    #[tokio::test]
+    async fn cleanup_filtered() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_cleanup()
+            .with(eq(GetHeroFilter {
+                name_filter: Some("Super%".to_string()),
+                can_fly: Some(true),
+                ..Default::default()
+            }))
+            .return_once(|_| Ok(vec![1]));
+
+        let app = heroes_routes(Arc::new(repo_mock));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/cleanup?name=Super%25&can_fly=true")
+                    .method("POST")
+                    .body(hyper::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[rstest]
+    #[case("/cleanup")]
+    #[case("/cleanup?confirm=yes")]
+    #[case("/cleanup?include_deleted=true")]
+    #[tokio::test]
+    async fn cleanup_requires_confirmation(#[case] uri: &str) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_cleanup().never();
+
+        let app = heroes_routes(Arc::new(repo_mock));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .method("POST")
+                    .body(hyper::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn get_heroes() {
         let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(0));
         repo_mock.expect_get_by_name()
-            .with(eq("Super%"))
-            .returning(|_| Ok(vec![Default::default()]));
+            .with(
+                eq(GetHeroFilter {
+                    name_filter: Some("Super%".to_string()),
+                    ..Default::default()
+                }),
+                eq(HeroOrdering::default()),
+                eq(Pagination::default()),
+            )
+            .returning(|_, _, _| Ok(vec![Default::default()]));
 
         let repo = Arc::new(repo_mock) as DynHeroesRepository;
 
@@ -161,4 +545,878 @@ mod tests {
 
         assert!(matches!(body, Value::Array { .. }));
     }
+
+    #[tokio::test]
+    async fn get_hero_by_id() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_get_by_id().with(eq(1)).returning(|id| {
+            Ok(Some(IdentifyableHero {
+                id,
+                version: 3,
+                ..Default::default()
+            }))
+        });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(Request::builder().uri("/1").method("GET").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"3\"");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["id"], 1);
+        assert!(body["createdAt"].is_string());
+        assert!(body["updatedAt"].is_string());
+    }
+
+    #[tokio::test]
+    async fn get_hero_by_id_not_found() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_get_by_id().with(eq(1)).returning(|_| Ok(None));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(Request::builder().uri("/1").method("GET").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["title"], "Hero not found");
+    }
+
+    #[tokio::test]
+    async fn update_hero() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_update()
+            .withf(|id, version, hero| {
+                *id == 1 && version.is_none() && hero.name.as_deref() == Some("Batman") && hero.can_fly.is_none()
+            })
+            .returning(|id, _, hero| {
+                Ok(Some(IdentifyableHero {
+                    id,
+                    inner_hero: Hero {
+                        name: hero.name.clone().unwrap(),
+                        ..Default::default()
+                    },
+                    version: 2,
+                    ..Default::default()
+                }))
+            });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PATCH")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{ "name": "Batman" }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["name"], "Batman");
+        assert_eq!(body["version"], 2);
+    }
+
+    #[tokio::test]
+    async fn update_hero_not_found() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_update().returning(|_, _, _| Ok(None));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PATCH")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{ "canFly": true }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    const BATMAN: &str = r#"{ "name": "Batman", "canFly": false, "abilities": "martial arts" }"#;
+
+    #[tokio::test]
+    async fn replace_hero() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_replace()
+            .withf(|id, version, hero| {
+                *id == 1 && version.is_none() && hero.name == "Batman" && hero.realname.is_none()
+            })
+            .returning(|id, _, hero| {
+                Ok(Some(IdentifyableHero {
+                    id,
+                    inner_hero: hero.clone(),
+                    version: 2,
+                    ..Default::default()
+                }))
+            });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PUT")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(BATMAN))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"2\"");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["name"], "Batman");
+        assert_eq!(body["abilities"], "martial arts");
+    }
+
+    #[tokio::test]
+    async fn replace_hero_not_found() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_replace().returning(|_, _, _| Ok(None));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PUT")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(BATMAN))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn replace_hero_incomplete() {
+        // Repository must not be called if the body does not contain a complete hero
+        let repo = Arc::new(MockHeroesRepositoryTrait::new()) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PUT")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{ "name": "Batman" }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn update_hero_with_matching_version() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_update()
+            .withf(|id, version, _| *id == 1 && *version == Some(1))
+            .returning(|id, _, _| {
+                Ok(Some(IdentifyableHero {
+                    id,
+                    version: 2,
+                    ..Default::default()
+                }))
+            });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PATCH")
+                    .header("Content-Type", "application/json")
+                    .header("If-Match", "\"1\"")
+                    .body(Body::from(r#"{ "canFly": true }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"2\"");
+    }
+
+    #[rstest]
+    #[case("*", StatusCode::OK)]
+    // Weak ETags never match
+    #[case("W/\"1\"", StatusCode::CONFLICT)]
+    // The DB can only check a single version
+    #[case("\"1\", \"2\"", StatusCode::BAD_REQUEST)]
+    #[case("\"abc\"", StatusCode::BAD_REQUEST)]
+    // Malformed header (ETags have to be quoted)
+    #[case("2", StatusCode::BAD_REQUEST)]
+    #[tokio::test]
+    async fn update_hero_if_match(#[case] if_match: &str, #[case] status_code: StatusCode) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_update()
+            .withf(|id, version, _| *id == 1 && version.is_none())
+            .returning(|id, _, _| {
+                Ok(Some(IdentifyableHero {
+                    id,
+                    version: 2,
+                    ..Default::default()
+                }))
+            });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PATCH")
+                    .header("Content-Type", "application/json")
+                    .header("If-Match", if_match)
+                    .body(Body::from(r#"{ "canFly": true }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
+
+    #[tokio::test]
+    async fn update_hero_with_stale_version() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_update().returning(|_, _, _| Ok(None));
+        repo_mock.expect_get_by_id().with(eq(1)).returning(|id| {
+            Ok(Some(IdentifyableHero {
+                id,
+                version: 2,
+                ..Default::default()
+            }))
+        });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("PATCH")
+                    .header("Content-Type", "application/json")
+                    .header("If-Match", "\"1\"")
+                    .body(Body::from(r#"{ "canFly": true }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[rstest]
+    #[case(true, StatusCode::NO_CONTENT)]
+    #[case(false, StatusCode::NOT_FOUND)]
+    #[tokio::test]
+    async fn delete_hero(#[case] deleted: bool, #[case] status_code: StatusCode) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_delete().with(eq(1)).return_once(move |_| Ok(deleted));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/1")
+                    .method("DELETE")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
+
+    #[tokio::test]
+    async fn get_heroes_paginated() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(0));
+        repo_mock
+            .expect_get_by_name()
+            .with(eq(GetHeroFilter::default()), always(), eq(Pagination::new(5, 100)))
+            .returning(|_, _, _| Ok(vec![]));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/?offset=5&limit=10000")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[rstest]
+    #[case("/?sort=name&desc=true", StatusCode::OK)]
+    #[case("/?sort=realname", StatusCode::BAD_REQUEST)]
+    #[tokio::test]
+    async fn get_heroes_sorted(#[case] uri: &'static str, #[case] status_code: StatusCode) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(0));
+        repo_mock
+            .expect_get_by_name()
+            .with(
+                always(),
+                eq(HeroOrdering {
+                    sort: HeroSortColumn::Name,
+                    desc: true,
+                }),
+                always(),
+            )
+            .returning(|_, _, _| Ok(vec![]));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(Request::builder().uri(uri).method("GET").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
+
+    #[rstest]
+    #[case("/?can_fly=true", Some(true))]
+    #[case("/?can_fly=false", Some(false))]
+    #[case("/", None)]
+    #[tokio::test]
+    async fn get_heroes_can_fly(#[case] uri: &'static str, #[case] can_fly: Option<bool>) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(0));
+        repo_mock
+            .expect_get_by_name()
+            .withf(move |filter, _, _| filter.can_fly == can_fly)
+            .returning(|_, _, _| Ok(vec![]));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(Request::builder().uri(uri).method("GET").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn get_heroes_by_ability() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(0));
+        repo_mock
+            .expect_get_by_name()
+            .withf(|filter, _, _| filter.ability.as_deref() == Some("strength") && filter.can_fly == Some(true))
+            .returning(|_, _, _| Ok(vec![]));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/?can_fly=true&ability=strength")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn search_heroes() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_search()
+            .withf(|term, _, _| term == "bat man")
+            .returning(|_, _, _| Ok(vec![]));
+        repo_mock.expect_count_search().returning(|_| Ok(0));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/?search=bat%20man")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[rstest]
+    #[case(2, Some(7))]
+    #[case(3, None)]
+    #[tokio::test]
+    async fn get_heroes_page(#[case] limit: i64, #[case] next_cursor: Option<i64>) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_get_after()
+            .with(eq(5), eq(limit))
+            .returning(|after, _| {
+                Ok((after + 1..=after + 2)
+                    .map(|id| IdentifyableHero {
+                        id,
+                        ..Default::default()
+                    })
+                    .collect())
+            });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/page?after=5&limit={limit}"))
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["heroes"].as_array().unwrap().len(), 2);
+        assert_eq!(body["nextCursor"], json!(next_cursor));
+    }
+
+    #[rstest]
+    #[case("/", false)]
+    #[case("/?include_deleted=false", false)]
+    #[case("/?include_deleted=true", true)]
+    #[tokio::test]
+    async fn get_heroes_include_deleted(#[case] uri: &'static str, #[case] include_deleted: bool) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(0));
+        repo_mock
+            .expect_get_by_name()
+            .withf(move |filter, _, _| filter.include_deleted == include_deleted)
+            .returning(|_, _, _| Ok(vec![]));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(Request::builder().uri(uri).method("GET").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[rstest]
+    #[case("/?can_fly=true&limit=1")]
+    // The total count of a search is based on the search term
+    #[case("/?search=man&limit=1")]
+    #[tokio::test]
+    async fn get_heroes_total_count(#[case] uri: &'static str) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_get_by_name()
+            .returning(|_, _, _| Ok(vec![Default::default()]));
+        repo_mock
+            .expect_count()
+            .withf(|filter| filter.can_fly == Some(true))
+            .returning(|_| Ok(42));
+        repo_mock
+            .expect_search()
+            .returning(|_, _, _| Ok(vec![Default::default()]));
+        repo_mock
+            .expect_count_search()
+            .withf(|term| term == "man")
+            .returning(|_| Ok(42));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(Request::builder().uri(uri).method("GET").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let total_count = response.headers()[TOTAL_COUNT_HEADER].to_str().unwrap();
+        assert_eq!(42, total_count.parse::<i64>().unwrap());
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(1)]
+    #[case(3)]
+    #[tokio::test]
+    async fn stream_heroes(#[case] count: i64) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_stream_by_name()
+            .withf(|filter, _| filter.can_fly == Some(true))
+            .returning(move |_, _| {
+                stream::iter((1..=count).map(|id| {
+                    Ok(IdentifyableHero {
+                        id,
+                        ..Default::default()
+                    })
+                }))
+                .boxed()
+            });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/stream?can_fly=true")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Vec<Value> = serde_json::from_slice(&body).unwrap();
+        let ids: Vec<_> = body.iter().map(|h| h["id"].as_i64().unwrap()).collect();
+        assert_eq!((1..=count).collect::<Vec<_>>(), ids);
+    }
+
+    #[tokio::test]
+    async fn stream_heroes_error() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_stream_by_name()
+            .returning(|_, _| stream::iter([Err(Error::PoolTimedOut)]).boxed());
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/stream")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn stream_heroes_error_mid_stream() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_stream_by_name()
+            .returning(|_, _| stream::iter([Ok(IdentifyableHero::default()), Err(Error::PoolTimedOut)]).boxed());
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/stream")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // Status has already been sent, the body is aborted
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(hyper::body::to_bytes(response.into_body()).await.is_err());
+    }
+
+    #[rstest]
+    #[case(true, StatusCode::OK)]
+    #[case(false, StatusCode::NOT_FOUND)]
+    #[tokio::test]
+    async fn hero_exists(#[case] exists: bool, #[case] status_code: StatusCode) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_exists_by_name()
+            .withf(|name| name == "Super Man")
+            .return_once(move |_| Ok(exists));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/?name=Super%20Man")
+                    .method("HEAD")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
+
+    #[tokio::test]
+    async fn count_heroes() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock
+            .expect_count()
+            .withf(|filter| filter.name_filter.as_deref() == Some("Super%"))
+            .returning(|_| Ok(42));
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/count?name=Super%")
+                    .method("GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body, json!({ "count": 42 }));
+    }
+
+    #[rstest]
+    #[case(&[], "/v1/heroes/1")]
+    #[case(&[("Host", "localhost:4000")], "http://localhost:4000/v1/heroes/1")]
+    #[case(
+        &[("Host", "10.0.0.1:4000"), ("X-Forwarded-Host", "api.example.com"), ("X-Forwarded-Proto", "https")],
+        "https://api.example.com/v1/heroes/1"
+    )]
+    #[case(&[("X-Forwarded-Host", "a.example.com, b.example.com")], "http://a.example.com/v1/heroes/1")]
+    #[tokio::test]
+    async fn insert_hero_location(#[case] request_headers: &[(&str, &str)], #[case] location: &str) {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_insert().returning(|_| {
+            Ok(HeroPkVersion {
+                id: 1,
+                version: 1,
+                ..Default::default()
+            })
+        });
+
+        let repo = Arc::new(repo_mock) as DynHeroesRepository;
+
+        let mut request = Request::builder()
+            .uri("/v1/heroes")
+            .method("POST")
+            .header("Content-Type", "application/json");
+        for (name, value) in request_headers {
+            request = request.header(*name, *value);
+        }
+
+        let body = r#"{ "name": "Superman", "canFly": true, "abilities": "flying" }"#;
+        let app = Router::new().nest("/v1/heroes", heroes_routes(repo));
+        let response = app.oneshot(request.body(Body::from(body)).unwrap()).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[LOCATION], location);
+    }
+
+    #[rstest]
+    #[case(r#"{ "firstSeen": "2022-01-01T00:00:00Z", "name": "", "canFly": false }"#)]
+    #[case(r#"{ "firstSeen": "2022-01-01T00:00:00Z", "name": "Superman", "canFly": true, "realname": "" }"#)]
+    #[case(r#"{ "firstSeen": "2022-01-01T00:00:00Z", "name": "Batman", "canFly": true, "abilities": "a,b,c,d,e,f" }"#)]
+    // Abilities are required
+    #[case(r#"{ "firstSeen": "2022-01-01T00:00:00Z", "name": "Batman", "canFly": false }"#)]
+    #[tokio::test]
+    async fn insert_invalid_hero(#[case] body: &'static str) {
+        // Repository must not be called for invalid heroes
+        let repo = Arc::new(MockHeroesRepositoryTrait::new()) as DynHeroesRepository;
+
+        let app = heroes_routes(repo);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .method("POST")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn hero_events() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_insert().returning(|_| {
+            Ok(HeroPkVersion {
+                id: 1,
+                version: 1,
+                ..Default::default()
+            })
+        });
+
+        let app = heroes_routes(Arc::new(repo_mock));
+        let events = app
+            .clone()
+            .oneshot(Request::builder().uri("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(events.status(), StatusCode::OK);
+        assert_eq!(events.headers()[CONTENT_TYPE], "text/event-stream");
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .method("POST")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(BATMAN))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The event stream never ends, therefore we only read the first chunk
+        let mut body = events.into_body();
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.data())
+            .await
+            .expect("No event received")
+            .unwrap()
+            .unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert_eq!(chunk, "event:change\ndata:{\"kind\":\"created\",\"id\":1}\n\n");
+    }
+
+    #[tokio::test]
+    async fn cleanup_events() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_cleanup().return_once(|_| Ok(vec![1, 2]));
+
+        let app = heroes_routes(Arc::new(repo_mock));
+        let events = app
+            .clone()
+            .oneshot(Request::builder().uri("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(events.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/cleanup?confirm=all")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // Every deleted hero is published as a separate event
+        let mut body = events.into_body();
+        let mut received = String::new();
+        while received.matches("\n\n").count() < 2 {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), body.data())
+                .await
+                .expect("No event received")
+                .unwrap()
+                .unwrap();
+            received.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        assert_eq!(
+            received,
+            "event:change\ndata:{\"kind\":\"deleted\",\"id\":1}\n\nevent:change\ndata:{\"kind\":\"deleted\",\"id\":2}\n\n"
+        );
+    }
 }
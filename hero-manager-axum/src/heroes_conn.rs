@@ -0,0 +1,112 @@
+// Alternative hero handlers using a connection per request
+//
+// The handlers in heroes.rs access the DB through a repository that holds the
+// whole connection pool. Every statement checks out a connection on its own.
+// The handlers in this module use the DatabaseConnection extractor instead.
+// The connection is checked out before the handler runs and kept until the
+// response has been built.
+//
+// Tradeoffs:
+// * A connection per request makes it easy to run multiple statements on the
+//   same connection (e.g. in a transaction spanning the whole request).
+// * Connections are held for the entire request, also while the handler does
+//   not talk to the DB. Under load, the pool is exhausted sooner.
+// * Handlers depend on sqlx directly. They cannot be unit-tested with a mocked
+//   repository; tests need a running Postgres server.
+
+use crate::{
+    axum_helpers::DatabaseConnection,
+    data::{log_error, select_heroes},
+    error,
+    model::{GetHeroFilter, HeroOrdering, IdentifyableHero, Pagination},
+};
+use axum::{
+    extract::{Path, Query},
+    routing::get,
+    Json, Router,
+};
+use sqlx::PgPool;
+
+/// Setup hero routes that use a connection per request
+pub fn heroes_conn_routes(pool: PgPool) -> Router {
+    Router::new()
+        .route("/", get(get_heroes))
+        .route("/:id", get(get_hero_by_id))
+        .with_state(pool)
+}
+
+pub async fn get_heroes(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Query(filter): Query<GetHeroFilter>,
+    Query(ordering): Query<HeroOrdering>,
+    Query(pagination): Query<Pagination>,
+) -> error::Result<Json<Vec<IdentifyableHero>>> {
+    let heroes = select_heroes(&filter, ordering, pagination.bounded())
+        .build_query_as::<IdentifyableHero>()
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(log_error)?;
+    Ok(Json(heroes))
+}
+
+pub async fn get_hero_by_id(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Path(id): Path<i64>,
+) -> error::Result<Json<IdentifyableHero>> {
+    sqlx::query_as::<_, IdentifyableHero>("SELECT * FROM heroes WHERE id = $1 AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(log_error)?
+        .map(Json)
+        .ok_or(error::Error::NotFound("Hero"))
+}
+
+#[cfg(all(test, feature = "integration-tests"))]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{HeroesRepository, HeroesRepositoryTrait},
+        model::Hero,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn get_hero_by_id(pool: PgPool) {
+        let pk = HeroesRepository(pool.clone())
+            .insert(&Hero {
+                name: "Superman".to_string(),
+                abilities: Some(vec!["flying".to_string()]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let app = heroes_conn_routes(pool);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/{}", pk.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["name"], "Superman");
+
+        let response = app
+            .oneshot(Request::builder().uri("/0").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
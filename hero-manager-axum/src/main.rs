@@ -1,19 +1,29 @@
-use crate::{data::HeroesRepository, heroes::DynHeroesRepository, model::AppConfiguration};
+use crate::{
+    cache::CachedHeroesRepository, data::HeroesRepository, heroes::DynHeroesRepository, model::AppConfiguration,
+    singleflight::SingleFlightHeroesRepository,
+};
 use axum::{error_handling::HandleErrorLayer, http, BoxError, Router};
-use clap::{crate_version, Parser};
+use clap::{crate_version, ArgAction, Parser};
 use model::Environment;
-use sqlx::postgres::PgPoolOptions;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    PgPool,
+};
+use std::{net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
 use tokio::signal;
 use tower::ServiceBuilder;
 use tower_http::{catch_panic::CatchPanicLayer, trace::TraceLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod axum_helpers;
+mod cache;
 mod data;
 mod error;
 mod healthcheck;
 mod heroes;
+mod heroes_conn;
 mod model;
+mod singleflight;
+mod telemetry;
 
 /// Arguments for clap
 #[derive(Parser, Debug)]
@@ -27,6 +37,49 @@ struct Args {
 
     #[arg(short, long, default_value = "", env = "DATABASE_URL")]
     database_url: String,
+
+    /// Maximum number of connections in the database connection pool
+    #[arg(long, default_value_t = 5)]
+    max_connections: u32,
+
+    /// Minimum number of idle connections kept in the database connection pool
+    #[arg(long, default_value_t = 0)]
+    min_connections: u32,
+
+    /// Seconds to wait for a free connection before giving up
+    #[arg(long, default_value_t = 30)]
+    acquire_timeout: u64,
+
+    /// Seconds after which the database aborts a statement (0 = no timeout)
+    #[arg(long, default_value_t = 10)]
+    statement_timeout: u64,
+
+    /// Endpoint of an OpenTelemetry collector (OTLP over gRPC) to which spans are exported
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Accept HTTP/2 without TLS (h2c with prior knowledge) in addition to HTTP/1.1 (e.g. --http2 false)
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    http2: bool,
+
+    /// Seconds for which hero listings are cached in memory (0 = no caching)
+    #[arg(long, default_value_t = 0)]
+    listing_cache_ttl: u64,
+}
+
+/// Builds the options for database connections from the command-line args
+fn connect_options(cli: &Args) -> Result<PgConnectOptions, sqlx::Error> {
+    // Runaway queries must not hold a connection forever
+    Ok(PgConnectOptions::from_str(&cli.database_url)?
+        .options([("statement_timeout", format!("{}s", cli.statement_timeout))]))
+}
+
+/// Builds the options for the database connection pool from the command-line args
+fn pool_options(cli: &Args) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(cli.max_connections)
+        .min_connections(cli.min_connections)
+        .acquire_timeout(Duration::from_secs(cli.acquire_timeout))
 }
 
 #[tokio::main]
@@ -35,12 +88,15 @@ async fn main() {
     let cli = Args::parse();
 
     // Setup connection pool
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&cli.database_url)
+    let pool = pool_options(&cli)
+        .connect_with(connect_options(&cli).expect("database URL is valid"))
         .await
         .expect("can connect to database");
 
+    // Bring the DB schema up to date. The migrations from the migrations folder
+    // are embedded into the binary at compile time.
+    sqlx::migrate!().run(&pool).await.expect("can apply database migrations");
+
     // Build app configuration object
     let app_config = Arc::new(AppConfiguration {
         version: crate_version!(),
@@ -48,21 +104,74 @@ async fn main() {
     });
 
     // Configure tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "hero_manager_axum=debug,tower_http=debug,sqlx=debug".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    telemetry::init_tracing(cli.otlp_endpoint.as_deref()).expect("can configure tracing");
+
+    // Concurrent identical listings share a single query; the cache (if enabled) sits in front of that
+    let repo = Arc::new(HeroesRepository(pool.clone())) as DynHeroesRepository;
+    let mut repo = Arc::new(SingleFlightHeroesRepository::new(repo)) as DynHeroesRepository;
+    if cli.listing_cache_ttl > 0 {
+        repo = Arc::new(CachedHeroesRepository::new(repo, Duration::from_secs(cli.listing_cache_ttl)));
+    }
+    let app = build_app(app_config, repo, pool);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], cli.port));
+    println!("listening on {}", addr);
+    axum::Server::bind(&addr)
+        .http1_only(!cli.http2)
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    telemetry::shutdown_tracing();
+}
+
+/// Versions of the API
+///
+/// Clients select a version with the URI prefix (e.g. /v1/heroes). To add a version,
+/// add a variant here and its routes in api_routes. Requests for versions that are
+/// not listed (e.g. /v2 until it is implemented) get 404. Health checks and probes
+/// are not versioned.
+#[derive(Debug, Clone, Copy)]
+enum ApiVersion {
+    V1,
+}
+
+impl ApiVersion {
+    const ALL: [ApiVersion; 1] = [ApiVersion::V1];
+
+    fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+        }
+    }
+}
+
+/// Routes of a single API version (without version prefix)
+fn api_routes(version: ApiVersion, repo: DynHeroesRepository, pool: PgPool) -> Router {
+    match version {
+        ApiVersion::V1 => Router::new()
+            // Add heroes routes under /heroes
+            .nest("/heroes", heroes::heroes_routes(repo))
+            // Add alternative heroes routes using a connection per request
+            .nest("/conn/heroes", heroes_conn::heroes_conn_routes(pool)),
+    }
+}
 
-    let repo = Arc::new(HeroesRepository(pool)) as DynHeroesRepository;
+/// Setup top-level router
+///
+/// Handlers share state via Axum's state API: the heroes handlers get the repository
+/// (dyn trait so that it can be mocked), the probes get the connection pool.
+fn build_app(app_config: Arc<AppConfiguration>, repo: DynHeroesRepository, pool: PgPool) -> Router {
+    let api = ApiVersion::ALL.into_iter().fold(Router::new(), |router, version| {
+        router.nest(version.prefix(), api_routes(version, repo.clone(), pool.clone()))
+    });
 
-    // Setup top-level router
-    let app = Router::new()
+    Router::new()
         // Add healthcheck routes
-        .merge(healthcheck::healthcheck_routes(app_config.clone()))
-        // Add heroes routes under /heroes
-        .nest("/heroes", heroes::heroes_routes(repo))
+        .merge(healthcheck::healthcheck_routes(app_config))
+        .merge(healthcheck::probe_routes(pool))
+        .merge(api)
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
@@ -71,15 +180,7 @@ async fn main() {
                 }))
                 .timeout(Duration::from_secs(2))
                 .layer(CatchPanicLayer::custom(error::handle_panic)),
-        );
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], cli.port));
-    println!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+        )
 }
 
 async fn shutdown_signal() {
@@ -105,3 +206,108 @@ async fn shutdown_signal() {
 
     println!("signal received, starting graceful shutdown");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::MockHeroesRepositoryTrait;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    #[test]
+    fn pool_options_from_args() {
+        let cli = Args::try_parse_from([
+            "hero-manager-axum",
+            "--max-connections",
+            "20",
+            "--min-connections",
+            "2",
+            "--acquire-timeout",
+            "10",
+        ])
+        .unwrap();
+
+        let options = pool_options(&cli);
+        assert_eq!(20, options.get_max_connections());
+        assert_eq!(2, options.get_min_connections());
+        assert_eq!(Duration::from_secs(10), options.get_acquire_timeout());
+    }
+
+    #[tokio::test]
+    async fn app_routes() {
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        repo_mock.expect_count().returning(|_| Ok(42));
+
+        // The pool is never connected because no DB probe is called
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://postgres@127.0.0.1:1/heroes")
+            .unwrap();
+        let app_config = Arc::new(AppConfiguration {
+            version: "1.0.0",
+            env: Environment::Test,
+        });
+        let app = build_app(app_config, Arc::new(repo_mock), pool);
+
+        for (uri, status) in [
+            ("/livez", http::StatusCode::OK),
+            ("/health_1", http::StatusCode::OK),
+            ("/v1/heroes/count", http::StatusCode::OK),
+            // Not implemented yet
+            ("/v2/heroes/count", http::StatusCode::NOT_FOUND),
+            ("/heroes/count", http::StatusCode::NOT_FOUND),
+            ("/unknown", http::StatusCode::NOT_FOUND),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(status, response.status(), "{uri}");
+        }
+    }
+
+    #[test]
+    fn connect_options_statement_timeout() {
+        let cli = Args::try_parse_from([
+            "hero-manager-axum",
+            "--database-url",
+            "postgres://postgres@localhost/heroes",
+            "--statement-timeout",
+            "5",
+        ])
+        .unwrap();
+
+        let options = connect_options(&cli).unwrap();
+        assert_eq!(Some("-c statement_timeout=5s"), options.get_options());
+        assert_eq!(Some("heroes"), options.get_database());
+    }
+
+    #[test]
+    fn http2_flag() {
+        assert!(Args::try_parse_from(["hero-manager-axum"]).unwrap().http2);
+        assert!(!Args::try_parse_from(["hero-manager-axum", "--http2", "false"]).unwrap().http2);
+    }
+
+    #[test]
+    fn pool_options_defaults() {
+        let options = pool_options(&Args::try_parse_from(["hero-manager-axum"]).unwrap());
+        assert_eq!(5, options.get_max_connections());
+        assert_eq!(0, options.get_min_connections());
+        assert_eq!(Duration::from_secs(30), options.get_acquire_timeout());
+    }
+
+    // Note that sqlx::test creates a throwaway database for each test. It needs
+    // a DATABASE_URL pointing to a running Postgres server.
+
+    #[cfg(feature = "integration-tests")]
+    #[sqlx::test(migrations = false)]
+    async fn migrations_apply_cleanly(pool: sqlx::PgPool) {
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM heroes")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(0, count);
+    }
+}
@@ -6,13 +6,13 @@
 //
 // Nevertheless, the model has been chosen to demonstrate some interesting aspects:
 // * Storing vectors in Postgres (abilities)
-// * Customizing serde for properties (abilities)
+// * Customizing serde for properties (abilities, see dto::comma_separated)
 // * Various serde macros (e.g. camelCase, flatten)
 // * Auto-mapping DB columns to fiels (FromRow)
 
 use chrono::{DateTime, Utc};
 use clap::ValueEnum;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use validator::Validate;
 
@@ -30,26 +30,203 @@ pub struct AppConfiguration {
     pub env: Environment
 }
 
+/// Default number of heroes returned by a list request
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Maximum number of heroes returned by a list request
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Parameters for pagination
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pagination {
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default = "default_page_size")]
+    pub limit: i64,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Pagination::new(0, DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl Pagination {
+    pub fn new(offset: i64, limit: i64) -> Pagination {
+        Pagination { offset, limit }
+    }
+
+    /// Returns pagination parameters that are within the allowed bounds
+    pub fn bounded(self) -> Self {
+        Pagination::new(self.offset.max(0), self.limit.clamp(1, MAX_PAGE_SIZE))
+    }
+}
+
+fn default_page_size() -> i64 {
+    DEFAULT_PAGE_SIZE
+}
+
+/// Parameters for cursor-based pagination
+///
+/// In contrast to offset pagination, pages do not drift if heroes are inserted
+/// or deleted while a client pages through the list.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    /// Id of the last hero of the previous page (0 for the first page)
+    #[serde(default)]
+    pub after: i64,
+    #[serde(default = "default_page_size")]
+    pub limit: i64,
+}
+
+impl Cursor {
+    /// Returns cursor parameters with a limit within the allowed bounds
+    pub fn bounded(self) -> Self {
+        Cursor {
+            limit: self.limit.clamp(1, MAX_PAGE_SIZE),
+            ..self
+        }
+    }
+}
+
+/// Page of heroes returned by cursor-based pagination
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeroPage {
+    pub heroes: Vec<IdentifyableHero>,
+    /// Cursor for the next page; None if there are no more heroes
+    pub next_cursor: Option<i64>,
+}
+
+/// Filter for hero listings
+///
+/// All given criteria have to match (AND).
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct GetHeroFilter {
+    /// LIKE pattern for the hero name (case-insensitive)
+    #[serde(rename = "name")]
+    pub name_filter: Option<String>,
+    pub can_fly: Option<bool>,
+    pub ability: Option<String>,
+    /// Include soft-deleted heroes
+    #[serde(default)]
+    pub include_deleted: bool,
+    // In practice, add additional query parameters here
+}
+
+impl GetHeroFilter {
+    /// Checks whether the filter restricts the heroes in any way
+    ///
+    /// include_deleted is not a restriction, it widens the result.
+    pub fn has_criteria(&self) -> bool {
+        self.name_filter.is_some() || self.can_fly.is_some() || self.ability.is_some()
+    }
+}
+
+/// Parameters for deleting heroes
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CleanupQuery {
+    /// Has to be "all" to delete all heroes without a filter
+    pub confirm: Option<String>,
+}
+
+/// Parameters for checking whether a hero exists
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HeroExistsQuery {
+    /// Exact name of the hero
+    pub name: String,
+}
+
+/// Parameters for searching heroes
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct HeroSearch {
+    /// Text that has to be contained in name or realname (case-insensitive)
+    pub search: Option<String>,
+}
+
+/// Columns that hero listings can be sorted by
+///
+/// Only columns listed here can end up in the ORDER BY clause. This
+/// prevents SQL injection through the sort query parameter.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HeroSortColumn {
+    #[default]
+    Id,
+    Name,
+    FirstSeen,
+}
+
+impl HeroSortColumn {
+    fn column_name(self) -> &'static str {
+        match self {
+            HeroSortColumn::Id => "id",
+            HeroSortColumn::Name => "name",
+            HeroSortColumn::FirstSeen => "first_seen",
+        }
+    }
+}
+
+/// Parameters for sorting hero listings
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeroOrdering {
+    #[serde(default)]
+    pub sort: HeroSortColumn,
+    #[serde(default)]
+    pub desc: bool,
+}
+
+impl HeroOrdering {
+    /// Builds the ORDER BY clause
+    ///
+    /// Id is used as a tie breaker so that pagination is stable.
+    pub fn to_sql(self) -> String {
+        let direction = if self.desc { "DESC" } else { "ASC" };
+        match self.sort {
+            HeroSortColumn::Id => format!("ORDER BY id {direction}"),
+            column => format!("ORDER BY {} {direction}, id {direction}", column.column_name()),
+        }
+    }
+}
+
 /// Represents a hero
 #[derive(Serialize, Deserialize, Validate, Clone)]
 #[serde(rename_all = "camelCase")]
 #[derive(FromRow, Default)]
 pub struct Hero {
+    /// Point in time when the hero was first seen; defaults to now if omitted
+    #[serde(default = "Utc::now")]
     pub first_seen: DateTime<Utc>,
+    #[validate(length(min = 1, max = 100))]
     pub name: String,
     pub can_fly: bool,
+    #[validate(length(min = 1))]
     pub realname: Option<String>,
-    #[serde(
-        deserialize_with = "deserialize_abilities",
-        serialize_with = "serialize_abilities",
-        default
-    )]
-    #[validate(length(max = 5))]
+    /// Required (the DB column is NOT NULL); Option only because of comma_separated
+    #[serde(with = "dto::comma_separated", default)]
+    #[validate(required, length(min = 1, max = 5))]
+    pub abilities: Option<Vec<String>>,
+}
+
+/// DTO for patching a hero
+///
+/// Only fields that are present in the request body are changed.
+#[derive(Serialize, Deserialize, Validate, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateHero {
+    pub first_seen: Option<DateTime<Utc>>,
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    pub can_fly: Option<bool>,
+    #[validate(length(min = 1))]
+    pub realname: Option<String>,
+    #[serde(with = "dto::comma_separated", default)]
+    #[validate(length(min = 1, max = 5))]
     pub abilities: Option<Vec<String>>,
 }
 
 /// Represents a hero with primary key and version
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 #[derive(FromRow, Default)]
 pub struct IdentifyableHero {
@@ -58,29 +235,30 @@ pub struct IdentifyableHero {
     #[sqlx(flatten)]
     pub inner_hero: Hero,
     pub version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Point in time when the hero has been (soft) deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
-/// Deserialize vector of abilities from comma-separated string
-fn deserialize_abilities<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let concat_abilities = Option::<String>::deserialize(deserializer)?;
-    match concat_abilities {
-        Some(abilities) => Ok(Some(abilities.split(',').map(|s| s.trim().to_string()).collect())),
-        None => Ok(None),
-    }
+/// Kind of change of a hero
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HeroChangeKind {
+    Created,
+    Updated,
+    Deleted,
 }
 
-/// Serialize vector of abilities into comma-separated string
-fn serialize_abilities<S>(x: &Option<Vec<String>>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    match x {
-        Some(abilities) => serde::Serialize::serialize(&abilities.join(", "), serializer),
-        None => serde::Serialize::serialize(&Option::<Vec<String>>::None, serializer),
-    }
+/// Notification about a changed hero
+///
+/// Only contains the id. Clients interested in the hero's data have to load it.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HeroChange {
+    pub kind: HeroChangeKind,
+    pub id: i64,
 }
 
 #[cfg(test)]
@@ -88,15 +266,13 @@ mod tests {
     // The following tests verify that abilities are serialized
     // and deserialized properly.
 
+    use super::{Hero, HeroOrdering, HeroSortColumn, Pagination, MAX_PAGE_SIZE};
+    use chrono::{TimeZone, Utc};
     use serde::{Deserialize, Serialize};
 
     #[derive(Serialize, Deserialize)]
     struct JustAbilities {
-        #[serde(
-            serialize_with = "super::serialize_abilities",
-            deserialize_with = "super::deserialize_abilities",
-            default
-        )]
+        #[serde(with = "dto::comma_separated", default)]
         pub abilities: Option<Vec<String>>,
     }
 
@@ -128,9 +304,56 @@ mod tests {
         assert_eq!(vec!["a", "b"], serialized.abilities.unwrap());
     }
 
+    #[test]
+    fn deserialize_abilities_array() {
+        let serialized: JustAbilities = serde_json::from_str(r#"{ "abilities": ["a", " b"] }"#).unwrap();
+        assert_eq!(vec!["a", "b"], serialized.abilities.unwrap());
+    }
+
+    #[test]
+    fn deserialize_abilities_invalid() {
+        assert!(serde_json::from_str::<JustAbilities>(r#"{ "abilities": 42 }"#).is_err());
+    }
+
     #[test]
     fn deserialize_none() {
         let serialized: JustAbilities = serde_json::from_str("{}").unwrap();
         assert!(serialized.abilities.is_none());
     }
+
+    #[rstest::rstest]
+    #[case(HeroSortColumn::Id, false, "ORDER BY id ASC")]
+    #[case(HeroSortColumn::Id, true, "ORDER BY id DESC")]
+    #[case(HeroSortColumn::Name, false, "ORDER BY name ASC, id ASC")]
+    #[case(HeroSortColumn::FirstSeen, true, "ORDER BY first_seen DESC, id DESC")]
+    fn ordering_to_sql(#[case] sort: HeroSortColumn, #[case] desc: bool, #[case] expected: &str) {
+        assert_eq!(expected, HeroOrdering { sort, desc }.to_sql());
+    }
+
+    #[test]
+    fn ordering_unknown_column() {
+        assert!(serde_json::from_str::<HeroOrdering>(r#"{ "sort": "realname" }"#).is_err());
+        assert!(serde_json::from_str::<HeroOrdering>(r#"{ "sort": "name; DROP TABLE heroes" }"#).is_err());
+    }
+
+    #[test]
+    fn pagination_bounded() {
+        assert_eq!(Pagination::new(0, MAX_PAGE_SIZE), Pagination::new(-1, 1000).bounded());
+        assert_eq!(Pagination::new(5, 1), Pagination::new(5, 0).bounded());
+    }
+
+    #[test]
+    fn first_seen_defaults_to_now() {
+        let before = Utc::now();
+        let hero: Hero = serde_json::from_str(r#"{ "name": "Batman", "canFly": false }"#).unwrap();
+        assert!(hero.first_seen >= before && hero.first_seen <= Utc::now());
+    }
+
+    #[test]
+    fn first_seen_explicit() {
+        let hero: Hero =
+            serde_json::from_str(r#"{ "firstSeen": "1939-05-01T00:00:00Z", "name": "Batman", "canFly": false }"#)
+                .unwrap();
+        assert_eq!(Utc.with_ymd_and_hms(1939, 5, 1, 0, 0, 0).unwrap(), hero.first_seen);
+    }
 }
@@ -0,0 +1,295 @@
+// Deduplication of concurrent identical queries (singleflight)
+//
+// During load spikes, many clients request the same hero listing at the same
+// time. Without deduplication, every request issues its own SQL query. With
+// SingleFlight, the first request (leader) runs the query. Requests with the
+// same key arriving while the query is in flight (followers) wait for it and
+// get a copy of its result.
+//
+// Errors are not shared because sqlx errors cannot be cloned. If the leader
+// fails (or is cancelled), followers run the query themselves.
+
+use std::{collections::HashMap, future::Future, hash::Hash, sync::Mutex};
+
+use axum::async_trait;
+use futures::stream::BoxStream;
+use tokio::sync::broadcast;
+
+use crate::{
+    data::{HeroPkVersion, HeroesRepositoryTrait},
+    heroes::DynHeroesRepository,
+    model::{GetHeroFilter, Hero, HeroOrdering, IdentifyableHero, Pagination, UpdateHero},
+};
+
+/// Runs operations with the same key only once at a time
+pub struct SingleFlight<K, V> {
+    /// Operations in flight; followers subscribe to get the result of the leader
+    in_flight: Mutex<HashMap<K, broadcast::Sender<V>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    /// Runs the operation unless an operation with the same key is already in flight
+    ///
+    /// In the latter case, waits for the running operation and returns its result.
+    pub async fn run<F, Fut, E>(&self, key: K, operation: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let follower = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(sender) => Some(sender.subscribe()),
+                None => {
+                    in_flight.insert(key.clone(), broadcast::channel(1).0);
+                    None
+                },
+            }
+        };
+
+        if let Some(mut receiver) = follower {
+            // Closed without value if the leader failed or has been cancelled
+            if let Ok(value) = receiver.recv().await {
+                return Ok(value);
+            }
+            return operation().await;
+        }
+
+        // Removes the key even if this future is dropped while the operation is running
+        let guard = InFlight { flights: self, key };
+        let result = operation().await;
+        if let (Some(sender), Ok(value)) = (guard.finish(), &result) {
+            // Fails if there are no followers, which is fine
+            let _ = sender.send(value.clone());
+        }
+        result
+    }
+}
+
+/// Marks an operation as in flight while it exists
+struct InFlight<'a, K: Hash + Eq, V> {
+    flights: &'a SingleFlight<K, V>,
+    key: K,
+}
+
+impl<K: Hash + Eq, V> InFlight<'_, K, V> {
+    /// Removes the key so that new requests start a new operation
+    fn finish(&self) -> Option<broadcast::Sender<V>> {
+        self.flights.in_flight.lock().unwrap().remove(&self.key)
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for InFlight<'_, K, V> {
+    fn drop(&mut self) {
+        // Dropping the sender wakes up waiting followers
+        self.finish();
+    }
+}
+
+/// Arguments of get_by_name identifying identical queries
+type ListingKey = (GetHeroFilter, HeroOrdering, Pagination);
+
+/// Repository deduplicating concurrent identical hero listings of another repository
+pub struct SingleFlightHeroesRepository {
+    inner: DynHeroesRepository,
+    listings: SingleFlight<ListingKey, Vec<IdentifyableHero>>,
+}
+
+impl SingleFlightHeroesRepository {
+    pub fn new(inner: DynHeroesRepository) -> Self {
+        SingleFlightHeroesRepository {
+            inner,
+            listings: SingleFlight::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl HeroesRepositoryTrait for SingleFlightHeroesRepository {
+    async fn cleanup(&self, filter: &GetHeroFilter) -> Result<Vec<i64>, sqlx::error::Error> {
+        self.inner.cleanup(filter).await
+    }
+
+    async fn delete(&self, id: i64) -> Result<bool, sqlx::error::Error> {
+        self.inner.delete(id).await
+    }
+
+    async fn get_by_name(
+        &self,
+        filter: &GetHeroFilter,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        let key = (filter.clone(), ordering, pagination);
+        self.listings
+            .run(key, || self.inner.get_by_name(filter, ordering, pagination))
+            .await
+    }
+
+    async fn get_after(&self, after_id: i64, limit: i64) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.get_after(after_id, limit).await
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        ordering: HeroOrdering,
+        pagination: Pagination,
+    ) -> Result<Vec<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.search(term, ordering, pagination).await
+    }
+
+    fn stream_by_name(
+        &self,
+        filter: GetHeroFilter,
+        ordering: HeroOrdering,
+    ) -> BoxStream<'static, Result<IdentifyableHero, sqlx::error::Error>> {
+        self.inner.stream_by_name(filter, ordering)
+    }
+
+    async fn count(&self, filter: &GetHeroFilter) -> Result<i64, sqlx::error::Error> {
+        self.inner.count(filter).await
+    }
+
+    async fn count_search(&self, term: &str) -> Result<i64, sqlx::error::Error> {
+        self.inner.count_search(term).await
+    }
+
+    async fn exists_by_name(&self, name: &str) -> Result<bool, sqlx::error::Error> {
+        self.inner.exists_by_name(name).await
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.get_by_id(id).await
+    }
+
+    async fn insert(&self, hero: &Hero) -> Result<HeroPkVersion, sqlx::error::Error> {
+        self.inner.insert(hero).await
+    }
+
+    async fn update(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &UpdateHero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.update(id, expected_version, hero).await
+    }
+
+    async fn replace(
+        &self,
+        id: i64,
+        expected_version: Option<i32>,
+        hero: &Hero,
+    ) -> Result<Option<IdentifyableHero>, sqlx::error::Error> {
+        self.inner.replace(id, expected_version, hero).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::MockHeroesRepositoryTrait;
+    use futures::future::join_all;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Barrier,
+    };
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn identical_listings_share_query() {
+        const REQUESTS: usize = 10;
+
+        // Keeps the query in flight until all requests have arrived. The mock cannot
+        // await, so it blocks its worker thread; the others handle the requests.
+        let release = Arc::new(Barrier::new(2));
+        let mut repo_mock = MockHeroesRepositoryTrait::new();
+        let query_release = release.clone();
+        repo_mock.expect_get_by_name().times(1).returning(move |_, _, _| {
+            query_release.wait();
+            Ok(vec![IdentifyableHero {
+                id: 1,
+                ..Default::default()
+            }])
+        });
+
+        let repo = Arc::new(SingleFlightHeroesRepository::new(Arc::new(repo_mock)));
+        let requests = (0..REQUESTS)
+            .map(|_| {
+                let repo = repo.clone();
+                tokio::spawn(async move {
+                    repo.get_by_name(
+                        &GetHeroFilter::default(),
+                        HeroOrdering::default(),
+                        Pagination::default(),
+                    )
+                    .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Release the query once all other requests have subscribed to its result
+        let key = (GetHeroFilter::default(), HeroOrdering::default(), Pagination::default());
+        let followers = || {
+            let in_flight = repo.listings.in_flight.lock().unwrap();
+            in_flight.get(&key).map_or(0, |sender| sender.receiver_count())
+        };
+        while followers() < REQUESTS - 1 {
+            tokio::task::yield_now().await;
+        }
+        release.wait();
+
+        for result in join_all(requests).await {
+            assert_eq!(result.unwrap().unwrap()[0].id, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn different_keys_run_separately() {
+        let calls = AtomicUsize::new(0);
+        let flights = SingleFlight::<&str, usize>::default();
+        let operation = || async {
+            tokio::task::yield_now().await;
+            Ok::<_, ()>(calls.fetch_add(1, Ordering::SeqCst))
+        };
+
+        let (a1, a2, b) = tokio::join!(
+            flights.run("a", operation),
+            flights.run("a", operation),
+            flights.run("b", operation)
+        );
+        assert_eq!(a1, a2);
+        assert_ne!(a1, b);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Finished operations are not remembered
+        flights.run("a", operation).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_shared() {
+        let calls = AtomicUsize::new(0);
+        let flights = SingleFlight::<&str, usize>::default();
+        let operation = || async {
+            tokio::task::yield_now().await;
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err("leader failed"),
+                call => Ok(call),
+            }
+        };
+
+        let (leader, follower) = tokio::join!(flights.run("a", operation), flights.run("a", operation));
+        assert_eq!(leader, Err("leader failed"));
+        // The follower ran the operation itself
+        assert_eq!(follower, Ok(1));
+    }
+}
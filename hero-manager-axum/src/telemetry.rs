@@ -0,0 +1,73 @@
+// Tracing setup
+//
+// Logs always go to stdout. If an OTLP endpoint is configured, spans are
+// additionally exported to an OpenTelemetry collector (e.g. Jaeger or the
+// OpenTelemetry Collector) using OTLP over gRPC.
+
+use opentelemetry::{trace::TraceError, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Name under which the spans of this service show up in the collector
+const SERVICE_NAME: &str = "hero-manager-axum";
+
+/// Builds a tracer exporting spans to the collector at the given endpoint
+///
+/// Spans are exported in batches in the background. Note that the connection
+/// to the collector is established lazily. An unreachable collector does not
+/// prevent the app from starting.
+pub fn otlp_tracer(endpoint: &str) -> Result<trace::Tracer, TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", SERVICE_NAME)])),
+        )
+        .install_batch(runtime::Tokio)
+}
+
+/// Configures tracing for the app
+///
+/// Falls back to logging to stdout only if no OTLP endpoint is given.
+pub fn init_tracing(otlp_endpoint: Option<&str>) -> Result<(), TraceError> {
+    let otlp_layer = match otlp_endpoint {
+        Some(endpoint) => Some(tracing_opentelemetry::layer().with_tracer(otlp_tracer(endpoint)?)),
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "hero_manager_axum=debug,tower_http=debug,sqlx=debug".into()),
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .with(otlp_layer)
+        .init();
+    Ok(())
+}
+
+/// Exports the spans that have not been exported yet
+///
+/// Has to be called before the app exits.
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::Registry;
+
+    // Shutting down the batch exporter blocks a single-threaded runtime
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscriber_with_otlp_exporter() {
+        // Nothing listens at this endpoint. Building must succeed nevertheless.
+        let tracer = otlp_tracer("http://localhost:4317").unwrap();
+        let subscriber = Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("test_span").in_scope(|| tracing::info!("inside span"));
+        });
+        shutdown_tracing();
+    }
+}
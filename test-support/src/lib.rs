@@ -0,0 +1,82 @@
+//! Helpers for testing the HTTP samples in-process
+//!
+//! Every sample used to bind its own listener and build its own client in tests.
+//! TestServer starts an app on an ephemeral port and returns a reqwest client for it.
+//! It works with every framework that can serve from a std TcpListener.
+
+use std::{
+    future::Future,
+    net::{SocketAddr, TcpListener},
+};
+
+use reqwest::{Client, RequestBuilder};
+
+/// App running on an ephemeral port of localhost
+pub struct TestServer {
+    addr: SocketAddr,
+    client: Client,
+}
+
+impl TestServer {
+    /// Starts a server on an ephemeral port; has to be called within a Tokio runtime
+    ///
+    /// `serve` gets the bound (non-blocking) listener and returns the future running
+    /// the server, e.g. for axum:
+    /// `|listener| async move { axum::serve(tokio::net::TcpListener::from_std(listener)?, app).await }`.
+    pub fn start<F, Fut>(serve: F) -> Self
+    where
+        F: FnOnce(TcpListener) -> Fut,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("cannot bind ephemeral port");
+        listener
+            .set_nonblocking(true)
+            .expect("cannot make listener non-blocking");
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+
+        TestServer {
+            addr,
+            client: Client::new(),
+        }
+    }
+
+    /// Starts an axum (0.7) router on an ephemeral port
+    #[cfg(feature = "axum")]
+    pub fn start_axum(app: axum::Router) -> Self {
+        Self::start(|listener| async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, app).await.unwrap();
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Gets the absolute URL of a path (e.g. /todos)
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.client.get(self.url(path))
+    }
+
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.client.post(self.url(path))
+    }
+
+    pub fn patch(&self, path: &str) -> RequestBuilder {
+        self.client.patch(self.url(path))
+    }
+
+    pub fn delete(&self, path: &str) -> RequestBuilder {
+        self.client.delete(self.url(path))
+    }
+}
@@ -6,14 +6,95 @@ use actix_web::{
     web::{Data, Json, Path, Query},
     App, Either, HttpResponse, HttpServer, Responder, ResponseError,
 };
+use app_config::AppConfig;
 use log::debug;
 use simplelog::{Config, LevelFilter, SimpleLogger};
-use std::{fmt::Display, sync::Arc};
-use todo_logic::{IdentifyableTodoItem, Pagination, TodoItem, TodoStore, TodoStoreError, UpdateTodoItem};
+use async_trait::async_trait;
+use std::{fmt::Display, path::PathBuf, sync::Arc};
+use todo_logic::{
+    postgres_repository::PostgresTodoRepository, redis_repository::RedisTodoRepository,
+    sled_repository::SledTodoRepository, DynTodoRepository, IdentifyableTodoItem, Pagination, TodoItem,
+    TodoRepository, TodoStore, TodoStoreError, UpdateTodoItem,
+};
 use tokio::sync::RwLock;
+use validated_json::ValidatedJson;
+
+mod validated_json;
 
 /// Type for our shared state
-type Db = Arc<RwLock<TodoStore>>;
+///
+/// Handlers work with any TodoRepository. The database_url setting selects
+/// which one (see connect_repository).
+type Db = DynTodoRepository;
+
+/// Prefix of the Redis keys of the todo items
+const REDIS_PREFIX: &str = "todos";
+
+/// Creates the repository selected by the database_url setting
+///
+/// * `redis://...` stores the todo items in Redis
+/// * `postgres://...` stores the todo items in Postgres
+/// * `sled://<directory>` stores the todo items in a sled database in the given directory
+///
+/// Without database_url, the todo items are kept in memory and persisted to persist_path.
+async fn connect_repository(config: &AppConfig) -> Result<Db, TodoStoreError> {
+    let Some(url) = config.database_url.as_deref() else {
+        let store = TodoStore::load(&config.persist_path).await?;
+        return Ok(Arc::new(FileTodoStore {
+            store: RwLock::new(store),
+            path: config.persist_path.clone(),
+        }));
+    };
+
+    match url.split_once("://") {
+        Some(("redis" | "rediss", _)) => Ok(Arc::new(RedisTodoRepository::connect(url, REDIS_PREFIX).await?)),
+        Some(("postgres" | "postgresql", _)) => PostgresTodoRepository::connect(url).await,
+        Some(("sled", path)) => Ok(Arc::new(SledTodoRepository::open(path)?)),
+        _ => Err(TodoStoreError::RepositoryError(
+            format!("unsupported database URL: {url}").into(),
+        )),
+    }
+}
+
+/// In-memory todo items that are persisted to the given file
+///
+/// TodoStore's implementation of TodoRepository always persists to
+/// TodoStore::DEFAULT_FILENAME; this one respects the persist_path setting.
+struct FileTodoStore {
+    store: RwLock<TodoStore>,
+    path: PathBuf,
+}
+
+#[async_trait]
+impl TodoRepository for FileTodoStore {
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError> {
+        self.store.get_todos(pagination).await
+    }
+
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        self.store.get_todo(id).await
+    }
+
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError> {
+        self.store.add_todo(todo).await
+    }
+
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        self.store.remove_todo(id).await
+    }
+
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        self.store.update_todo(id, todo).await
+    }
+
+    async fn persist(&self) -> Result<(), TodoStoreError> {
+        self.store.read().await.persist_to(&self.path).await
+    }
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -23,8 +104,15 @@ async fn main() -> std::io::Result<()> {
     // compatible logger, but for this example we'll use simplelog.
     SimpleLogger::init(LevelFilter::Debug, Config::default()).unwrap();
 
-    // Create shared data store
-    let state = Data::new(Db::default());
+    // Settings from app.toml, APP_* environment variables and command-line args
+    let config = AppConfig::load_from_args().expect("configuration is valid");
+
+    // Create the shared repository (e.g. with the todo items persisted by a previous run)
+    let state = Data::new(
+        connect_repository(&config)
+            .await
+            .expect("can connect to the todo repository"),
+    );
 
     HttpServer::new(move || {
         App::new()
@@ -60,10 +148,9 @@ async fn main() -> std::io::Result<()> {
 /// Actix comes with a lot of built-in responders, but you can also
 /// implement your own.
 #[get("/todos")]
-async fn get_todos(pagination: Query<Pagination>, db: Data<Db>) -> impl Responder {
-    let todos = db.read().await;
+async fn get_todos(pagination: Query<Pagination>, db: Data<Db>) -> Result<impl Responder, AppError> {
     let Query(pagination) = pagination;
-    Json(todos.get_todos(pagination))
+    Ok(Json(db.get_todos(pagination).await?))
 }
 
 /// If a method returns different return types, Actix offers
@@ -71,46 +158,48 @@ async fn get_todos(pagination: Query<Pagination>, db: Data<Db>) -> impl Responde
 type ItemOrStatus = Either<Json<IdentifyableTodoItem>, HttpResponse>;
 
 /// Get a single todo item
-async fn get_todo(id: Path<usize>, db: Data<Db>) -> ItemOrStatus {
-    let todos = db.read().await;
-    if let Some(item) = todos.get_todo(*id) {
-        Either::Left(Json(item.clone()))
+async fn get_todo(id: Path<usize>, db: Data<Db>) -> Result<ItemOrStatus, AppError> {
+    if let Some(item) = db.get_todo(*id).await? {
+        Ok(Either::Left(Json(item)))
     } else {
         // Use HttpResponse to build responses with status code,
         // body, headers, etc.
-        Either::Right(HttpResponse::NotFound().body("Not found"))
+        Ok(Either::Right(HttpResponse::NotFound().body("Not found")))
     }
 }
 
 /// Add a new todo item
 ///
-/// Note the use of the Json extractor to extract the body.
+/// Note the use of a custom extractor to extract and validate the body
+/// (see validated_json.rs). Invalid todo items never reach the handler.
 #[post("/todos")]
-async fn add_todo(db: Data<Db>, todo: Json<TodoItem>) -> impl Responder {
-    let mut todos = db.write().await;
-    let todo = todos.add_todo(todo.clone());
-    HttpResponse::Created().json(todo)
+async fn add_todo(db: Data<Db>, todo: ValidatedJson<TodoItem>) -> Result<impl Responder, AppError> {
+    let todo = db.add_todo(todo.into_inner()).await?;
+    Ok(HttpResponse::Created().json(todo))
 }
 
 /// Delete a todo item
 ///
 /// Note the use of another Extractor, Path, to extract the id.
 #[delete("/todos/{id}")]
-async fn delete_todo(id: Path<usize>, db: Data<Db>) -> impl Responder {
-    match db.write().await.remove_todo(*id) {
+async fn delete_todo(id: Path<usize>, db: Data<Db>) -> Result<impl Responder, AppError> {
+    Ok(match db.remove_todo(*id).await? {
         Some(_) => HttpResponse::NoContent(),
         None => HttpResponse::NotFound(),
-    }
+    })
 }
 
 /// Update a todo item
 #[patch("/todos/{id}")]
-async fn update_todo(id: Path<usize>, db: Data<Db>, input: Json<UpdateTodoItem>) -> ItemOrStatus {
-    let mut todos = db.write().await;
-    let res = todos.update_todo(&id, input.into_inner());
+async fn update_todo(
+    id: Path<usize>,
+    db: Data<Db>,
+    input: ValidatedJson<UpdateTodoItem>,
+) -> Result<ItemOrStatus, AppError> {
+    let res = db.update_todo(*id, input.into_inner()).await?;
     match res {
-        Some(todo) => Either::Left(Json(todo.clone())),
-        None => Either::Right(HttpResponse::NotFound().finish()),
+        Some(todo) => Ok(Either::Left(Json(todo))),
+        None => Ok(Either::Right(HttpResponse::NotFound().finish())),
     }
 }
 
@@ -140,7 +229,11 @@ impl Display for AppError {
 /// More about error handling at https://actix.rs/docs/errors/.
 impl ResponseError for AppError {
     fn status_code(&self) -> actix_web::http::StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        match self {
+            AppError::TodoStore(TodoStoreError::Conflict(_)) => StatusCode::CONFLICT,
+            AppError::TodoStore(TodoStoreError::ValidationError(_)) => StatusCode::BAD_REQUEST,
+            AppError::TodoStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
     }
 
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
@@ -148,12 +241,15 @@ impl ResponseError for AppError {
             AppError::TodoStore(e) => match e {
                 TodoStoreError::FileAccessError(_) => "Error while writing to file",
                 TodoStoreError::SerializationError(_) => "Error during serialization",
+                TodoStoreError::RepositoryError(_) => "Error while accessing the repository",
+                TodoStoreError::Conflict(_) => "Conflict with an existing todo item",
+                TodoStoreError::ValidationError(_) => "Invalid todo item",
             },
         })
     }
 }
 
-/// Persist the todo store to disk
+/// Persist the todo items
 ///
 /// Note the return type here. We can return our custom error type
 /// AppError as it implements ResponseError.
@@ -162,7 +258,6 @@ async fn persist(db: Data<Db>) -> Result<&'static str, AppError> {
     // Write a log message
     debug!("Persisting todos");
 
-    let todos = db.read().await;
-    todos.persist().await?;
+    db.persist().await?;
     Ok("")
 }
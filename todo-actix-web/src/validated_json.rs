@@ -0,0 +1,155 @@
+// Extractor for JSON bodies that have to pass validation
+//
+// Works like actix's Json extractor, but additionally runs the rules of the
+// validator crate (e.g. #[validate(length(min = 1))]). Handlers using it only
+// get valid data. Errors are sent as problem details (RFC 7807).
+
+use std::{fmt::Display, future::Future, ops::Deref, pin::Pin};
+
+use actix_web::{
+    dev::Payload,
+    error::JsonPayloadError,
+    http::{header, StatusCode},
+    web::JsonBody,
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use validator::{Validate, ValidationErrors};
+
+/// JSON body that has been deserialized and validated
+///
+/// Rejects bodies that cannot be deserialized with 400 (or 413/415, see
+/// JsonPayloadError) and bodies that violate validation rules with 422.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate + 'static,
+{
+    type Error = ValidatedJsonError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = JsonBody::<T>::new(req, payload, None, true);
+        Box::pin(async move {
+            let value = body.await.map_err(ValidatedJsonError::Malformed)?;
+            value.validate().map_err(ValidatedJsonError::Invalid)?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+/// Rejection of ValidatedJson
+#[derive(Debug)]
+pub enum ValidatedJsonError {
+    /// Body is not JSON or does not match the expected structure
+    Malformed(JsonPayloadError),
+    /// Body violates validation rules
+    Invalid(ValidationErrors),
+}
+
+impl Display for ValidatedJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidatedJsonError::Malformed(e) => write!(f, "{e}"),
+            ValidatedJsonError::Invalid(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Sends errors as problem details (application/problem+json)
+impl ResponseError for ValidatedJsonError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ValidatedJsonError::Malformed(e) => e.status_code(),
+            ValidatedJsonError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let title = match self {
+            ValidatedJsonError::Malformed(_) => "Invalid JSON in request body",
+            ValidatedJsonError::Invalid(_) => "Validation of request body failed",
+        };
+        let status = self.status_code();
+        HttpResponse::build(status)
+            .insert_header((header::CONTENT_TYPE, "application/problem+json"))
+            .json(json!({
+                "type": "about:blank",
+                "title": title,
+                "status": status.as_u16(),
+                "detail": self.to_string(),
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::header::ContentType, test, web, App};
+    use todo_logic::TodoItem;
+
+    async fn handler(todo: ValidatedJson<TodoItem>) -> HttpResponse {
+        HttpResponse::Ok().body(todo.into_inner().title)
+    }
+
+    async fn post(body: &'static str) -> (StatusCode, Option<String>, web::Bytes) {
+        let app = test::init_service(App::new().route("/", web::post().to(handler))).await;
+        let req = test::TestRequest::post()
+            .uri("/")
+            .insert_header(ContentType::json())
+            .set_payload(body)
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), content_type, test::read_body(response).await)
+    }
+
+    #[actix_web::test]
+    async fn valid_json() {
+        let (status, _, body) =
+            post(r#"{"title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Buy milk");
+    }
+
+    #[actix_web::test]
+    async fn invalid_entity() {
+        let (status, content_type, body) =
+            post(r#"{"title": "", "notes": "", "assigned_to": "Rainer", "completed": false}"#).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["status"], 422);
+        assert!(problem["detail"].as_str().unwrap().contains("title"));
+    }
+
+    #[actix_web::test]
+    async fn malformed_json() {
+        let (status, content_type, _) = post(r#"{"title": "Buy milk""#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+
+        let (status, _, _) = post(r#"{"title": "Buy milk"}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
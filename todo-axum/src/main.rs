@@ -1,22 +1,164 @@
+use app_config::AppConfig;
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path, Query, Request, State},
+    http::{request::Parts, StatusCode, Version},
+    middleware::{self, Next},
     response::{Html, IntoResponse, Response},
     routing::{delete, get, post},
     Json, Router,
 };
+use axum_server::{tls_rustls::RustlsConfig, Handle};
 use serde_json::json;
-use std::sync::Arc;
-use todo_logic::{Pagination, TodoItem, TodoStore, TodoStoreError, UpdateTodoItem};
-use tokio::{net::TcpListener, sync::RwLock};
+use std::{future::Future, io, path, pin::pin, sync::Arc, time::Duration};
+use tenant_repository::TenantTodoRepository;
+use todo_logic::{
+    DynTodoRepository, IdentifyableTodoItem, Pagination, ParseSortSpecError, SortSpec, TenantId, TenantTodoStore,
+    TodoFilter, TodoItem, TodoStoreError, UpdateTodoItem,
+};
+use tokio::{
+    net::TcpListener,
+    signal,
+    sync::{oneshot, RwLock},
+    time::{self, Instant},
+};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use validated_json::ValidatedJson;
+use webhook::{EventKind, WebhookDispatcher};
+
+mod tenant_repository;
+mod validated_json;
+mod webhook;
 
 /// Type for our shared state
 ///
 /// In our sample application, we store the todo list in memory. As the state is shared
 /// between concurrently running web requests, we need to make it thread-safe.
-type Db = Arc<RwLock<TodoStore>>;
+/// Every tenant has its own todo list.
+type Db = Arc<RwLock<TenantTodoStore>>;
+
+/// State shared by all handlers
+///
+/// Handlers extract the parts they need (e.g. State<Db>) thanks to FromRef.
+#[derive(Clone, Default)]
+struct AppState {
+    db: Db,
+    /// Informs external systems about changed todo items
+    webhook: WebhookDispatcher,
+    persist_dir: PersistDir,
+    /// Repository used for single todo items instead of the in-memory store (e.g. a mock in tests)
+    ///
+    /// Listing and persisting todo items always work on the in-memory store.
+    repository: Option<DynTodoRepository>,
+}
+
+/// Directory to which the todo items of all tenants are persisted
+#[derive(Clone)]
+struct PersistDir(Arc<path::Path>);
+
+impl Default for PersistDir {
+    fn default() -> Self {
+        PersistDir(Arc::from(path::Path::new(".")))
+    }
+}
+
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for WebhookDispatcher {
+    fn from_ref(state: &AppState) -> Self {
+        state.webhook.clone()
+    }
+}
+
+impl FromRef<AppState> for PersistDir {
+    fn from_ref(state: &AppState) -> Self {
+        state.persist_dir.clone()
+    }
+}
+
+/// Header containing the id of the tenant
+const TENANT_HEADER: &str = "x-tenant-id";
+
+/// Tenant used for requests without tenant header
+const DEFAULT_TENANT: &str = "default";
+
+/// Tenant whose todo items a request works on
+///
+/// Note how we implement our own extractor. In practice, the tenant would
+/// rather come from a claim of an authenticated user than from a plain header.
+struct Tenant(TenantId);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Tenant {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let id = match parts.headers.get(TENANT_HEADER) {
+            Some(value) => value.to_str().unwrap_or_default(),
+            None => DEFAULT_TENANT,
+        };
+        TenantId::new(id)
+            .map(Tenant)
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid tenant id"))
+    }
+}
+
+/// Repository with the todo items of the tenant of a request
+///
+/// Handlers of single todo items work with the TodoRepository trait instead of
+/// the in-memory store. With that, they can be tested with MockTodoRepository.
+struct Todos(DynTodoRepository);
+
+#[async_trait]
+impl FromRequestParts<AppState> for Todos {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Tenant(tenant) = Tenant::from_request_parts(parts, state).await?;
+        let repository = match &state.repository {
+            Some(repository) => repository.clone(),
+            None => Arc::new(TenantTodoRepository::new(
+                state.db.clone(),
+                state.persist_dir.clone(),
+                tenant,
+            )),
+        };
+        Ok(Todos(repository))
+    }
+}
+
+/// Header with which clients make retries of POST /todos safe
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Maximum length of an idempotency key
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Optional key identifying repeated requests (e.g. retries after network errors)
+///
+/// Clients typically send a random UUID per logical request.
+struct IdempotencyKey(Option<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IdempotencyKey {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(IdempotencyKey(None));
+        };
+        value
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+            .map(|key| IdempotencyKey(Some(key.to_string())))
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid idempotency key"))
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -29,28 +171,223 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Create shared data store
-    let db = Db::default();
+    // Load settings from defaults, app.toml, APP_* environment variables, and
+    // command-line args (e.g. --bind-address 127.0.0.1:4000)
+    let config = AppConfig::load_from_args().expect("configuration is valid");
+
+    // Create shared data store with the todo items persisted by a previous run
+    let db = Arc::new(RwLock::new(
+        TenantTodoStore::load(&config.persist_dir)
+            .await
+            .expect("can load persisted todo items"),
+    ));
+    let webhook = config
+        .webhook_url
+        .clone()
+        .map(WebhookDispatcher::spawn)
+        .unwrap_or_default();
+    let persist_dir = PersistDir(Arc::from(config.persist_dir.as_path()));
+    let mut app = app(AppState {
+        db: db.clone(),
+        webhook,
+        persist_dir: persist_dir.clone(),
+        repository: None,
+    });
+    if !config.http2 {
+        app = app.layer(middleware::from_fn(reject_http2));
+    }
+
+    // Save changed todo items periodically. After the server has shut down,
+    // the task saves a last time.
+    let (stop_autosave, autosave_stopped) = oneshot::channel::<()>();
+    let autosave = tokio::spawn(autosave(
+        db,
+        persist_dir,
+        Duration::from_secs(config.autosave_interval),
+        async {
+            autosave_stopped.await.ok();
+        },
+    ));
+
+    // Note that Axum has great examples for a log of practical scenarios,
+    // including graceful shutdown (https://github.com/tokio-rs/axum/tree/main/examples)
+    let tls_config = load_tls_config(config.tls_cert.as_deref(), config.tls_key.as_deref(), config.http2)
+        .await
+        .expect("can load TLS certificate and key");
+    match tls_config {
+        Some(tls_config) => {
+            // axum itself only serves plain HTTP. axum-server adds TLS based on rustls.
+            // Clients choose between HTTP/1.1 and HTTP/2 during the TLS handshake (ALPN).
+            tracing::debug!("listening on https://{}", config.bind_address);
+            let handle = Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown_signal().await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            axum_server::bind_rustls(config.bind_address, tls_config)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        },
+        None => {
+            let listener = TcpListener::bind(config.bind_address).await.unwrap();
+            tracing::debug!("listening on {}", listener.local_addr().unwrap());
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+                .unwrap();
+        },
+    }
+
+    stop_autosave.send(()).ok();
+    autosave.await.unwrap();
+}
+
+/// Completes when the process receives Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::debug!("signal received, starting graceful shutdown");
+}
+
+/// Saves changed todo items every `period` until `shutdown` completes
+///
+/// Saves a last time after shutdown so that no changes get lost. A period of
+/// zero disables periodic saving.
+async fn autosave(db: Db, dir: PersistDir, period: Duration, shutdown: impl Future<Output = ()>) {
+    let mut shutdown = pin!(shutdown);
+    if period.is_zero() {
+        shutdown.as_mut().await;
+    } else {
+        let mut interval = time::interval_at(Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => save_if_dirty(&db, &dir).await,
+                _ = shutdown.as_mut() => break,
+            }
+        }
+    }
+
+    save_if_dirty(&db, &dir).await;
+}
+
+/// Persists the todo items of all tenants if any of them changed since the last save
+async fn save_if_dirty(db: &Db, dir: &PersistDir) {
+    let todos = db.read().await;
+    if todos.is_dirty() {
+        if let Err(e) = todos.persist_to(&*dir.0).await {
+            tracing::error!("autosave failed: {e}");
+        }
+    }
+}
+
+/// Loads the TLS certificate chain and private key (PEM files) if HTTPS is configured
+///
+/// Returns None if neither a certificate nor a key is given. HTTP/2 is only offered
+/// to clients if `http2` is set.
+async fn load_tls_config(
+    cert: Option<&path::Path>,
+    key: Option<&path::Path>,
+    http2: bool,
+) -> io::Result<Option<RustlsConfig>> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => {
+            let config = RustlsConfig::from_pem_file(cert, key).await?;
+            if !http2 {
+                let mut server_config = (*config.get_inner()).clone();
+                server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+                config.reload_from_config(Arc::new(server_config));
+            }
+            Ok(Some(config))
+        },
+        (None, None) => Ok(None),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "TLS certificate and key have to be given together",
+        )),
+    }
+}
+
+/// Rejects HTTP/2 requests; used if HTTP/2 is disabled
+///
+/// Without TLS, hyper detects HTTP/2 clients (h2c) on its own and cannot be
+/// restricted to HTTP/1.1. Therefore, the protocol version is checked per request.
+async fn reject_http2(request: Request, next: Next) -> Response {
+    if request.version() == Version::HTTP_2 {
+        return StatusCode::HTTP_VERSION_NOT_SUPPORTED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Versions of the API
+///
+/// Clients select a version with the URI prefix (e.g. /v1/todos). To add a version,
+/// add a variant here and its routes in api_routes. Requests for versions that are
+/// not listed (e.g. /v2 until it is implemented) get 404.
+#[derive(Debug, Clone, Copy)]
+enum ApiVersion {
+    V1,
+}
+
+impl ApiVersion {
+    const ALL: [ApiVersion; 1] = [ApiVersion::V1];
+
+    fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+        }
+    }
+}
+
+/// Routes of a single API version (without version prefix)
+fn api_routes(version: ApiVersion) -> Router<AppState> {
+    match version {
+        // Here we setup the routes. Note: No macros
+        // A new version can start with the routes of the previous one and replace the changed ones.
+        ApiVersion::V1 => Router::new()
+            .route("/todos", get(get_todos).post(add_todo))
+            .route("/todos/:id", delete(delete_todo).patch(update_todo).get(get_todo))
+            .route("/todos/persist", post(persist)),
+    }
+}
+
+/// Builds the app with all routes and the given shared state
+fn app(state: AppState) -> Router {
+    let api = ApiVersion::ALL.into_iter().fold(Router::new(), |router, version| {
+        router.nest(version.prefix(), api_routes(version))
+    });
 
     // We register our shared state so that handlers can get it using the State extractor.
     // Note that this will change in Axum 0.6. See more at
     // https://docs.rs/axum/0.6.0-rc.4/axum/index.html#sharing-state-with-handlers
-    let app = Router::new()
-        // Here we setup the routes. Note: No macros
+    Router::new()
         .route("/", get(say_hello))
-        .route("/todos", get(get_todos).post(add_todo))
-        .route("/todos/:id", delete(delete_todo).patch(update_todo).get(get_todo))
-        .route("/todos/persist", post(persist))
-        .with_state(db)
+        .merge(api)
+        .with_state(state)
         // Using tower to add tracing layer
-        .layer(TraceLayer::new_for_http());
-
-    // In practice: Use graceful shutdown.
-    // Note that Axum has great examples for a log of practical scenarios,
-    // including graceful shutdown (https://github.com/tokio-rs/axum/tree/main/examples)
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+        .layer(TraceLayer::new_for_http())
 }
 
 /// Say hello
@@ -58,63 +395,111 @@ async fn say_hello() -> Html<&'static str> {
     Html("<h1>Hello, World!</h1>")
 }
 
+/// Sort order of todo listings as given in the query string
+#[derive(serde::Deserialize)]
+struct Sorting {
+    sort: Option<String>,
+}
+
 /// Get list of todo items
 ///
 /// Note how the Query extractor is used to get query parameters. Note how the State
 /// extractor is used to get the database (changes in Axum 0.6 RC).
 /// Extractors are technically types that implement FromRequest. You can create
 /// your own extractors or use the ones provided by Axum.
-async fn get_todos(pagination: Option<Query<Pagination>>, State(db): State<Db>) -> impl IntoResponse {
+///
+/// Todo items can be filtered (e.g. `?q=milk&assigned_to=rainer&completed=false`, see
+/// TodoFilter). Only todo items matching all given filters are returned.
+///
+/// `?sort=title` sorts by the given field, `?sort=-title` in descending order (see
+/// SortSpec). Unknown fields are rejected with 400.
+async fn get_todos(
+    pagination: Option<Query<Pagination>>,
+    Query(filter): Query<TodoFilter>,
+    Query(sorting): Query<Sorting>,
+    Tenant(tenant): Tenant,
+    State(db): State<Db>,
+) -> Result<Json<Vec<IdentifyableTodoItem>>, (StatusCode, String)> {
+    let sort = match sorting.sort {
+        Some(sort) => sort
+            .parse()
+            .map_err(|e: ParseSortSpecError| (StatusCode::BAD_REQUEST, e.to_string()))?,
+        None => SortSpec::default(),
+    };
     let todos = db.read().await;
     let Query(pagination) = pagination.unwrap_or_default();
+    let Some(todos) = todos.get(&tenant) else {
+        return Ok(Json(Vec::new()));
+    };
     // Json is an extractor and a response.
-    Json(todos.get_todos(pagination))
+    Ok(Json(todos.query(&filter, sort, pagination)))
 }
 
 /// Get a single todo item
 ///
 /// Note how the Path extractor is used to get query parameters.
-async fn get_todo(Path(id): Path<usize>, State(db): State<Db>) -> impl IntoResponse {
-    let todos = db.read().await;
-    if let Some(item) = todos.get_todo(id) {
+async fn get_todo(Path(id): Path<usize>, Todos(todos): Todos) -> Result<Response, AppError> {
+    Ok(match todos.get_todo(id).await? {
         // Note how to return Json
-        Json(item).into_response()
-    } else {
+        Some(item) => Json(item).into_response(),
         // Note how a tuple can be turned into a response
-        (StatusCode::NOT_FOUND, "Not found").into_response()
-    }
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    })
 }
 
 /// Add a new todo item
 ///
-/// Note that this time, Json is used as an extractor. This means that the request body
-/// will be deserialized into a TodoItem.
-async fn add_todo(State(db): State<Db>, Json(todo): Json<TodoItem>) -> impl IntoResponse {
-    let mut todos = db.write().await;
-    let todo = todos.add_todo(todo);
-    (StatusCode::CREATED, Json(todo))
+/// Note that this time, the request body is extracted. ValidatedJson deserializes it
+/// into a TodoItem (like axum's Json) and rejects invalid todo items with 422.
+///
+/// If the client sends an idempotency key, repeated requests with the same key return
+/// the todo item created by the first one instead of adding a duplicate.
+async fn add_todo(
+    Tenant(tenant): Tenant,
+    IdempotencyKey(key): IdempotencyKey,
+    Todos(todos): Todos,
+    State(webhook): State<WebhookDispatcher>,
+    ValidatedJson(todo): ValidatedJson<TodoItem>,
+) -> Result<impl IntoResponse, AppError> {
+    let (todo, created) = match key {
+        Some(key) => todos.add_todo_with_key(&key, todo).await?,
+        None => (todos.add_todo(todo).await?, true),
+    };
+    if created {
+        webhook.dispatch(EventKind::Created, tenant.as_str(), todo.clone());
+    }
+    Ok((StatusCode::CREATED, Json(todo)))
 }
 
 /// Delete a todo item
-async fn delete_todo(Path(id): Path<usize>, State(db): State<Db>) -> impl IntoResponse {
-    if db.write().await.remove_todo(id).is_some() {
-        StatusCode::NO_CONTENT
+async fn delete_todo(
+    Path(id): Path<usize>,
+    Tenant(tenant): Tenant,
+    Todos(todos): Todos,
+    State(webhook): State<WebhookDispatcher>,
+) -> Result<StatusCode, AppError> {
+    if let Some(todo) = todos.remove_todo(id).await? {
+        webhook.dispatch(EventKind::Deleted, tenant.as_str(), todo);
+        Ok(StatusCode::NO_CONTENT)
     } else {
-        StatusCode::NOT_FOUND
+        Ok(StatusCode::NOT_FOUND)
     }
 }
 
 /// Update a todo item
 async fn update_todo(
     Path(id): Path<usize>,
-    State(db): State<Db>,
-    Json(input): Json<UpdateTodoItem>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let mut todos = db.write().await;
-    let res = todos.update_todo(&id, input);
-    match res {
-        Some(todo) => Ok(Json(todo.clone())),
-        None => Err(StatusCode::NOT_FOUND),
+    Tenant(tenant): Tenant,
+    Todos(todos): Todos,
+    State(webhook): State<WebhookDispatcher>,
+    ValidatedJson(input): ValidatedJson<UpdateTodoItem>,
+) -> Result<Response, AppError> {
+    match todos.update_todo(id, input).await? {
+        Some(todo) => {
+            webhook.dispatch(EventKind::Updated, tenant.as_str(), todo.clone());
+            Ok(Json(todo).into_response())
+        },
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
     }
 }
 
@@ -141,6 +526,14 @@ impl IntoResponse for AppError {
             AppError::UserRepo(TodoStoreError::SerializationError(_)) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Error during serialization")
             },
+            AppError::UserRepo(TodoStoreError::RepositoryError(_)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error while accessing the repository",
+            ),
+            AppError::UserRepo(TodoStoreError::Conflict(_)) => {
+                (StatusCode::CONFLICT, "Conflict with an existing todo item")
+            },
+            AppError::UserRepo(TodoStoreError::ValidationError(_)) => (StatusCode::BAD_REQUEST, "Invalid todo item"),
         };
 
         let body = Json(json!({
@@ -152,9 +545,404 @@ impl IntoResponse for AppError {
 }
 
 /// Persist the todo store to disk
-async fn persist(State(db): State<Db>) -> Result<(), AppError> {
+///
+/// The todo items of every tenant are written to a separate file.
+async fn persist(State(db): State<Db>, State(dir): State<PersistDir>) -> Result<(), AppError> {
     tracing::debug!("Persisting todos");
     let todos = db.read().await;
-    todos.persist().await?;
+    todos.persist_to(&*dir.0).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::TestServer;
+    use todo_logic::MockTodoRepository;
+
+    #[tokio::test]
+    async fn todos_over_http() {
+        let server = TestServer::start_axum(app(AppState::default()));
+
+        let response = server
+            .post("/v1/todos")
+            .json(&json!({ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+        let todos: serde_json::Value = server.get("/v1/todos").send().await.unwrap().json().await.unwrap();
+        assert_eq!(todos[0]["title"], "Buy milk");
+
+        let response = server.delete("/v1/todos/0").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let response = server.get("/v1/todos/0").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn invalid_todos_are_rejected() {
+        let server = TestServer::start_axum(app(AppState::default()));
+        for title in [String::new(), "x".repeat(201)] {
+            let response = server
+                .post("/v1/todos")
+                .json(&json!({ "title": title, "notes": "", "assigned_to": "Rainer", "completed": false }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["fields"]["title"][0]["code"], "length");
+        }
+
+        server
+            .post("/v1/todos")
+            .json(&sample_todo("Buy milk"))
+            .send()
+            .await
+            .unwrap();
+        let response = server
+            .patch("/v1/todos/0")
+            .json(&json!({ "title": "" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+        let todos: serde_json::Value = server.get("/v1/todos").send().await.unwrap().json().await.unwrap();
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["title"], "Buy milk");
+    }
+
+    #[tokio::test]
+    async fn search_todos() {
+        let server = TestServer::start_axum(app(AppState::default()));
+        for title in ["Buy milk", "Learn Rust", "Buy MILK chocolate"] {
+            server.post("/v1/todos").json(&sample_todo(title)).send().await.unwrap();
+        }
+        let titles = |query: &'static str| {
+            let request = server.get("/v1/todos").query(&[("q", query)]).send();
+            async move {
+                let todos: Vec<TodoItem> = request.await.unwrap().json().await.unwrap();
+                todos.into_iter().map(|todo| todo.title).collect::<Vec<_>>()
+            }
+        };
+
+        let mut found = titles("Milk").await;
+        found.sort();
+        assert_eq!(found, ["Buy MILK chocolate", "Buy milk"]);
+        assert!(titles("Python").await.is_empty());
+        assert_eq!(titles("").await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn filter_todos_by_assignee() {
+        let server = TestServer::start_axum(app(AppState::default()));
+        for (title, assigned_to) in [("Buy milk", "Rainer"), ("Learn Rust", "Karin"), ("Buy bread", "rainer")] {
+            let todo = TodoItem {
+                assigned_to: assigned_to.to_string(),
+                ..sample_todo(title)
+            };
+            server.post("/v1/todos").json(&todo).send().await.unwrap();
+        }
+        server
+            .patch("/v1/todos/2")
+            .json(&json!({ "completed": true }))
+            .send()
+            .await
+            .unwrap();
+        let titles = |query: &'static [(&'static str, &'static str)]| {
+            let request = server.get("/v1/todos").query(query).send();
+            async move {
+                let todos: Vec<TodoItem> = request.await.unwrap().json().await.unwrap();
+                let mut titles = todos.into_iter().map(|todo| todo.title).collect::<Vec<_>>();
+                titles.sort();
+                titles
+            }
+        };
+
+        assert_eq!(titles(&[("assigned_to", "RAINER")]).await, ["Buy bread", "Buy milk"]);
+        assert_eq!(titles(&[("assigned_to", "Karin")]).await, ["Learn Rust"]);
+        assert!(titles(&[("assigned_to", "Nobody")]).await.is_empty());
+        assert_eq!(
+            titles(&[("assigned_to", "rainer"), ("completed", "false")]).await,
+            ["Buy milk"]
+        );
+        assert_eq!(
+            titles(&[("assigned_to", "rainer"), ("q", "bread")]).await,
+            ["Buy bread"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sort_todos() {
+        let server = TestServer::start_axum(app(AppState::default()));
+        for title in ["learn Rust", "Buy milk", "Clean up"] {
+            server.post("/v1/todos").json(&sample_todo(title)).send().await.unwrap();
+        }
+        let titles = |sort: &'static str| {
+            let request = server.get("/v1/todos").query(&[("sort", sort)]).send();
+            async move {
+                let todos: Vec<TodoItem> = request.await.unwrap().json().await.unwrap();
+                todos.into_iter().map(|todo| todo.title).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(titles("title").await, ["Buy milk", "Clean up", "learn Rust"]);
+        assert_eq!(titles("-title").await, ["learn Rust", "Clean up", "Buy milk"]);
+        assert_eq!(titles("-id").await, ["Clean up", "Buy milk", "learn Rust"]);
+
+        let response = server
+            .get("/v1/todos")
+            .query(&[("sort", "notes")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn idempotent_add_todo() {
+        let server = TestServer::start_axum(app(AppState::default()));
+        let add = |key: &'static str| {
+            server
+                .post("/v1/todos")
+                .header(IDEMPOTENCY_KEY_HEADER, key)
+                .json(&json!({ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }))
+                .send()
+        };
+        let id = |response: reqwest::Response| async move {
+            assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+            response.json::<serde_json::Value>().await.unwrap()["id"].clone()
+        };
+
+        let first = id(add("key-1").await.unwrap()).await;
+        assert_eq!(id(add("key-1").await.unwrap()).await, first);
+        assert_ne!(id(add("key-2").await.unwrap()).await, first);
+
+        let todos: serde_json::Value = server.get("/v1/todos").send().await.unwrap().json().await.unwrap();
+        assert_eq!(todos.as_array().unwrap().len(), 2);
+
+        let response = add("").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn api_versions() {
+        let server = TestServer::start_axum(app(AppState::default()));
+
+        let response = server.get("/v1/todos").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        // Not implemented yet
+        let response = server.get("/v2/todos").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        // Routes without version do not exist
+        let response = server.get("/todos").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn tls_config() {
+        let certs = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-certs");
+        let (cert, key) = (certs.join("localhost.crt"), certs.join("localhost.key"));
+        let config = load_tls_config(Some(&cert), Some(&key), true).await.unwrap().unwrap();
+        assert!(config.get_inner().alpn_protocols.contains(&b"h2".to_vec()));
+        let config = load_tls_config(Some(&cert), Some(&key), false).await.unwrap().unwrap();
+        assert_eq!(config.get_inner().alpn_protocols, [b"http/1.1".to_vec()]);
+
+        assert!(load_tls_config(None, None, true).await.unwrap().is_none());
+        assert!(load_tls_config(Some(&cert), None, true).await.is_err());
+        // Key and certificate swapped
+        assert!(load_tls_config(Some(&key), Some(&cert), true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn http2_cleartext() {
+        // Client starting with HTTP/2 right away (h2c with prior knowledge)
+        let client = reqwest::Client::builder().http2_prior_knowledge().build().unwrap();
+
+        let server = TestServer::start_axum(app(AppState::default()));
+        let response = client.get(server.url("/v1/todos")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+
+        let server = TestServer::start_axum(app(AppState::default()).layer(middleware::from_fn(reject_http2)));
+        let response = client.get(server.url("/v1/todos")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::HTTP_VERSION_NOT_SUPPORTED);
+        let response = server.get("/v1/todos").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.version(), reqwest::Version::HTTP_11);
+    }
+
+    fn sample_todo(title: &str) -> TodoItem {
+        TodoItem {
+            title: title.to_string(),
+            notes: String::new(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn autosave_persists_changes() {
+        const PERIOD: Duration = Duration::from_secs(30);
+        let tenant = TenantId::new("autosave-test").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join(TenantTodoStore::filename(&tenant));
+        let db = Db::default();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let task = tokio::spawn(autosave(db.clone(), PersistDir(Arc::from(dir.path())), PERIOD, async {
+            stopped.await.ok();
+        }));
+
+        // Changes are saved with the next tick
+        db.write().await.get_mut(&tenant).add_todo(sample_todo("Buy milk"));
+        time::sleep(PERIOD).await;
+        while db.read().await.is_dirty() {
+            tokio::task::yield_now().await;
+        }
+        assert!(filename.exists());
+
+        // Without changes, nothing is saved
+        std::fs::remove_file(&filename).unwrap();
+        time::sleep(PERIOD * 2).await;
+        assert!(!filename.exists());
+
+        // Changes since the last tick are saved when shutting down
+        db.write().await.get_mut(&tenant).add_todo(sample_todo("Learn Rust"));
+        stop.send(()).unwrap();
+        task.await.unwrap();
+        assert!(!db.read().await.is_dirty());
+        let saved = std::fs::read_to_string(&filename).unwrap();
+        assert!(saved.contains("Learn Rust"));
+    }
+
+    #[tokio::test]
+    async fn mutations_trigger_webhook() {
+        let webhook = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(204))
+            .expect(3)
+            .mount(&webhook)
+            .await;
+        let server = TestServer::start_axum(app(AppState {
+            webhook: WebhookDispatcher::spawn(webhook.uri()),
+            ..Default::default()
+        }));
+
+        server
+            .post("/v1/todos")
+            .json(&sample_todo("Buy milk"))
+            .send()
+            .await
+            .unwrap();
+        server
+            .patch("/v1/todos/0")
+            .json(&json!({ "completed": true }))
+            .send()
+            .await
+            .unwrap();
+        server.delete("/v1/todos/0").send().await.unwrap();
+        // Not found, no event
+        server.delete("/v1/todos/0").send().await.unwrap();
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            events = webhook.received_requests().await.unwrap();
+            if events.len() >= 3 {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        let events = events
+            .iter()
+            .map(|request| request.body_json::<serde_json::Value>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event["event"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            ["created", "updated", "deleted"]
+        );
+        assert_eq!(events[1]["todo"]["completed"], true);
+    }
+
+    #[tokio::test]
+    async fn tenants_are_isolated() {
+        let server = TestServer::start_axum(app(AppState::default()));
+        let todo = json!({ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false });
+        let response = server
+            .post("/v1/todos")
+            .header(TENANT_HEADER, "alice")
+            .json(&todo)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+        let todos: serde_json::Value = server
+            .get("/v1/todos")
+            .header(TENANT_HEADER, "bob")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(todos, json!([]));
+        let response = server
+            .delete("/v1/todos/0")
+            .header(TENANT_HEADER, "bob")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let response = server
+            .get("/v1/todos/0")
+            .header(TENANT_HEADER, "alice")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let response = server
+            .get("/v1/todos")
+            .header(TENANT_HEADER, "../bob")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    /// Example for testing handlers with a mocked TodoRepository
+    ///
+    /// The handlers of single todo items get the repository from the state, so tests can swap in a mock.
+    #[tokio::test]
+    async fn handler_with_mocked_repository() {
+        let mut repo = MockTodoRepository::new();
+        repo.expect_get_todo()
+            .withf(|id| *id == 42)
+            .times(1)
+            .returning(|id| Ok(Some(IdentifyableTodoItem::new(id, sample_todo("Buy milk")))));
+        repo.expect_get_todo().returning(|_| Ok(None));
+        repo.expect_add_todo()
+            .times(1)
+            .returning(|todo| Ok(IdentifyableTodoItem::new(1, todo)));
+        let server = TestServer::start_axum(app(AppState {
+            repository: Some(Arc::new(repo)),
+            ..Default::default()
+        }));
+
+        let todo: IdentifyableTodoItem = server.get("/v1/todos/42").send().await.unwrap().json().await.unwrap();
+        assert_eq!(todo.item.title, "Buy milk");
+        let response = server.get("/v1/todos/1").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let response = server.post("/v1/todos").json(&sample_todo("Learn Rust")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let todo: IdentifyableTodoItem = response.json().await.unwrap();
+        assert_eq!(todo.item.title, "Learn Rust");
+    }
+}
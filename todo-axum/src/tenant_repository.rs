@@ -0,0 +1,77 @@
+// Todo items of a single tenant as a TodoRepository
+//
+// The in-memory store keeps a separate list of todo items per tenant. Handlers
+// should not care about that. Therefore, they get a TodoRepository that is
+// scoped to the tenant of the request. The same handlers work with any other
+// implementation of the trait (e.g. MockTodoRepository in tests).
+
+use axum::async_trait;
+use todo_logic::{
+    IdentifyableTodoItem, Pagination, TenantId, TodoItem, TodoRepository, TodoStoreError, UpdateTodoItem,
+};
+
+use crate::{Db, PersistDir};
+
+/// Repository with the todo items of a tenant in the in-memory store
+pub struct TenantTodoRepository {
+    db: Db,
+    dir: PersistDir,
+    tenant: TenantId,
+}
+
+impl TenantTodoRepository {
+    pub fn new(db: Db, dir: PersistDir, tenant: TenantId) -> Self {
+        TenantTodoRepository { db, dir, tenant }
+    }
+}
+
+#[async_trait]
+impl TodoRepository for TenantTodoRepository {
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError> {
+        let todos = self.db.read().await;
+        Ok(todos
+            .get(&self.tenant)
+            .map(|todos| todos.get_todos(pagination))
+            .unwrap_or_default())
+    }
+
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let todos = self.db.read().await;
+        Ok(todos.get(&self.tenant).and_then(|todos| todos.get_todo(id)).cloned())
+    }
+
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError> {
+        Ok(self.db.write().await.get_mut(&self.tenant).add_todo(todo))
+    }
+
+    async fn add_todo_with_key(
+        &self,
+        key: &str,
+        todo: TodoItem,
+    ) -> Result<(IdentifyableTodoItem, bool), TodoStoreError> {
+        Ok(self.db.write().await.get_mut(&self.tenant).add_todo_with_key(key, todo))
+    }
+
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        Ok(self.db.write().await.get_mut(&self.tenant).remove_todo(id))
+    }
+
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        Ok(self
+            .db
+            .write()
+            .await
+            .get_mut(&self.tenant)
+            .update_todo(&id, todo)
+            .cloned())
+    }
+
+    /// Persists the todo items of all tenants (like the persist handler)
+    async fn persist(&self) -> Result<(), TodoStoreError> {
+        self.db.read().await.persist_to(&*self.dir.0).await
+    }
+}
@@ -0,0 +1,61 @@
+// Extractor for JSON bodies that have to pass validation
+//
+// Works like axum's Json extractor, but additionally runs the rules of the
+// validator crate (e.g. #[validate(length(min = 1, max = 200))]). Handlers using
+// it only get valid data.
+
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use validator::{Validate, ValidationErrors};
+
+/// JSON body that has been deserialized and validated
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ValidatedJsonRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(ValidatedJsonRejection::Malformed)?;
+        value.validate().map_err(ValidatedJsonRejection::Invalid)?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Rejection of ValidatedJson
+#[derive(Debug)]
+pub enum ValidatedJsonRejection {
+    /// Body is not JSON or does not match the expected structure; answered like axum's Json does
+    Malformed(JsonRejection),
+    /// Body violates validation rules; answered with 422 and the failed rules per field
+    Invalid(ValidationErrors),
+}
+
+impl IntoResponse for ValidatedJsonRejection {
+    fn into_response(self) -> Response {
+        match self {
+            ValidatedJsonRejection::Malformed(rejection) => rejection.into_response(),
+            ValidatedJsonRejection::Invalid(errors) => {
+                let body = Json(json!({
+                    "error": "Validation of request body failed",
+                    "fields": errors,
+                }));
+                (StatusCode::UNPROCESSABLE_ENTITY, body).into_response()
+            },
+        }
+    }
+}
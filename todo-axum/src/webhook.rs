@@ -0,0 +1,179 @@
+// Change data capture via webhooks
+//
+// Every change of a todo item is sent as a JSON event to a webhook URL (e.g. of
+// an external system that needs to know about changed todo items). Handlers only
+// put events into a bounded queue. A background task posts them to the webhook,
+// so slow or unavailable webhooks do not slow down the API. If the queue is full,
+// events are dropped.
+
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Serialize;
+use todo_logic::IdentifyableTodoItem;
+use tokio::sync::mpsc;
+
+/// Number of events that can wait for being sent
+pub const QUEUE_CAPACITY: usize = 100;
+
+/// Number of attempts for sending an event before it is dropped
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry; doubles with every further retry
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Kind of change of a todo item
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// Event sent to the webhook
+#[derive(Serialize, Debug, Clone)]
+pub struct WebhookEvent {
+    pub event: EventKind,
+    pub tenant: String,
+    pub todo: IdentifyableTodoItem,
+}
+
+/// Queues events for sending them to the webhook
+///
+/// The default dispatcher is disabled and drops all events.
+#[derive(Clone, Default)]
+pub struct WebhookDispatcher {
+    sender: Option<mpsc::Sender<WebhookEvent>>,
+}
+
+impl WebhookDispatcher {
+    /// Starts a background task sending events to the given URL; needs a Tokio runtime
+    pub fn spawn(url: String) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(send_events(Client::new(), url, receiver));
+        WebhookDispatcher { sender: Some(sender) }
+    }
+
+    /// Queues an event without waiting; drops it if the queue is full
+    pub fn dispatch(&self, event: EventKind, tenant: &str, todo: IdentifyableTodoItem) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let event = WebhookEvent {
+            event,
+            tenant: tenant.to_string(),
+            todo,
+        };
+        if let Err(e) = sender.try_send(event) {
+            tracing::warn!("dropping webhook event: {e}");
+        }
+    }
+}
+
+/// Sends queued events one after the other until all dispatchers are dropped
+async fn send_events(client: Client, url: String, mut receiver: mpsc::Receiver<WebhookEvent>) {
+    while let Some(event) = receiver.recv().await {
+        if let Err(e) = send_with_retries(&client, &url, &event).await {
+            tracing::error!("cannot send {:?} event for todo {}: {e}", event.event, event.todo.id);
+        }
+    }
+}
+
+/// Posts an event; retries on network errors and server errors (5xx)
+async fn send_with_retries(client: &Client, url: &str, event: &WebhookEvent) -> reqwest::Result<()> {
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let result = client.post(url).json(event).send().await;
+        match result.and_then(|response| response.error_for_status()) {
+            Ok(_) => return Ok(()),
+            // Client errors (4xx) do not go away by retrying
+            Err(e) if attempt == MAX_ATTEMPTS || e.status().is_some_and(|s| s.is_client_error()) => return Err(e),
+            Err(e) => tracing::warn!("webhook attempt {attempt} failed, retrying: {e}"),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use todo_logic::TodoItem;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn sample_todo() -> IdentifyableTodoItem {
+        IdentifyableTodoItem::new(
+            42,
+            TodoItem {
+                title: "Buy milk".to_string(),
+                notes: String::new(),
+                assigned_to: "Rainer".to_string(),
+                completed: false,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn webhook_receives_event() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(body_partial_json(json!({
+                "event": "created",
+                "tenant": "default",
+                "todo": { "id": 42, "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dispatcher = WebhookDispatcher::spawn(format!("{}/hook", server.uri()));
+        dispatcher.dispatch(EventKind::Created, "default", sample_todo());
+        wait_for_requests(&server, 1).await;
+    }
+
+    #[tokio::test]
+    async fn webhook_retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dispatcher = WebhookDispatcher::spawn(server.uri());
+        dispatcher.dispatch(EventKind::Deleted, "default", sample_todo());
+        wait_for_requests(&server, 2).await;
+    }
+
+    #[test]
+    fn disabled_dispatcher_drops_events() {
+        WebhookDispatcher::default().dispatch(EventKind::Updated, "default", sample_todo());
+    }
+
+    /// Waits until the mock server has received the given number of requests
+    async fn wait_for_requests(server: &MockServer, count: usize) {
+        for _ in 0..100 {
+            if server.received_requests().await.unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("webhook has not been called {count} times");
+    }
+}
@@ -0,0 +1,350 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use reqwest::{RequestBuilder, StatusCode};
+use todo_logic::{IdentifyableTodoItem, Pagination, TodoItem, UpdateTodoItem};
+
+/// Command-line client for the todo APIs
+///
+/// Works with all HTTP samples (axum, actix-web, Rocket, warp) as they share the
+/// same routes and the types from todo-logic. todo-axum serves its API under a
+/// version prefix, so use `--api-version v1` with it.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Cli {
+    /// Base URL of the todo API
+    #[arg(long, env = "TODO_BASE_URL", default_value = "http://localhost:3000")]
+    base_url: String,
+
+    /// Version prefix of the API routes (e.g. v1 for todo-axum); none by default
+    #[arg(long, env = "TODO_API_VERSION")]
+    api_version: Option<String>,
+
+    /// API key that is sent in the X-API-Key header
+    #[arg(long, env = "TODO_API_KEY")]
+    api_key: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List todo items
+    List {
+        #[arg(long)]
+        offset: Option<usize>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Add a new todo item
+    Add {
+        title: String,
+        #[arg(long, default_value = "")]
+        notes: String,
+        #[arg(long, default_value = "")]
+        assigned_to: String,
+    },
+    /// Get a single todo item
+    Get { id: usize },
+    /// Update the given fields of a todo item
+    Update {
+        id: usize,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        notes: Option<String>,
+        #[arg(long)]
+        assigned_to: Option<String>,
+        #[arg(long)]
+        completed: Option<bool>,
+    },
+    /// Delete a todo item
+    Delete { id: usize },
+    /// Store the todo items on the server's disk
+    Persist,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    run(cli, &mut std::io::stdout()).await
+}
+
+/// Executes a command and prints its result
+async fn run(cli: Cli, out: &mut impl Write) -> Result<()> {
+    let base_url = match cli.api_version {
+        Some(version) => format!("{}/{}", cli.base_url.trim_end_matches('/'), version),
+        None => cli.base_url,
+    };
+    let client = TodoClient::new(base_url, cli.api_key);
+    match cli.command {
+        Command::List { offset, limit } => {
+            let todos = client.list(Pagination::new(offset, limit)).await?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&todos)?)?;
+        },
+        Command::Add {
+            title,
+            notes,
+            assigned_to,
+        } => {
+            let todo = TodoItem {
+                title,
+                notes,
+                assigned_to,
+                completed: false,
+            };
+            let todo = client.add(&todo).await?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&todo)?)?;
+        },
+        Command::Get { id } => {
+            let todo = client.get(id).await?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&todo)?)?;
+        },
+        Command::Update {
+            id,
+            title,
+            notes,
+            assigned_to,
+            completed,
+        } => {
+            let update = UpdateTodoItem {
+                title,
+                notes,
+                assigned_to,
+                completed,
+            };
+            let todo = client.update(id, &update).await?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&todo)?)?;
+        },
+        Command::Delete { id } => {
+            client.delete(id).await?;
+            writeln!(out, "Deleted todo {id}")?;
+        },
+        Command::Persist => {
+            client.persist().await?;
+            writeln!(out, "Persisted todos")?;
+        },
+    }
+
+    Ok(())
+}
+
+/// Client for the todo API
+struct TodoClient {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl TodoClient {
+    fn new(base_url: String, api_key: Option<String>) -> Self {
+        TodoClient {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    /// Creates a request and adds the API key (if any)
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(api_key) => request.header("X-API-Key", api_key),
+            None => request,
+        }
+    }
+
+    async fn list(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>> {
+        let response = self
+            .request(reqwest::Method::GET, "/todos")
+            .query(&pagination)
+            .send()
+            .await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn add(&self, todo: &TodoItem) -> Result<IdentifyableTodoItem> {
+        let response = self.request(reqwest::Method::POST, "/todos").json(todo).send().await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn get(&self, id: usize) -> Result<IdentifyableTodoItem> {
+        let response = self
+            .request(reqwest::Method::GET, &format!("/todos/{id}"))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            bail!("todo {id} not found");
+        }
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn update(&self, id: usize, update: &UpdateTodoItem) -> Result<IdentifyableTodoItem> {
+        let response = self
+            .request(reqwest::Method::PATCH, &format!("/todos/{id}"))
+            .json(update)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            bail!("todo {id} not found");
+        }
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn delete(&self, id: usize) -> Result<()> {
+        let response = self
+            .request(reqwest::Method::DELETE, &format!("/todos/{id}"))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            bail!("todo {id} not found");
+        }
+
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    async fn persist(&self) -> Result<()> {
+        let response = self.request(reqwest::Method::POST, "/todos/persist").send().await?;
+        response.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_json, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    /// Runs the CLI with the given arguments against the mock server and returns the output
+    async fn run_cli(server: &MockServer, args: &[&str]) -> Result<String> {
+        let uri = server.uri();
+        let mut all_args = vec!["todo-cli", "--base-url", &uri, "--api-key", "secret"];
+        all_args.extend(args);
+
+        let mut out = Vec::new();
+        run(Cli::parse_from(all_args), &mut out).await?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn sample_todo() -> serde_json::Value {
+        json!({
+            "id": 0, "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false,
+            "createdAt": "2024-01-02T03:04:05Z", "updatedAt": "2024-01-02T03:04:05Z"
+        })
+    }
+
+    #[tokio::test]
+    async fn list() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/todos"))
+            .and(query_param("limit", "1"))
+            .and(header("X-API-Key", "secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([sample_todo()])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = run_cli(&server, &["list", "--limit", "1"]).await.unwrap();
+        let todos: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(todos, json!([sample_todo()]));
+    }
+
+    #[tokio::test]
+    async fn add() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/todos"))
+            .and(body_json(
+                json!({ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }),
+            ))
+            .respond_with(ResponseTemplate::new(201).set_body_json(sample_todo()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = run_cli(&server, &["add", "Buy milk", "--assigned-to", "Rainer"])
+            .await
+            .unwrap();
+        assert!(output.contains(r#""title": "Buy milk""#));
+    }
+
+    #[tokio::test]
+    async fn update() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/todos/0"))
+            .and(body_json(
+                json!({ "title": null, "notes": null, "assigned_to": null, "completed": true }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(sample_todo()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        run_cli(&server, &["update", "0", "--completed", "true"]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/todos/0"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = run_cli(&server, &["delete", "0"]).await.unwrap();
+        assert_eq!(output, "Deleted todo 0\n");
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let server = MockServer::start().await;
+        Mock::given(path("/todos/42"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        for args in [&["get", "42"][..], &["delete", "42"], &["update", "42", "--title", "x"]] {
+            let error = run_cli(&server, args).await.unwrap_err();
+            assert_eq!(error.to_string(), "todo 42 not found");
+        }
+    }
+
+    #[tokio::test]
+    async fn api_version() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/todos/0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(sample_todo()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        run_cli(&server, &["--api-version", "v1", "get", "0"]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn persist_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/todos/persist"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(run_cli(&server, &["persist"]).await.is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use async_graphql::{http::GraphiQLSource, Context, EmptySubscription, Object, Result, Schema};
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+use todo_logic::{IdentifyableTodoItem, Pagination, TodoItem, TodoStore, UpdateTodoItem};
+use tokio::{net::TcpListener, sync::RwLock};
+use validator::Validate;
+
+/// Type for our shared state
+///
+/// Like in the REST samples, the todo list is stored in memory and shared
+/// between concurrently running requests.
+type Db = Arc<RwLock<TodoStore>>;
+
+/// The GraphQL schema; there are no subscriptions in this sample
+type TodoSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+#[tokio::main]
+async fn main() {
+    let app = Router::new()
+        // GraphQL needs a single endpoint only. GET serves the GraphiQL IDE for trying queries.
+        .route("/graphql", get(graphiql).post(graphql))
+        .with_state(build_schema(Db::default()));
+
+    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    println!("GraphiQL: http://localhost:3000/graphql");
+    axum::serve(listener, app).await.unwrap();
+}
+
+/// Builds the schema; the todo store is available to resolvers as context data
+fn build_schema(db: Db) -> TodoSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(db)
+        .finish()
+}
+
+async fn graphql(State(schema): State<TodoSchema>, Json(request): Json<async_graphql::Request>) -> impl IntoResponse {
+    Json(schema.execute(request).await)
+}
+
+async fn graphiql() -> impl IntoResponse {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Get list of todo items
+    ///
+    /// Note that the types of todo-logic are used as GraphQL objects (see feature
+    /// graphql in todo-logic).
+    async fn todos(&self, ctx: &Context<'_>, offset: Option<usize>, limit: Option<usize>) -> Vec<IdentifyableTodoItem> {
+        let db = ctx.data_unchecked::<Db>();
+        db.read().await.get_todos(Pagination::new(offset, limit))
+    }
+
+    /// Get a single todo item; null if it does not exist
+    async fn todo(&self, ctx: &Context<'_>, id: usize) -> Option<IdentifyableTodoItem> {
+        let db = ctx.data_unchecked::<Db>();
+        db.read().await.get_todo(id).cloned()
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Add a new todo item
+    async fn add_todo(&self, ctx: &Context<'_>, todo: TodoItem) -> Result<IdentifyableTodoItem> {
+        // Validation errors are returned in the errors list of the GraphQL response
+        todo.validate()?;
+        let db = ctx.data_unchecked::<Db>();
+        Ok(db.write().await.add_todo(todo))
+    }
+
+    /// Patch a todo item; null if it does not exist
+    async fn update_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>> {
+        todo.validate()?;
+        let db = ctx.data_unchecked::<Db>();
+        Ok(db.write().await.update_todo(&id, todo).cloned())
+    }
+
+    /// Delete a todo item; false if it does not exist
+    async fn delete_todo(&self, ctx: &Context<'_>, id: usize) -> bool {
+        let db = ctx.data_unchecked::<Db>();
+        db.write().await.remove_todo(id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{Request, Variables};
+    use serde_json::json;
+
+    /// Executes a query and returns its data; fails if there are errors
+    async fn execute(schema: &TodoSchema, request: impl Into<Request>) -> serde_json::Value {
+        let response = schema.execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    async fn add_sample_todos(schema: &TodoSchema) {
+        for title in ["Buy milk", "Learn Rust"] {
+            let todo = json!({ "title": title, "notes": "", "assignedTo": "Rainer", "completed": false });
+            let request = Request::new("mutation AddTodo($todo: TodoItemInput!) { addTodo(todo: $todo) { id } }")
+                .variables(Variables::from_json(json!({ "todo": todo })));
+            execute(schema, request).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn query_todos() {
+        let schema = build_schema(Db::default());
+        add_sample_todos(&schema).await;
+
+        let data = execute(&schema, "{ todos { id title } }").await;
+        assert_eq!(data["todos"].as_array().unwrap().len(), 2);
+
+        let data = execute(&schema, "{ todos(offset: 1, limit: 5) { id } }").await;
+        assert_eq!(data["todos"].as_array().unwrap().len(), 1);
+
+        let data = execute(&schema, "{ todo(id: 0) { id title assignedTo completed } }").await;
+        assert_eq!(
+            data["todo"],
+            json!({ "id": 0, "title": "Buy milk", "assignedTo": "Rainer", "completed": false })
+        );
+
+        let data = execute(&schema, "{ todo(id: 42) { id } }").await;
+        assert!(data["todo"].is_null());
+    }
+
+    #[tokio::test]
+    async fn update_and_delete_todo() {
+        let schema = build_schema(Db::default());
+        add_sample_todos(&schema).await;
+
+        let data = execute(
+            &schema,
+            "mutation { updateTodo(id: 0, todo: { completed: true }) { title completed } }",
+        )
+        .await;
+        assert_eq!(data["updateTodo"], json!({ "title": "Buy milk", "completed": true }));
+
+        let data = execute(&schema, "mutation { deleteTodo(id: 0) }").await;
+        assert_eq!(data["deleteTodo"], true);
+        let data = execute(&schema, "mutation { deleteTodo(id: 0) }").await;
+        assert_eq!(data["deleteTodo"], false);
+    }
+
+    #[tokio::test]
+    async fn add_invalid_todo() {
+        let schema = build_schema(Db::default());
+        let response = schema
+            .execute(r#"mutation { addTodo(todo: { title: "", notes: "", assignedTo: "", completed: false }) { id } }"#)
+            .await;
+        assert_eq!(response.errors.len(), 1);
+
+        let data = execute(&schema, "{ todos { id } }").await;
+        assert_eq!(data["todos"].as_array().unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,252 @@
+use std::sync::Arc;
+
+use todo_logic::{Pagination, TodoStore, TodoStoreError};
+use tokio::sync::RwLock;
+use tonic::{transport::Server, Request, Response, Status};
+use validator::Validate;
+
+// The code generated from proto/todo.proto (see build.rs). Contains the message
+// types as well as the server and client for TodoService.
+pub mod proto {
+    tonic::include_proto!("todo");
+}
+
+use proto::{
+    todo_service_server::{TodoService, TodoServiceServer},
+    Empty, Todo, TodoId, TodoList,
+};
+
+/// Type for our shared state
+///
+/// Like in the HTTP samples, the todo list is stored in memory and shared
+/// between concurrently running requests.
+type Db = Arc<RwLock<TodoStore>>;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = "0.0.0.0:50051".parse()?;
+    println!("listening on {addr}");
+
+    // Note that there are no routes. Tonic dispatches the RPCs defined in the
+    // proto file to the methods of our service.
+    Server::builder()
+        .add_service(TodoServiceServer::new(TodoServiceImpl::default()))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+/// Implementation of the gRPC service on top of the todo logic
+#[derive(Default)]
+pub struct TodoServiceImpl {
+    db: Db,
+}
+
+// Conversions between the generated protobuf types and the types of the todo logic
+
+impl From<proto::Pagination> for Pagination {
+    fn from(pagination: proto::Pagination) -> Self {
+        Pagination::new(
+            pagination.offset.map(|offset| offset as usize),
+            pagination.limit.map(|limit| limit as usize),
+        )
+    }
+}
+
+impl From<proto::TodoItem> for todo_logic::TodoItem {
+    fn from(item: proto::TodoItem) -> Self {
+        todo_logic::TodoItem {
+            title: item.title,
+            notes: item.notes,
+            assigned_to: item.assigned_to,
+            completed: item.completed,
+        }
+    }
+}
+
+impl From<proto::UpdateTodoItem> for todo_logic::UpdateTodoItem {
+    fn from(item: proto::UpdateTodoItem) -> Self {
+        todo_logic::UpdateTodoItem {
+            title: item.title,
+            notes: item.notes,
+            assigned_to: item.assigned_to,
+            completed: item.completed,
+        }
+    }
+}
+
+impl From<todo_logic::IdentifyableTodoItem> for Todo {
+    fn from(todo: todo_logic::IdentifyableTodoItem) -> Self {
+        Todo {
+            id: todo.id as u64,
+            item: Some(proto::TodoItem {
+                title: todo.item.title,
+                notes: todo.item.notes,
+                assigned_to: todo.item.assigned_to,
+                completed: todo.item.completed,
+            }),
+        }
+    }
+}
+
+/// Turns errors of the todo store into gRPC status codes
+fn to_status(e: TodoStoreError) -> Status {
+    match e {
+        TodoStoreError::FileAccessError(_) => Status::unavailable("error while writing to file"),
+        TodoStoreError::SerializationError(_) => Status::internal("error during serialization"),
+        TodoStoreError::RepositoryError(_) => Status::unavailable("error while accessing the repository"),
+        TodoStoreError::Conflict(detail) => Status::already_exists(detail),
+        TodoStoreError::ValidationError(detail) => Status::invalid_argument(detail),
+    }
+}
+
+fn not_found(id: u64) -> Status {
+    Status::not_found(format!("todo {id} not found"))
+}
+
+#[tonic::async_trait]
+impl TodoService for TodoServiceImpl {
+    async fn get_todos(&self, request: Request<proto::Pagination>) -> Result<Response<TodoList>, Status> {
+        let todos = self.db.read().await.get_todos(request.into_inner().into());
+        Ok(Response::new(TodoList {
+            todos: todos.into_iter().map(Todo::from).collect(),
+        }))
+    }
+
+    async fn get_todo(&self, request: Request<TodoId>) -> Result<Response<Todo>, Status> {
+        let id = request.into_inner().id;
+        let todos = self.db.read().await;
+        let todo = todos.get_todo(id as usize).ok_or_else(|| not_found(id))?;
+        Ok(Response::new(todo.clone().into()))
+    }
+
+    async fn add_todo(&self, request: Request<proto::TodoItem>) -> Result<Response<Todo>, Status> {
+        // Invalid messages are rejected like invalid JSON bodies in the HTTP samples
+        let todo = todo_logic::TodoItem::from(request.into_inner());
+        todo.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let todo = self.db.write().await.add_todo(todo);
+        Ok(Response::new(todo.into()))
+    }
+
+    async fn update_todo(&self, request: Request<proto::UpdateTodoItem>) -> Result<Response<Todo>, Status> {
+        let update = request.into_inner();
+        let id = update.id;
+        let update = todo_logic::UpdateTodoItem::from(update);
+        update.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let mut todos = self.db.write().await;
+        let todo = todos.update_todo(&(id as usize), update).ok_or_else(|| not_found(id))?;
+        Ok(Response::new(todo.clone().into()))
+    }
+
+    async fn delete_todo(&self, request: Request<TodoId>) -> Result<Response<Empty>, Status> {
+        let id = request.into_inner().id;
+        self.db
+            .write()
+            .await
+            .remove_todo(id as usize)
+            .ok_or_else(|| not_found(id))?;
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn persist(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.db.read().await.persist().await.map_err(to_status)?;
+        Ok(Response::new(Empty {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::todo_service_client::TodoServiceClient;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{transport::Channel, Code};
+
+    /// Starts the service on an ephemeral port and returns a client connected to it
+    async fn client() -> TodoServiceClient<Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(TodoServiceServer::new(TodoServiceImpl::default()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        TodoServiceClient::connect(format!("http://{addr}")).await.unwrap()
+    }
+
+    fn sample_todo(title: &str) -> proto::TodoItem {
+        proto::TodoItem {
+            title: title.to_string(),
+            notes: String::new(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn add_and_get_todos() {
+        let mut client = client().await;
+        let todo = client.add_todo(sample_todo("Buy milk")).await.unwrap().into_inner();
+        client.add_todo(sample_todo("Learn Rust")).await.unwrap();
+
+        let fetched = client.get_todo(TodoId { id: todo.id }).await.unwrap().into_inner();
+        assert_eq!(fetched, todo);
+
+        let pagination = proto::Pagination {
+            offset: None,
+            limit: Some(1),
+        };
+        let todos = client.get_todos(pagination).await.unwrap().into_inner();
+        assert_eq!(todos.todos.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn update_and_delete_todo() {
+        let mut client = client().await;
+        let todo = client.add_todo(sample_todo("Buy milk")).await.unwrap().into_inner();
+
+        let update = proto::UpdateTodoItem {
+            id: todo.id,
+            completed: Some(true),
+            ..Default::default()
+        };
+        let updated = client.update_todo(update).await.unwrap().into_inner();
+        let item = updated.item.unwrap();
+        assert!(item.completed);
+        assert_eq!(item.title, "Buy milk");
+
+        client.delete_todo(TodoId { id: todo.id }).await.unwrap();
+        let status = client.get_todo(TodoId { id: todo.id }).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn invalid_and_missing_todos() {
+        let mut client = client().await;
+        let status = client.add_todo(sample_todo("")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let update = proto::UpdateTodoItem {
+            id: 42,
+            completed: Some(true),
+            ..Default::default()
+        };
+        let status = client.update_todo(update).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+        let status = client.delete_todo(TodoId { id: 42 }).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[test]
+    fn store_errors_to_status() {
+        let e = TodoStoreError::FileAccessError(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert_eq!(to_status(e).code(), Code::Unavailable);
+
+        let e = TodoStoreError::SerializationError(serde_json::from_str::<u32>("x").unwrap_err());
+        assert_eq!(to_status(e).code(), Code::Internal);
+
+        let e = TodoStoreError::ValidationError("check constraint title_length_check violated".to_string());
+        assert_eq!(to_status(e).code(), Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,83 @@
+// Benchmarks for the in-memory todo store
+//
+// Run them with `cargo bench -p todo-logic --bench todo_store` (or `just bench`).
+// Criterion writes HTML reports to target/criterion and compares every run with the
+// previous one. Append a filter to run only some benchmarks (e.g. `-- search`).
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use todo_logic::{Pagination, TodoItem, TodoStore};
+
+/// Store sizes the benchmarks are run with
+const SIZES: [usize; 2] = [1_000, 100_000];
+
+/// Creates a todo item; every tenth one matches the search benchmark
+fn todo(i: usize) -> TodoItem {
+    TodoItem {
+        title: format!("Todo {i}"),
+        notes: if i.is_multiple_of(10) {
+            "Buy milk".to_string()
+        } else {
+            String::new()
+        },
+        assigned_to: "Rainer".to_string(),
+        completed: false,
+    }
+}
+
+fn store(size: usize) -> TodoStore {
+    let mut store = TodoStore::default();
+    for i in 0..size {
+        store.add_todo(todo(i));
+    }
+
+    store
+}
+
+fn add_todo(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_todo");
+    for size in SIZES {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched_ref(
+                || store(size),
+                |store| store.add_todo(todo(size)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn get_todos(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_todos");
+    for size in SIZES {
+        let store = store(size);
+        // Pages at the start, in the middle, and at the end of the store
+        for offset in [0, size / 2, size - 10] {
+            group.bench_with_input(BenchmarkId::new(size.to_string(), offset), &offset, |b, &offset| {
+                b.iter(|| store.get_todos(black_box(Pagination::new(Some(offset), Some(10)))))
+            });
+        }
+
+        group.bench_function(BenchmarkId::new(size.to_string(), "all"), |b| {
+            b.iter(|| store.get_todos(black_box(Pagination::default())))
+        });
+    }
+
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    for size in SIZES {
+        let store = store(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &store, |b, store| {
+            b.iter(|| store.search(black_box("milk"), Pagination::new(None, Some(10))))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, add_todo, get_todos, search);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS todos (
+    id bigserial PRIMARY KEY,
+    title text NOT NULL,
+    notes text NOT NULL DEFAULT '',
+    assigned_to text NOT NULL DEFAULT '',
+    completed boolean NOT NULL DEFAULT false
+);
+
+-- Same rule as the validation of TodoItem
+ALTER TABLE todos ADD CONSTRAINT title_length_check CHECK (length(title) >= 1);
@@ -0,0 +1,3 @@
+ALTER TABLE todos
+    DROP COLUMN IF EXISTS created_at,
+    DROP COLUMN IF EXISTS updated_at;
@@ -0,0 +1,4 @@
+ALTER TABLE todos
+    DROP CONSTRAINT IF EXISTS notes_length_check,
+    DROP CONSTRAINT IF EXISTS title_length_check,
+    ADD CONSTRAINT title_length_check CHECK (length(title) >= 1);
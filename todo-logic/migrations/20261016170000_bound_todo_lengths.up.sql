@@ -0,0 +1,5 @@
+-- Upper bounds of the validation of TodoItem (title 1 to 200 characters, notes at most 2000)
+ALTER TABLE todos
+    DROP CONSTRAINT title_length_check,
+    ADD CONSTRAINT title_length_check CHECK (length(title) BETWEEN 1 AND 200),
+    ADD CONSTRAINT notes_length_check CHECK (length(notes) <= 2000);
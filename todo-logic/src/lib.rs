@@ -1,58 +1,34 @@
-use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    sync::atomic::{AtomicUsize, Ordering},
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
-#[cfg(feature = "persist")]
+use chrono::Utc;
+#[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+use std::path::Path;
+#[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
 use tokio::fs;
 
-/// Represents a single todo item
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct TodoItem {
-    pub title: String,
-    pub notes: String,
-    pub assigned_to: String,
-    pub completed: bool,
-}
-
-/// DTO for patching a todo item
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct UpdateTodoItem {
-    pub title: Option<String>,
-    pub notes: Option<String>,
-    pub assigned_to: Option<String>,
-    pub completed: Option<bool>,
-}
-
-/// Represents a todo item with an id
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct IdentifyableTodoItem {
-    pub id: usize,
-
-    #[serde(flatten)]
-    pub item: TodoItem,
-}
-
-impl IdentifyableTodoItem {
-    pub fn new(id: usize, item: TodoItem) -> IdentifyableTodoItem {
-        IdentifyableTodoItem { id, item }
-    }
-}
+#[cfg(feature = "postgres")]
+pub mod postgres_repository;
+#[cfg(feature = "redis")]
+pub mod redis_repository;
+mod repository;
+#[cfg(feature = "sled")]
+pub mod sled_repository;
+mod sort;
+mod tenant;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-/// Parameters for pagination
-///
-/// Used to demonstrate handling of query parameters.
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct Pagination {
-    pub offset: Option<usize>,
-    pub limit: Option<usize>,
-}
-impl Pagination {
-    pub fn new(offset: Option<usize>, limit: Option<usize>) -> Pagination {
-        Pagination { offset, limit }
-    }
-}
+#[cfg(any(test, feature = "mock"))]
+pub use repository::MockTodoRepository;
+// The todo types are shared with the samples (see dto crate)
+pub use dto::{IdentifyableTodoItem, Pagination, TodoFilter, TodoItem, UpdateTodoItem};
+pub use repository::{DynTodoRepository, TodoRepository};
+pub use sort::{ParseSortSpecError, SortField, SortSpec};
+pub use tenant::{TenantId, TenantTodoStore};
 
 /// Error type for the todo items store
 #[derive(thiserror::Error, Debug)]
@@ -61,33 +37,53 @@ pub enum TodoStoreError {
     FileAccessError(#[from] std::io::Error),
     #[error("serialization error")]
     SerializationError(#[from] serde_json::error::Error),
+    #[error("repository error")]
+    RepositoryError(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("invalid todo item: {0}")]
+    ValidationError(String),
 }
 
+/// Time for which idempotency keys are remembered (see TodoStore::add_todo_with_key)
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// Todo items store
 #[derive(Default)]
 pub struct TodoStore {
-    store: HashMap<usize, IdentifyableTodoItem>,
+    /// Todo items by id
+    ///
+    /// Sorted so that lists (and therefore pages) always have the same order.
+    store: BTreeMap<usize, IdentifyableTodoItem>,
     id_generator: AtomicUsize,
+    /// Set if todo items changed since they have been persisted the last time
+    dirty: AtomicBool,
+    /// Todo items created with idempotency keys and the time they have been created
+    ///
+    /// Not persisted; keys are forgotten when the process restarts.
+    idempotency_keys: HashMap<String, (Instant, IdentifyableTodoItem)>,
 }
 impl TodoStore {
+    /// File used by persist
+    pub const DEFAULT_FILENAME: &'static str = "todo_store.json";
+
     pub fn from_hashmap(store: HashMap<usize, IdentifyableTodoItem>) -> Self {
-        let id_generator = AtomicUsize::new(
-            store
-                .keys()
-                .max()
-                .map(|v| v + 1)
-                .unwrap_or(0),
-        );
+        let store = BTreeMap::from_iter(store);
+        let id_generator = AtomicUsize::new(store.keys().next_back().map(|v| v + 1).unwrap_or(0));
         TodoStore {
             store,
             id_generator,
+            dirty: AtomicBool::new(false),
+            idempotency_keys: HashMap::new(),
         }
     }
 
     /// Get list of todo items
     ///
-    /// Supports pagination.
+    /// Todo items are sorted by id. Supports pagination. Pages contain at most
+    /// Pagination::DEFAULT_MAX_LIMIT items.
     pub fn get_todos(&self, pagination: Pagination) -> Vec<IdentifyableTodoItem> {
+        let pagination = pagination.clamp(Pagination::DEFAULT_MAX_LIMIT);
         self.store
             .values()
             .skip(pagination.offset.unwrap_or(0))
@@ -96,64 +92,546 @@ impl TodoStore {
             .collect::<Vec<_>>()
     }
 
+    /// Search todo items whose title or notes contain the given text
+    ///
+    /// The comparison is case-insensitive. Supports pagination like get_todos.
+    pub fn search(&self, query: &str, pagination: Pagination) -> Vec<IdentifyableTodoItem> {
+        let filter = TodoFilter {
+            q: Some(query.to_string()),
+            ..Default::default()
+        };
+        self.get_filtered(&filter, pagination)
+    }
+
+    /// Get todo items assigned to the given person
+    ///
+    /// The comparison is case-insensitive. Supports pagination like get_todos.
+    pub fn get_by_assignee(&self, who: &str, pagination: Pagination) -> Vec<IdentifyableTodoItem> {
+        let filter = TodoFilter {
+            assigned_to: Some(who.to_string()),
+            ..Default::default()
+        };
+        self.get_filtered(&filter, pagination)
+    }
+
+    /// Get todo items matching all given filters
+    ///
+    /// Supports pagination like get_todos. Pagination applies to the matching todo items.
+    pub fn get_filtered(&self, filter: &TodoFilter, pagination: Pagination) -> Vec<IdentifyableTodoItem> {
+        self.query(filter, SortSpec::default(), pagination)
+    }
+
+    /// Get list of todo items in the given order
+    ///
+    /// Supports pagination like get_todos.
+    pub fn get_sorted(&self, sort: SortSpec, pagination: Pagination) -> Vec<IdentifyableTodoItem> {
+        self.query(&TodoFilter::default(), sort, pagination)
+    }
+
+    /// Get todo items matching all given filters in the given order
+    ///
+    /// Supports pagination like get_todos. Pagination applies to the sorted, matching todo items.
+    pub fn query(&self, filter: &TodoFilter, sort: SortSpec, pagination: Pagination) -> Vec<IdentifyableTodoItem> {
+        let pagination = pagination.clamp(Pagination::DEFAULT_MAX_LIMIT);
+        // Lowercase once instead of for every todo item
+        let query = filter.q.as_deref().map(str::to_lowercase);
+        let assignee = filter.assigned_to.as_deref().map(str::to_lowercase);
+        let mut todos = self
+            .store
+            .values()
+            .filter(|todo| {
+                let item = &todo.item;
+                query.as_ref().is_none_or(|query| {
+                    item.title.to_lowercase().contains(query) || item.notes.to_lowercase().contains(query)
+                }) && assignee
+                    .as_ref()
+                    .is_none_or(|assignee| item.assigned_to.to_lowercase() == *assignee)
+                    && filter.completed.is_none_or(|completed| item.completed == completed)
+            })
+            .collect::<Vec<_>>();
+        // The store is ordered by id already
+        if sort != SortSpec::default() {
+            todos.sort_by(|a, b| sort.compare(a, b));
+        }
+
+        todos
+            .into_iter()
+            .skip(pagination.offset.unwrap_or(0))
+            .take(pagination.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect::<Vec<_>>()
+    }
+
+    /// Get number of todo items
+    pub fn count(&self) -> usize {
+        self.store.len()
+    }
+
     /// Get a single todo item by id
     pub fn get_todo(&self, id: usize) -> Option<&IdentifyableTodoItem> {
         self.store.get(&id)
     }
 
+    /// Checks whether todo items changed since they have been persisted the last time
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
     /// Create a new todo item
     pub fn add_todo(&mut self, todo: TodoItem) -> IdentifyableTodoItem {
         let id = self.id_generator.fetch_add(1, Ordering::Relaxed);
         let new_item = IdentifyableTodoItem::new(id, todo);
         self.store.insert(id, new_item.clone());
+        self.dirty.store(true, Ordering::Relaxed);
         new_item
     }
 
+    /// Create a new todo item unless one has already been created with the given key
+    ///
+    /// Makes retries of clients safe. If the key has been used within IDEMPOTENCY_KEY_TTL,
+    /// the originally created todo item is returned and the given one is ignored. The
+    /// returned flag is true if a new todo item has been created.
+    pub fn add_todo_with_key(&mut self, key: &str, todo: TodoItem) -> (IdentifyableTodoItem, bool) {
+        self.add_todo_with_key_at(key, todo, Instant::now())
+    }
+
+    fn add_todo_with_key_at(&mut self, key: &str, todo: TodoItem, now: Instant) -> (IdentifyableTodoItem, bool) {
+        self.idempotency_keys
+            .retain(|_, (created, _)| now.duration_since(*created) < IDEMPOTENCY_KEY_TTL);
+        if let Some((_, item)) = self.idempotency_keys.get(key) {
+            return (item.clone(), false);
+        }
+
+        let item = self.add_todo(todo);
+        self.idempotency_keys.insert(key.to_string(), (now, item.clone()));
+        (item, true)
+    }
+
     /// Remove a todo item by id
     pub fn remove_todo(&mut self, id: usize) -> Option<IdentifyableTodoItem> {
-        self.store.remove(&id)
+        let item = self.store.remove(&id)?;
+        self.dirty.store(true, Ordering::Relaxed);
+        Some(item)
     }
 
     /// Patch a todo item by id
+    ///
+    /// updated_at is only set if a field actually changed.
     pub fn update_todo(&mut self, id: &usize, todo: UpdateTodoItem) -> Option<&IdentifyableTodoItem> {
-        if let Some(item) = self.store.get_mut(id) {
-            if let Some(title) = todo.title {
-                item.item.title = title;
-            }
-            if let Some(notes) = todo.notes {
-                item.item.notes = notes;
-            }
-            if let Some(assigned_to) = todo.assigned_to {
-                item.item.assigned_to = assigned_to;
-            }
-            if let Some(completed) = todo.completed {
-                item.item.completed = completed;
-            }
-
-            Some(item)
-        } else {
-            None
+        let item = self.store.get_mut(id)?;
+        if todo.apply_to(&mut item.item) {
+            item.updated_at = Utc::now();
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        Some(item)
+    }
+
+    /// Replace all fields of a todo item by id
+    ///
+    /// updated_at is only set if a field actually changed.
+    pub fn replace_todo(&mut self, id: usize, todo: TodoItem) -> Option<&IdentifyableTodoItem> {
+        let item = self.store.get_mut(&id)?;
+        if item.item != todo {
+            item.item = todo;
+            item.updated_at = Utc::now();
         }
+        self.dirty.store(true, Ordering::Relaxed);
+        Some(item)
     }
 
     /// Store todo items to disk
     ///
     /// Used to demonstrate error handling.
-    #[cfg(feature = "persist")]
+    #[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
     pub async fn persist(&self) -> Result<(), TodoStoreError> {
-        const FILENAME: &str = "todo_store.json";
+        self.persist_to(Self::DEFAULT_FILENAME).await
+    }
 
+    /// Store todo items to the given file
+    ///
+    /// The todo items are written to a temporary file next to the given one
+    /// (`<filename>.tmp`), which then replaces the given file. As renaming is atomic
+    /// on the same file system, the file is never left half-written (e.g. if the
+    /// process is killed while writing).
+    ///
+    /// Resets the dirty flag if the todo items have been written successfully.
+    #[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+    pub async fn persist_to(&self, filename: impl AsRef<Path>) -> Result<(), TodoStoreError> {
         let json = serde_json::to_string_pretty(&self.store.values().collect::<Vec<&IdentifyableTodoItem>>())
             .map_err(TodoStoreError::SerializationError)?;
-        fs::write(FILENAME, json.as_bytes())
+        let mut tmp_filename = filename.as_ref().as_os_str().to_owned();
+        tmp_filename.push(".tmp");
+        fs::write(&tmp_filename, json.as_bytes())
+            .await
+            .map_err(TodoStoreError::FileAccessError)?;
+        fs::rename(&tmp_filename, filename)
             .await
             .map_err(TodoStoreError::FileAccessError)?;
+        self.dirty.store(false, Ordering::Relaxed);
         Ok(())
     }
+
+    /// Load todo items from a file written by persist_to
+    ///
+    /// Returns an empty store if the file does not exist (e.g. on first start).
+    /// Ids of new todo items continue after the largest loaded id.
+    #[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+    pub async fn load(filename: impl AsRef<Path>) -> Result<TodoStore, TodoStoreError> {
+        let json = match fs::read(filename).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(TodoStore::default()),
+            Err(e) => return Err(TodoStoreError::FileAccessError(e)),
+        };
+        let todos: Vec<IdentifyableTodoItem> =
+            serde_json::from_slice(&json).map_err(TodoStoreError::SerializationError)?;
+        Ok(TodoStore::from_hashmap(
+            todos.into_iter().map(|todo| (todo.id, todo)).collect(),
+        ))
+    }
 }
 
 impl From<TodoStore> for HashMap<usize, IdentifyableTodoItem> {
     fn from(value: TodoStore) -> Self {
-        value.store
+        value.store.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn todo_item() -> impl Strategy<Value = TodoItem> {
+        (any::<String>(), any::<String>(), any::<String>(), any::<bool>()).prop_map(
+            |(title, notes, assigned_to, completed)| TodoItem {
+                title,
+                notes,
+                assigned_to,
+                completed,
+            },
+        )
+    }
+
+    fn update_todo_item() -> impl Strategy<Value = UpdateTodoItem> {
+        (
+            any::<Option<String>>(),
+            any::<Option<String>>(),
+            any::<Option<String>>(),
+            any::<Option<bool>>(),
+        )
+            .prop_map(|(title, notes, assigned_to, completed)| UpdateTodoItem {
+                title,
+                notes,
+                assigned_to,
+                completed,
+            })
+    }
+
+    fn sample_todo() -> TodoItem {
+        TodoItem {
+            title: "Buy milk".to_string(),
+            notes: String::new(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[test]
+    fn dirty_flag() {
+        let mut store = TodoStore::default();
+        assert!(!store.is_dirty());
+        // Failed changes do not make the store dirty
+        assert!(store.remove_todo(42).is_none());
+        assert!(!store.is_dirty());
+
+        let id = store.add_todo(sample_todo()).id;
+        assert!(store.is_dirty());
+
+        let mut store = TodoStore::from_hashmap(store.into());
+        assert!(!store.is_dirty());
+        store.replace_todo(id, sample_todo());
+        assert!(store.is_dirty());
+    }
+
+    #[test]
+    fn search() {
+        let mut store = TodoStore::default();
+        let milk = store.add_todo(sample_todo());
+        let rust = store.add_todo(TodoItem {
+            title: "Learn Rust".to_string(),
+            notes: "Read the MILK book".to_string(),
+            ..sample_todo()
+        });
+        store.add_todo(TodoItem {
+            title: "Learn Go".to_string(),
+            ..sample_todo()
+        });
+
+        assert_eq!(store.search("mIlK", Pagination::default()), vec![milk, rust]);
+        assert!(store.search("python", Pagination::default()).is_empty());
+        assert_eq!(store.search("", Pagination::default()).len(), 3);
+        assert_eq!(store.search("", Pagination::new(Some(1), Some(1))).len(), 1);
+    }
+
+    #[test]
+    fn filter_by_assignee() {
+        let mut store = TodoStore::default();
+        let assigned = |who: &str, completed| TodoItem {
+            assigned_to: who.to_string(),
+            completed,
+            ..sample_todo()
+        };
+        let rainer = store.add_todo(assigned("Rainer", false));
+        let rainer_done = store.add_todo(assigned("rainer", true));
+        let karin = store.add_todo(assigned("Karin", false));
+
+        assert_eq!(
+            store.get_by_assignee("RAINER", Pagination::default()),
+            vec![rainer.clone(), rainer_done]
+        );
+        assert_eq!(store.get_by_assignee("karin", Pagination::default()), vec![karin]);
+        // Only exact matches count
+        assert!(store.get_by_assignee("Rai", Pagination::default()).is_empty());
+        assert!(store.get_by_assignee("Nobody", Pagination::default()).is_empty());
+
+        let filter = TodoFilter {
+            assigned_to: Some("Rainer".to_string()),
+            completed: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(store.get_filtered(&filter, Pagination::default()), vec![rainer]);
+        assert_eq!(store.get_filtered(&TodoFilter::default(), Pagination::default()).len(), 3);
+    }
+
+    #[test]
+    fn timestamps() {
+        let mut store = TodoStore::default();
+        let todo = store.add_todo(sample_todo());
+        assert_eq!(todo.updated_at, todo.created_at);
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let update = |completed| UpdateTodoItem {
+            title: None,
+            notes: None,
+            assigned_to: None,
+            completed: Some(completed),
+        };
+        let updated = store.update_todo(&todo.id, update(true)).unwrap().clone();
+        assert_eq!(updated.created_at, todo.created_at);
+        assert!(updated.updated_at > updated.created_at);
+
+        // Updates without changes keep the timestamp
+        let unchanged = store.update_todo(&todo.id, update(true)).unwrap();
+        assert_eq!(unchanged.updated_at, updated.updated_at);
+        let replaced = store.replace_todo(todo.id, updated.item.clone()).unwrap();
+        assert_eq!(replaced.updated_at, updated.updated_at);
+    }
+
+    #[test]
+    fn pages_are_stable() {
+        let mut store = TodoStore::default();
+        for _ in 0..25 {
+            store.add_todo(sample_todo());
+        }
+        store.remove_todo(3);
+
+        let first = store.get_todos(Pagination::new(None, Some(12)));
+        let second = store.get_todos(Pagination::new(Some(12), Some(12)));
+        let ids = first.iter().chain(&second).map(|todo| todo.id).collect::<Vec<_>>();
+        let expected = (0..25).filter(|id| *id != 3).collect::<Vec<_>>();
+        // Pages are sorted by id, without duplicates or gaps
+        assert_eq!(ids, expected);
+        assert_eq!(store.get_todos(Pagination::new(None, Some(12))), first);
+    }
+
+    #[test]
+    fn sorting() {
+        let mut store = TodoStore::default();
+        for (title, completed) in [("learn Rust", true), ("Buy milk", false), ("Walk the dog", true), ("buy bread", false)] {
+            store.add_todo(TodoItem {
+                title: title.to_string(),
+                completed,
+                ..sample_todo()
+            });
+        }
+        let ids = |sort: &str, pagination| {
+            let todos = store.get_sorted(sort.parse().unwrap(), pagination);
+            todos.into_iter().map(|todo| todo.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("id", Pagination::default()), [0, 1, 2, 3]);
+        assert_eq!(ids("-id", Pagination::default()), [3, 2, 1, 0]);
+        // Titles are compared case-insensitively
+        assert_eq!(ids("title", Pagination::default()), [3, 1, 0, 2]);
+        assert_eq!(ids("-title", Pagination::default()), [2, 0, 1, 3]);
+        // Ties are ordered by id
+        assert_eq!(ids("completed", Pagination::default()), [1, 3, 0, 2]);
+        assert_eq!(ids("-completed", Pagination::default()), [2, 0, 3, 1]);
+        assert_eq!(ids("title", Pagination::new(Some(1), Some(2))), [1, 0]);
+
+        let filter = TodoFilter {
+            completed: Some(false),
+            ..Default::default()
+        };
+        let todos = store.query(&filter, "-title".parse().unwrap(), Pagination::default());
+        assert_eq!(todos.into_iter().map(|todo| todo.id).collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[test]
+    fn page_size_is_limited() {
+        let mut store = TodoStore::default();
+        for _ in 0..Pagination::DEFAULT_MAX_LIMIT + 10 {
+            store.add_todo(sample_todo());
+        }
+
+        let page = store.get_todos(Pagination::new(None, Some(10_000)));
+        assert_eq!(page.len(), Pagination::DEFAULT_MAX_LIMIT);
+        let page = store.get_todos(Pagination::default());
+        assert_eq!(page.len(), Pagination::DEFAULT_MAX_LIMIT);
+        let page = store.get_todos(Pagination::new(Some(Pagination::DEFAULT_MAX_LIMIT), None));
+        assert_eq!(page.len(), 10);
+        assert_eq!(store.search("milk", Pagination::default()).len(), Pagination::DEFAULT_MAX_LIMIT);
+    }
+
+    #[test]
+    fn idempotency_keys() {
+        let mut store = TodoStore::default();
+        let (first, created) = store.add_todo_with_key("key-1", sample_todo());
+        assert!(created);
+        let (repeated, created) = store.add_todo_with_key("key-1", sample_todo());
+        assert!(!created);
+        assert_eq!(repeated.id, first.id);
+        assert_eq!(store.count(), 1);
+
+        let (other, created) = store.add_todo_with_key("key-2", sample_todo());
+        assert!(created);
+        assert_ne!(other.id, first.id);
+        assert_eq!(store.count(), 2);
+
+        // Expired keys create new todo items
+        let later = Instant::now() + IDEMPOTENCY_KEY_TTL;
+        let (expired, created) = store.add_todo_with_key_at("key-1", sample_todo(), later);
+        assert!(created);
+        assert_ne!(expired.id, first.id);
+    }
+
+    #[cfg(feature = "persist")]
+    #[tokio::test]
+    async fn persist_resets_dirty_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("todo_store.json");
+        let mut store = TodoStore::default();
+        store.add_todo(sample_todo());
+
+        store.persist_to(&filename).await.unwrap();
+        assert!(!store.is_dirty());
+
+        // Failed writes keep the flag
+        store.add_todo(sample_todo());
+        let missing_dir = dir.path().join("missing").join("todo_store.json");
+        assert!(store.persist_to(missing_dir).await.is_err());
+        assert!(store.is_dirty());
+    }
+
+    #[cfg(feature = "persist")]
+    #[tokio::test]
+    async fn persist_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("todo_store.json");
+        let mut store = TodoStore::default();
+        store.add_todo(sample_todo());
+        let removed = store.add_todo(sample_todo());
+        store.add_todo(sample_todo());
+        store.remove_todo(removed.id);
+        store.persist_to(&filename).await.unwrap();
+
+        let mut loaded = TodoStore::load(&filename).await.unwrap();
+        assert_eq!(loaded.count(), 2);
+        for todo in store.get_todos(Pagination::default()) {
+            assert_eq!(loaded.get_todo(todo.id), Some(&todo));
+        }
+        assert!(!loaded.is_dirty());
+
+        // New ids do not collide with loaded ones
+        assert_eq!(loaded.add_todo(sample_todo()).id, 3);
+    }
+
+    #[cfg(feature = "persist")]
+    #[tokio::test]
+    async fn load_missing_or_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("todo_store.json");
+        assert_eq!(TodoStore::load(&filename).await.unwrap().count(), 0);
+
+        std::fs::write(&filename, "not json").unwrap();
+        assert!(matches!(
+            TodoStore::load(&filename).await,
+            Err(TodoStoreError::SerializationError(_))
+        ));
+    }
+
+    #[cfg(feature = "persist")]
+    #[tokio::test]
+    async fn persist_to_given_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut store = TodoStore::default();
+        let todo = store.add_todo(sample_todo());
+
+        store.persist_to(file.path()).await.unwrap();
+        let content: Vec<IdentifyableTodoItem> =
+            serde_json::from_str(&std::fs::read_to_string(file.path()).unwrap()).unwrap();
+        assert_eq!(content, vec![todo]);
+    }
+
+    #[cfg(feature = "persist")]
+    #[tokio::test]
+    async fn persist_replaces_leftover_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("todo_store.json");
+        let tmp_filename = dir.path().join("todo_store.json.tmp");
+        // Simulates a previous write that has been interrupted
+        std::fs::write(&tmp_filename, r#"[{"id": 0, "tit"#).unwrap();
+        let mut store = TodoStore::default();
+        let todo = store.add_todo(sample_todo());
+
+        store.persist_to(&filename).await.unwrap();
+        let content: Vec<IdentifyableTodoItem> =
+            serde_json::from_str(&std::fs::read_to_string(&filename).unwrap()).unwrap();
+        assert_eq!(content, vec![todo]);
+        assert!(!tmp_filename.exists());
+    }
+
+    proptest! {
+        #[test]
+        fn update_changes_exactly_given_fields(todo in todo_item(), update in update_todo_item()) {
+            let mut store = TodoStore::default();
+            let id = store.add_todo(todo.clone()).id;
+
+            // Fields given in the update change, all others are preserved
+            let expected = TodoItem {
+                title: update.title.clone().unwrap_or(todo.title),
+                notes: update.notes.clone().unwrap_or(todo.notes),
+                assigned_to: update.assigned_to.clone().unwrap_or(todo.assigned_to),
+                completed: update.completed.unwrap_or(todo.completed),
+            };
+            let expected = serde_json::to_value(expected).unwrap();
+
+            let updated = store.update_todo(&id, update).unwrap();
+            prop_assert_eq!(updated.id, id);
+            prop_assert_eq!(serde_json::to_value(&updated.item).unwrap(), expected.clone());
+
+            // The change is stored, not only returned
+            let stored = store.get_todo(id).unwrap();
+            prop_assert_eq!(serde_json::to_value(&stored.item).unwrap(), expected);
+        }
+
+        #[test]
+        fn update_missing_todo(todo in todo_item(), update in update_todo_item(), id in 1usize..) {
+            let mut store = TodoStore::default();
+            store.add_todo(todo);
+
+            prop_assert!(store.update_todo(&id, update).is_none());
+            prop_assert_eq!(store.count(), 1);
+        }
     }
 }
@@ -0,0 +1,254 @@
+// Todo repository on top of Postgres
+//
+// Works like the data access layer of the hero manager sample: sqlx with plain
+// SQL statements and the table created by the migrations in ./migrations.
+//
+// The integration tests use sqlx::test (it creates a throwaway database for every
+// test). They need a running Postgres server (DATABASE_URL) and are therefore
+// only compiled with the integration-tests feature.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+
+use crate::{
+    DynTodoRepository, IdentifyableTodoItem, Pagination, TodoItem, TodoRepository, TodoStoreError, UpdateTodoItem,
+};
+
+/// Turns sqlx errors into errors of the todo store
+///
+/// Constraint violations are caused by the client (e.g. an empty title). Therefore,
+/// they become conflicts and validation errors instead of repository errors.
+impl From<sqlx::Error> for TodoStoreError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(db_error) if db_error.is_unique_violation() => TodoStoreError::Conflict(format!(
+                "unique constraint {} violated",
+                db_error.constraint().unwrap_or("unknown")
+            )),
+            sqlx::Error::Database(db_error) if db_error.is_check_violation() => {
+                TodoStoreError::ValidationError(format!(
+                    "check constraint {} violated",
+                    db_error.constraint().unwrap_or("unknown")
+                ))
+            },
+            e => TodoStoreError::RepositoryError(Box::new(e)),
+        }
+    }
+}
+
+/// Represents a row of the todos table
+#[derive(FromRow)]
+struct TodoRow {
+    id: i64,
+    title: String,
+    notes: String,
+    assigned_to: String,
+    completed: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<TodoRow> for IdentifyableTodoItem {
+    fn from(row: TodoRow) -> Self {
+        IdentifyableTodoItem {
+            id: row.id as usize,
+            item: TodoItem {
+                title: row.title,
+                notes: row.notes,
+                assigned_to: row.assigned_to,
+                completed: row.completed,
+            },
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Implementation of the todo repository
+pub struct PostgresTodoRepository(pub PgPool);
+
+impl PostgresTodoRepository {
+    /// Connects to the database and creates the todos table if necessary
+    pub async fn connect(database_url: &str) -> Result<DynTodoRepository, TodoStoreError> {
+        let pool = PgPool::connect(database_url).await?;
+        sqlx::migrate!()
+            .run(&pool)
+            .await
+            .map_err(|e| TodoStoreError::RepositoryError(Box::new(e)))?;
+        Ok(Arc::new(PostgresTodoRepository(pool)))
+    }
+}
+
+#[async_trait]
+impl TodoRepository for PostgresTodoRepository {
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError> {
+        // Negative values are rejected by Postgres, so large ones must not wrap around
+        let pagination = pagination.clamp(Pagination::DEFAULT_MAX_LIMIT);
+        let rows = sqlx::query_as::<_, TodoRow>("SELECT * FROM todos ORDER BY id LIMIT $1 OFFSET $2")
+            .bind(pagination.limit.map(|limit| limit as i64))
+            .bind(i64::try_from(pagination.offset.unwrap_or(0)).unwrap_or(i64::MAX))
+            .fetch_all(&self.0)
+            .await?;
+        Ok(rows.into_iter().map(IdentifyableTodoItem::from).collect())
+    }
+
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let row = sqlx::query_as::<_, TodoRow>("SELECT * FROM todos WHERE id = $1")
+            .bind(id as i64)
+            .fetch_optional(&self.0)
+            .await?;
+        Ok(row.map(IdentifyableTodoItem::from))
+    }
+
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError> {
+        let row = sqlx::query_as::<_, TodoRow>(
+            r#"
+            INSERT INTO todos (title, notes, assigned_to, completed)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *"#,
+        )
+        .bind(&todo.title)
+        .bind(&todo.notes)
+        .bind(&todo.assigned_to)
+        .bind(todo.completed)
+        .fetch_one(&self.0)
+        .await?;
+        Ok(row.into())
+    }
+
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let row = sqlx::query_as::<_, TodoRow>("DELETE FROM todos WHERE id = $1 RETURNING *")
+            .bind(id as i64)
+            .fetch_optional(&self.0)
+            .await?;
+        Ok(row.map(IdentifyableTodoItem::from))
+    }
+
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let row = sqlx::query_as::<_, TodoRow>(
+            r#"
+            UPDATE todos SET
+                title = COALESCE($2, title),
+                notes = COALESCE($3, notes),
+                assigned_to = COALESCE($4, assigned_to),
+                completed = COALESCE($5, completed),
+                -- Columns on the right side of SET have their old values
+                updated_at = CASE
+                    WHEN (COALESCE($2, title), COALESCE($3, notes), COALESCE($4, assigned_to), COALESCE($5, completed))
+                        IS DISTINCT FROM (title, notes, assigned_to, completed)
+                    THEN now()
+                    ELSE updated_at
+                END
+            WHERE id = $1
+            RETURNING *"#,
+        )
+        .bind(id as i64)
+        .bind(todo.title)
+        .bind(todo.notes)
+        .bind(todo.assigned_to)
+        .bind(todo.completed)
+        .fetch_optional(&self.0)
+        .await?;
+        Ok(row.map(IdentifyableTodoItem::from))
+    }
+}
+
+#[cfg(all(test, feature = "integration-tests"))]
+mod tests {
+    use super::*;
+
+    fn sample_todo(title: &str) -> TodoItem {
+        TodoItem {
+            title: title.to_string(),
+            notes: String::new(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[sqlx::test]
+    async fn add_and_get_todos(pool: PgPool) {
+        let repo = PostgresTodoRepository(pool);
+        let mut ids = Vec::new();
+        for title in ["Buy milk", "Learn Rust", "Walk the dog"] {
+            ids.push(repo.add_todo(sample_todo(title)).await.unwrap().id);
+        }
+
+        let todo = repo.get_todo(ids[1]).await.unwrap().unwrap();
+        assert_eq!(todo.item.title, "Learn Rust");
+        assert!(repo.get_todo(42).await.unwrap().is_none());
+
+        let todos = repo.get_todos(Pagination::default()).await.unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), ids);
+        let todos = repo.get_todos(Pagination::new(Some(1), Some(1))).await.unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), [ids[1]]);
+
+        // Huge values must not turn into negative LIMIT or OFFSET
+        let todos = repo
+            .get_todos(Pagination::new(Some(1), Some(usize::MAX)))
+            .await
+            .unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), ids[1..]);
+        let todos = repo
+            .get_todos(Pagination::new(Some(usize::MAX), Some(usize::MAX)))
+            .await
+            .unwrap();
+        assert!(todos.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn update_and_remove_todo(pool: PgPool) {
+        let repo = PostgresTodoRepository(pool);
+        let todo = repo.add_todo(sample_todo("Buy milk")).await.unwrap();
+
+        let update = UpdateTodoItem {
+            title: None,
+            notes: Some("Low fat".to_string()),
+            assigned_to: None,
+            completed: Some(true),
+        };
+        let updated = repo.update_todo(todo.id, update.clone()).await.unwrap().unwrap();
+        assert_eq!(updated.item.title, "Buy milk");
+        assert_eq!(updated.item.notes, "Low fat");
+        assert!(updated.item.completed);
+        assert!(repo.update_todo(42, update).await.unwrap().is_none());
+
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_some());
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_none());
+    }
+
+    #[sqlx::test]
+    async fn empty_title(pool: PgPool) {
+        let repo = PostgresTodoRepository(pool);
+        let result = repo.add_todo(sample_todo("")).await;
+        assert!(matches!(result, Err(TodoStoreError::ValidationError(_))));
+    }
+
+    #[sqlx::test]
+    async fn too_long_title_and_notes(pool: PgPool) {
+        let repo = PostgresTodoRepository(pool);
+        let result = repo.add_todo(sample_todo(&"x".repeat(201))).await;
+        assert!(matches!(result, Err(TodoStoreError::ValidationError(_))));
+
+        let todo = TodoItem {
+            notes: "x".repeat(2001),
+            ..sample_todo("Buy milk")
+        };
+        let result = repo.add_todo(todo).await;
+        assert!(matches!(result, Err(TodoStoreError::ValidationError(_))));
+
+        // The bounds themselves are fine
+        let todo = TodoItem {
+            notes: "x".repeat(2000),
+            ..sample_todo(&"x".repeat(200))
+        };
+        repo.add_todo(todo).await.unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+// Todo repository on top of Redis
+//
+// Every todo item is stored as a Redis hash. Ids are generated with a counter
+// (INCR) and all ids are kept in a sorted set (scored by id) so that pages of
+// todo items can be read with ZRANGE.
+//
+// The integration tests need a running Redis server (REDIS_URL, defaults to
+// redis://127.0.0.1/) and are therefore only compiled with the
+// integration-tests feature.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+
+use crate::{IdentifyableTodoItem, Pagination, TodoItem, TodoRepository, TodoStoreError, UpdateTodoItem};
+
+impl From<redis::RedisError> for TodoStoreError {
+    fn from(e: redis::RedisError) -> Self {
+        TodoStoreError::RepositoryError(Box::new(e))
+    }
+}
+
+/// Repository storing todo items in Redis
+#[derive(Clone)]
+pub struct RedisTodoRepository {
+    connection: ConnectionManager,
+    prefix: String,
+}
+
+impl RedisTodoRepository {
+    /// Connects to the Redis server at the given URL
+    ///
+    /// All keys start with the given prefix. With that, multiple todo lists can
+    /// share a Redis database.
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self, TodoStoreError> {
+        let client = redis::Client::open(url)?;
+        Ok(RedisTodoRepository {
+            connection: client.get_connection_manager().await?,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn next_id_key(&self) -> String {
+        format!("{}:next_id", self.prefix)
+    }
+
+    fn ids_key(&self) -> String {
+        format!("{}:ids", self.prefix)
+    }
+
+    fn todo_key(&self, id: usize) -> String {
+        format!("{}:todo:{id}", self.prefix)
+    }
+
+    /// Reads the hash of a todo item; None if it does not exist
+    async fn read_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let fields: HashMap<String, String> = self.connection.clone().hgetall(self.todo_key(id)).await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+        // Like in JSON, todo items stored without timestamps get the current time
+        let timestamp = |name: &str| {
+            fields
+                .get(name)
+                .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                .map_or_else(Utc::now, |timestamp| timestamp.with_timezone(&Utc))
+        };
+        Ok(Some(IdentifyableTodoItem {
+            id,
+            item: TodoItem {
+                title: field("title"),
+                notes: field("notes"),
+                assigned_to: field("assigned_to"),
+                completed: field("completed") == "true",
+            },
+            created_at: timestamp("created_at"),
+            updated_at: timestamp("updated_at"),
+        }))
+    }
+}
+
+/// Sets fields of a todo item's hash only if the todo item still exists
+///
+/// Checking and writing in one script makes the update atomic. Otherwise, an update
+/// racing with a remove would recreate the removed item as an incomplete hash.
+/// Returns 1 if the item has been updated, 0 if it does not exist.
+fn update_script() -> &'static Script {
+    static SCRIPT: OnceLock<Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| {
+        Script::new(
+            r"
+            if redis.call('EXISTS', KEYS[1]) == 0 then
+                return 0
+            end
+            redis.call('HSET', KEYS[1], unpack(ARGV))
+            return 1
+            ",
+        )
+    })
+}
+
+/// Turns the given fields into field/value pairs of a Redis hash
+fn to_fields(
+    title: Option<String>,
+    notes: Option<String>,
+    assigned_to: Option<String>,
+    completed: Option<bool>,
+) -> Vec<(&'static str, String)> {
+    [
+        ("title", title),
+        ("notes", notes),
+        ("assigned_to", assigned_to),
+        ("completed", completed.map(|completed| completed.to_string())),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|value| (name, value)))
+    .collect()
+}
+
+#[async_trait]
+impl TodoRepository for RedisTodoRepository {
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError> {
+        // Clamped pagination always has a limit
+        let pagination = pagination.clamp(Pagination::DEFAULT_MAX_LIMIT);
+        let start = pagination.offset.unwrap_or(0);
+        let stop = match pagination.limit.unwrap_or_default() {
+            0 => return Ok(Vec::new()),
+            // Ranges in Redis include the stop index
+            limit => start.saturating_add(limit - 1),
+        };
+        // Offsets beyond isize::MAX cannot match any item anyway
+        let (start, stop) = (
+            isize::try_from(start).unwrap_or(isize::MAX),
+            isize::try_from(stop).unwrap_or(isize::MAX),
+        );
+        let ids: Vec<usize> = self.connection.clone().zrange(self.ids_key(), start, stop).await?;
+
+        let mut todos = Vec::with_capacity(ids.len());
+        for id in ids {
+            // Items deleted since reading the ids are skipped
+            if let Some(todo) = self.read_todo(id).await? {
+                todos.push(todo);
+            }
+        }
+
+        Ok(todos)
+    }
+
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        self.read_todo(id).await
+    }
+
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError> {
+        let mut connection = self.connection.clone();
+
+        // Ids start at 0 like in TodoStore
+        let id = connection.incr::<_, _, usize>(self.next_id_key(), 1).await? - 1;
+        let new_item = IdentifyableTodoItem::new(id, todo);
+        let mut fields = to_fields(
+            Some(new_item.item.title.clone()),
+            Some(new_item.item.notes.clone()),
+            Some(new_item.item.assigned_to.clone()),
+            Some(new_item.item.completed),
+        );
+        fields.push(("created_at", new_item.created_at.to_rfc3339()));
+        fields.push(("updated_at", new_item.updated_at.to_rfc3339()));
+        redis::pipe()
+            .atomic()
+            .hset_multiple(self.todo_key(id), &fields)
+            .zadd(self.ids_key(), id, id)
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+
+        Ok(new_item)
+    }
+
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let Some(todo) = self.read_todo(id).await? else {
+            return Ok(None);
+        };
+
+        redis::pipe()
+            .atomic()
+            .del(self.todo_key(id))
+            .zrem(self.ids_key(), id)
+            .query_async::<_, ()>(&mut self.connection.clone())
+            .await?;
+        Ok(Some(todo))
+    }
+
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let Some(mut item) = self.read_todo(id).await? else {
+            return Ok(None);
+        };
+
+        // updated_at is only set if a field actually changes
+        let mut fields = to_fields(
+            todo.title.clone(),
+            todo.notes.clone(),
+            todo.assigned_to.clone(),
+            todo.completed,
+        );
+        if todo.apply_to(&mut item.item) {
+            item.updated_at = Utc::now();
+            fields.push(("updated_at", item.updated_at.to_rfc3339()));
+
+            let mut invocation = update_script().key(self.todo_key(id));
+            for (name, value) in &fields {
+                invocation.arg(*name).arg(value);
+            }
+            let updated: bool = invocation.invoke_async(&mut self.connection.clone()).await?;
+            if !updated {
+                // Removed since reading it
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(item))
+    }
+}
+
+#[cfg(all(test, feature = "integration-tests"))]
+mod tests {
+    use super::*;
+
+    /// Connects to the test server; every test uses its own prefix and starts with no keys
+    async fn repository(prefix: &str) -> RedisTodoRepository {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+        let repo = RedisTodoRepository::connect(&url, &format!("todo-logic-test:{prefix}"))
+            .await
+            .unwrap();
+
+        let mut connection = repo.connection.clone();
+        let keys: Vec<String> = connection.keys(format!("{}:*", repo.prefix)).await.unwrap();
+        if !keys.is_empty() {
+            connection.del::<_, ()>(keys).await.unwrap();
+        }
+
+        repo
+    }
+
+    fn sample_todo(title: &str) -> TodoItem {
+        TodoItem {
+            title: title.to_string(),
+            notes: String::new(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn add_and_get_todos() {
+        let repo = repository("add_and_get_todos").await;
+        for title in ["Buy milk", "Learn Rust", "Walk the dog"] {
+            repo.add_todo(sample_todo(title)).await.unwrap();
+        }
+
+        let todo = repo.get_todo(1).await.unwrap().unwrap();
+        assert_eq!(todo.item.title, "Learn Rust");
+        assert!(repo.get_todo(42).await.unwrap().is_none());
+
+        let todos = repo.get_todos(Pagination::default()).await.unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), [0, 1, 2]);
+        let todos = repo.get_todos(Pagination::new(Some(1), Some(1))).await.unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), [1]);
+
+        // Huge values must not overflow
+        let todos = repo
+            .get_todos(Pagination::new(Some(1), Some(usize::MAX)))
+            .await
+            .unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), [1, 2]);
+        let todos = repo
+            .get_todos(Pagination::new(Some(usize::MAX), Some(usize::MAX)))
+            .await
+            .unwrap();
+        assert!(todos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_and_remove_todo() {
+        let repo = repository("update_and_remove_todo").await;
+        let todo = repo.add_todo(sample_todo("Buy milk")).await.unwrap();
+
+        let update = UpdateTodoItem {
+            title: None,
+            notes: Some("Low fat".to_string()),
+            assigned_to: None,
+            completed: Some(true),
+        };
+        let updated = repo.update_todo(todo.id, update.clone()).await.unwrap().unwrap();
+        assert_eq!(updated.item.title, "Buy milk");
+        assert_eq!(updated.item.notes, "Low fat");
+        assert!(updated.item.completed);
+        assert!(repo.update_todo(42, update).await.unwrap().is_none());
+
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_some());
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_none());
+
+        // Updates of removed items must not recreate them
+        let update = UpdateTodoItem {
+            title: Some("Buy cheese".to_string()),
+            notes: None,
+            assigned_to: None,
+            completed: None,
+        };
+        assert!(repo.update_todo(todo.id, update).await.unwrap().is_none());
+        assert!(repo.get_todo(todo.id).await.unwrap().is_none());
+        assert!(repo.get_todos(Pagination::default()).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+#[cfg(any(test, feature = "mock"))]
+use mockall::automock;
+
+#[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+use crate::TodoStore;
+use crate::{IdentifyableTodoItem, Pagination, TodoItem, TodoStoreError, UpdateTodoItem};
+
+/// Repository for maintaining todo items
+///
+/// In contrast to TodoStore, implementations of this trait can keep the todo
+/// items outside of the process (e.g. in Redis). With that, multiple instances
+/// of an API can share the same todo items.
+///
+/// Handlers using the trait can be tested with MockTodoRepository (enable the
+/// mock feature in dev-dependencies).
+#[cfg_attr(any(test, feature = "mock"), automock)]
+#[async_trait]
+pub trait TodoRepository {
+    /// Get list of todo items
+    ///
+    /// Supports pagination.
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError>;
+
+    /// Get a single todo item by id
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError>;
+
+    /// Create a new todo item
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError>;
+
+    /// Create a new todo item unless one has already been created with the given key
+    ///
+    /// Returns the todo item and whether it has just been created (see
+    /// TodoStore::add_todo_with_key). Repositories that do not remember keys
+    /// create a new todo item every time.
+    async fn add_todo_with_key(
+        &self,
+        key: &str,
+        todo: TodoItem,
+    ) -> Result<(IdentifyableTodoItem, bool), TodoStoreError> {
+        let _ = key;
+        Ok((self.add_todo(todo).await?, true))
+    }
+
+    /// Remove a todo item by id
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError>;
+
+    /// Patch a todo item by id
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError>;
+
+    /// Make sure that all todo items are stored durably
+    ///
+    /// Repositories storing every change immediately (e.g. in a database) have nothing to do.
+    async fn persist(&self) -> Result<(), TodoStoreError> {
+        Ok(())
+    }
+}
+
+/// Type for sharing a repository between handlers
+pub type DynTodoRepository = Arc<dyn TodoRepository + Send + Sync>;
+
+/// In-memory todo items shared between handlers
+///
+/// TodoStore needs exclusive access for changes, so the repository is implemented
+/// for a lock around it. persist writes to TodoStore::DEFAULT_FILENAME.
+#[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+#[async_trait]
+impl TodoRepository for tokio::sync::RwLock<TodoStore> {
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError> {
+        Ok(self.read().await.get_todos(pagination))
+    }
+
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        Ok(self.read().await.get_todo(id).cloned())
+    }
+
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError> {
+        Ok(self.write().await.add_todo(todo))
+    }
+
+    async fn add_todo_with_key(
+        &self,
+        key: &str,
+        todo: TodoItem,
+    ) -> Result<(IdentifyableTodoItem, bool), TodoStoreError> {
+        Ok(self.write().await.add_todo_with_key(key, todo))
+    }
+
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        Ok(self.write().await.remove_todo(id))
+    }
+
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        Ok(self.write().await.update_todo(&id, todo).cloned())
+    }
+
+    async fn persist(&self) -> Result<(), TodoStoreError> {
+        self.read().await.persist().await
+    }
+}
+
+#[cfg(all(test, feature = "persist"))]
+mod tests {
+    use super::*;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn todo_store_repository() {
+        let repo: DynTodoRepository = Arc::new(RwLock::new(TodoStore::default()));
+        let todo = repo
+            .add_todo(TodoItem {
+                title: "Buy milk".to_string(),
+                notes: String::new(),
+                assigned_to: "Rainer".to_string(),
+                completed: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(repo.get_todo(todo.id).await.unwrap(), Some(todo.clone()));
+
+        let update = UpdateTodoItem {
+            title: None,
+            notes: None,
+            assigned_to: None,
+            completed: Some(true),
+        };
+        let updated = repo.update_todo(todo.id, update).await.unwrap().unwrap();
+        assert!(updated.item.completed);
+        assert_eq!(repo.get_todos(Pagination::default()).await.unwrap(), vec![updated]);
+
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_some());
+        assert_eq!(repo.get_todo(todo.id).await.unwrap(), None);
+
+        let (first, created) = repo.add_todo_with_key("key-1", todo.item.clone()).await.unwrap();
+        assert!(created);
+        let (repeated, created) = repo.add_todo_with_key("key-1", todo.item).await.unwrap();
+        assert!(!created);
+        assert_eq!(repeated, first);
+    }
+}
@@ -0,0 +1,202 @@
+// Todo repository on top of the sled embedded database
+//
+// sled stores the todo items in files on the local disk. In contrast to Redis and
+// Postgres, no server is needed, but todo items still survive restarts.
+// Items are serialized as JSON. Keys are the big-endian bytes of the ids so
+// that iterating over the tree returns the items ordered by id.
+//
+// The tests need the sled feature (cargo test -p todo-logic --features sled).
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use sled::Tree;
+
+use crate::{IdentifyableTodoItem, Pagination, TodoItem, TodoRepository, TodoStoreError, UpdateTodoItem};
+
+impl From<sled::Error> for TodoStoreError {
+    fn from(e: sled::Error) -> Self {
+        TodoStoreError::RepositoryError(Box::new(e))
+    }
+}
+
+/// Repository storing todo items in a sled database
+pub struct SledTodoRepository {
+    tree: Tree,
+    id_generator: AtomicUsize,
+}
+
+fn key(id: usize) -> [u8; 8] {
+    (id as u64).to_be_bytes()
+}
+
+impl SledTodoRepository {
+    /// Opens (or creates) the database in the given directory
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TodoStoreError> {
+        let tree = sled::open(path)?.open_tree("todos")?;
+
+        // Like TodoStore::from_hashmap, continue after the highest existing id
+        let id_generator = match tree.last()? {
+            Some((_, value)) => serde_json::from_slice::<IdentifyableTodoItem>(&value)?.id + 1,
+            None => 0,
+        };
+        Ok(SledTodoRepository {
+            tree,
+            id_generator: AtomicUsize::new(id_generator),
+        })
+    }
+}
+
+#[async_trait]
+impl TodoRepository for SledTodoRepository {
+    async fn get_todos(&self, pagination: Pagination) -> Result<Vec<IdentifyableTodoItem>, TodoStoreError> {
+        let pagination = pagination.clamp(Pagination::DEFAULT_MAX_LIMIT);
+        self.tree
+            .iter()
+            .values()
+            .skip(pagination.offset.unwrap_or(0))
+            .take(pagination.limit.unwrap_or_default())
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    async fn get_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        match self.tree.get(key(id))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn add_todo(&self, todo: TodoItem) -> Result<IdentifyableTodoItem, TodoStoreError> {
+        let id = self.id_generator.fetch_add(1, Ordering::Relaxed);
+        let new_item = IdentifyableTodoItem::new(id, todo);
+        self.tree.insert(key(id), serde_json::to_vec(&new_item)?)?;
+        self.tree.flush_async().await?;
+        Ok(new_item)
+    }
+
+    async fn remove_todo(&self, id: usize) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        let Some(value) = self.tree.remove(key(id))? else {
+            return Ok(None);
+        };
+
+        self.tree.flush_async().await?;
+        Ok(Some(serde_json::from_slice(&value)?))
+    }
+
+    async fn update_todo(
+        &self,
+        id: usize,
+        todo: UpdateTodoItem,
+    ) -> Result<Option<IdentifyableTodoItem>, TodoStoreError> {
+        // Compare-and-swap so that concurrent updates of the same item do not get lost
+        loop {
+            let Some(old_value) = self.tree.get(key(id))? else {
+                return Ok(None);
+            };
+
+            let mut item = serde_json::from_slice::<IdentifyableTodoItem>(&old_value)?;
+            if !todo.clone().apply_to(&mut item.item) {
+                return Ok(Some(item));
+            }
+            item.updated_at = Utc::now();
+            let new_value = serde_json::to_vec(&item)?;
+            if self
+                .tree
+                .compare_and_swap(key(id), Some(old_value), Some(new_value))?
+                .is_ok()
+            {
+                self.tree.flush_async().await?;
+                return Ok(Some(item));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_todo(title: &str) -> TodoItem {
+        TodoItem {
+            title: title.to_string(),
+            notes: String::new(),
+            assigned_to: "Rainer".to_string(),
+            completed: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn add_and_get_todos() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = SledTodoRepository::open(dir.path()).unwrap();
+        for title in ["Buy milk", "Learn Rust", "Walk the dog"] {
+            repo.add_todo(sample_todo(title)).await.unwrap();
+        }
+
+        let todo = repo.get_todo(1).await.unwrap().unwrap();
+        assert_eq!(todo.item.title, "Learn Rust");
+        assert!(repo.get_todo(42).await.unwrap().is_none());
+
+        let todos = repo.get_todos(Pagination::default()).await.unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), [0, 1, 2]);
+        let todos = repo.get_todos(Pagination::new(Some(1), Some(1))).await.unwrap();
+        assert_eq!(todos.iter().map(|todo| todo.id).collect::<Vec<_>>(), [1]);
+
+        // Page size is limited, with and without a limit given by the client
+        for id in 3..=Pagination::DEFAULT_MAX_LIMIT {
+            let todo = IdentifyableTodoItem::new(id, sample_todo("Buy milk"));
+            repo.tree.insert(key(id), serde_json::to_vec(&todo).unwrap()).unwrap();
+        }
+        for pagination in [Pagination::default(), Pagination::new(None, Some(usize::MAX))] {
+            let todos = repo.get_todos(pagination).await.unwrap();
+            assert_eq!(todos.len(), Pagination::DEFAULT_MAX_LIMIT);
+        }
+    }
+
+    #[tokio::test]
+    async fn update_and_remove_todo() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = SledTodoRepository::open(dir.path()).unwrap();
+        let todo = repo.add_todo(sample_todo("Buy milk")).await.unwrap();
+
+        let update = UpdateTodoItem {
+            title: None,
+            notes: Some("Low fat".to_string()),
+            assigned_to: None,
+            completed: Some(true),
+        };
+        let updated = repo.update_todo(todo.id, update.clone()).await.unwrap().unwrap();
+        assert_eq!(updated.item.title, "Buy milk");
+        assert_eq!(updated.item.notes, "Low fat");
+        assert!(updated.item.completed);
+        assert!(repo.update_todo(42, update).await.unwrap().is_none());
+
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_some());
+        assert!(repo.remove_todo(todo.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let repo = SledTodoRepository::open(dir.path()).unwrap();
+            repo.add_todo(sample_todo("Buy milk")).await.unwrap();
+            let todo = repo.add_todo(sample_todo("Learn Rust")).await.unwrap();
+            repo.remove_todo(todo.id).await.unwrap();
+        }
+
+        let repo = SledTodoRepository::open(dir.path()).unwrap();
+        let todos = repo.get_todos(Pagination::default()).await.unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].item.title, "Buy milk");
+
+        // New ids continue after the highest existing one
+        let todo = repo.add_todo(sample_todo("Walk the dog")).await.unwrap();
+        assert_eq!(todo.id, 1);
+    }
+}
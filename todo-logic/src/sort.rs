@@ -0,0 +1,105 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use crate::IdentifyableTodoItem;
+
+/// Field by which lists of todo items can be sorted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortField {
+    /// Creation order
+    #[default]
+    Id,
+    /// Case-insensitive
+    Title,
+    /// Open todo items first
+    Completed,
+}
+
+/// Order of lists of todo items
+///
+/// Parsed from the value of sort query parameters: the name of the field, prefixed
+/// with `-` for descending order (e.g. `title` or `-title`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortSpec {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl SortSpec {
+    /// Compares two todo items
+    ///
+    /// Id is used as a tie breaker so that pagination is stable.
+    pub fn compare(&self, a: &IdentifyableTodoItem, b: &IdentifyableTodoItem) -> Ordering {
+        let ordering = match self.field {
+            SortField::Id => Ordering::Equal,
+            SortField::Title => a.item.title.to_lowercase().cmp(&b.item.title.to_lowercase()),
+            SortField::Completed => a.item.completed.cmp(&b.item.completed),
+        }
+        .then(a.id.cmp(&b.id));
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Error for sort parameters naming unknown fields
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("cannot sort by unknown field '{0}' (supported: id, title, completed)")]
+pub struct ParseSortSpecError(String);
+
+impl FromStr for SortSpec {
+    type Err = ParseSortSpecError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (descending, field) = match value.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, value),
+        };
+        let field = match field {
+            "id" => SortField::Id,
+            "title" => SortField::Title,
+            "completed" => SortField::Completed,
+            _ => return Err(ParseSortSpecError(field.to_string())),
+        };
+
+        Ok(SortSpec { field, descending })
+    }
+}
+
+impl fmt::Display for SortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = match self.field {
+            SortField::Id => "id",
+            SortField::Title => "title",
+            SortField::Completed => "completed",
+        };
+        if self.descending {
+            f.write_str("-")?;
+        }
+        f.write_str(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sort_specs() {
+        for (value, field, descending) in [
+            ("id", SortField::Id, false),
+            ("title", SortField::Title, false),
+            ("-title", SortField::Title, true),
+            ("-completed", SortField::Completed, true),
+        ] {
+            let spec = value.parse::<SortSpec>().unwrap();
+            assert_eq!(spec, SortSpec { field, descending });
+            assert_eq!(spec.to_string(), value);
+        }
+
+        for invalid in ["", "-", "Title", "notes", "--id", "+id"] {
+            assert!(invalid.parse::<SortSpec>().is_err(), "{invalid}");
+        }
+    }
+}
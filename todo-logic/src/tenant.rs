@@ -0,0 +1,181 @@
+use std::{collections::HashMap, fmt};
+
+use crate::TodoStore;
+#[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+use crate::TodoStoreError;
+#[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+use std::path::Path;
+
+/// Id of a tenant (e.g. a user) owning a separate list of todo items
+///
+/// Tenant ids become part of file names when persisting. Therefore, they may only
+/// contain ASCII letters, digits, dashes, and underscores.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TenantId(String);
+
+impl TenantId {
+    /// Maximum length of a tenant id
+    pub const MAX_LEN: usize = 64;
+
+    /// Creates a tenant id; None if the id is empty, too long, or contains invalid characters
+    pub fn new(id: &str) -> Option<TenantId> {
+        let valid = !id.is_empty()
+            && id.len() <= Self::MAX_LEN
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| TenantId(id.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Todo items stores of multiple tenants
+///
+/// Every tenant has its own TodoStore. Tenants cannot see or change the todo
+/// items of other tenants.
+#[derive(Default)]
+pub struct TenantTodoStore {
+    stores: HashMap<TenantId, TodoStore>,
+}
+
+impl TenantTodoStore {
+    /// Get the todo items store of a tenant; None if the tenant has no todo items yet
+    pub fn get(&self, tenant: &TenantId) -> Option<&TodoStore> {
+        self.stores.get(tenant)
+    }
+
+    /// Get the todo items store of a tenant for changing it
+    ///
+    /// Creates an empty store if the tenant has none yet.
+    pub fn get_mut(&mut self, tenant: &TenantId) -> &mut TodoStore {
+        self.stores.entry(tenant.clone()).or_default()
+    }
+
+    /// Get number of tenants
+    pub fn count(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Checks whether the todo items of any tenant changed since they have been persisted
+    pub fn is_dirty(&self) -> bool {
+        self.stores.values().any(TodoStore::is_dirty)
+    }
+
+    /// Name of the file in which the todo items of a tenant are stored
+    pub fn filename(tenant: &TenantId) -> String {
+        format!("todo_store.{tenant}.json")
+    }
+
+    /// Store todo items of all tenants to disk in the current directory
+    #[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+    pub async fn persist(&self) -> Result<(), TodoStoreError> {
+        self.persist_to(".").await
+    }
+
+    /// Store todo items of all tenants to the given directory
+    ///
+    /// Every tenant gets its own file (see filename).
+    #[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+    pub async fn persist_to(&self, dir: impl AsRef<Path>) -> Result<(), TodoStoreError> {
+        for (tenant, store) in &self.stores {
+            store.persist_to(dir.as_ref().join(Self::filename(tenant))).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the todo items of all tenants from the files in the given directory
+    ///
+    /// Files that do not follow the naming scheme of persist are ignored.
+    #[cfg(all(feature = "persist", not(target_arch = "wasm32")))]
+    pub async fn load(dir: impl AsRef<Path>) -> Result<TenantTodoStore, TodoStoreError> {
+        let mut stores = HashMap::new();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let tenant = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix("todo_store.")?.strip_suffix(".json"))
+                .and_then(TenantId::new);
+            if let Some(tenant) = tenant {
+                stores.insert(tenant, TodoStore::load(entry.path()).await?);
+            }
+        }
+
+        Ok(TenantTodoStore { stores })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pagination, TodoItem};
+
+    fn sample_todo(title: &str) -> TodoItem {
+        TodoItem {
+            title: title.to_string(),
+            notes: String::new(),
+            assigned_to: String::new(),
+            completed: false,
+        }
+    }
+
+    #[test]
+    fn tenant_ids() {
+        assert!(TenantId::new("user-42_a").is_some());
+        for id in ["", "../etc", "a b", "ä", &"x".repeat(TenantId::MAX_LEN + 1)] {
+            assert!(TenantId::new(id).is_none(), "{id}");
+        }
+    }
+
+    #[test]
+    fn tenants_are_isolated() {
+        let (alice, bob) = (TenantId::new("alice").unwrap(), TenantId::new("bob").unwrap());
+        let mut stores = TenantTodoStore::default();
+        let todo = stores.get_mut(&alice).add_todo(sample_todo("Buy milk"));
+        assert!(stores.get(&bob).is_none());
+
+        // Ids are per tenant. Removing bob's todo 0 does not touch alice's todo 0.
+        stores.get_mut(&bob).add_todo(sample_todo("Learn Rust"));
+        assert!(stores.get_mut(&bob).remove_todo(todo.id).is_some());
+        assert_eq!(
+            stores.get(&alice).unwrap().get_todo(todo.id).unwrap().item.title,
+            "Buy milk"
+        );
+
+        let todos = stores.get(&alice).unwrap().get_todos(Pagination::default());
+        assert_eq!(todos.len(), 1);
+        assert_eq!(stores.get(&bob).unwrap().count(), 0);
+        assert_eq!(stores.count(), 2);
+        assert!(stores.is_dirty());
+    }
+
+    #[cfg(feature = "persist")]
+    #[tokio::test]
+    async fn persist_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice = TenantId::new("alice").unwrap();
+        let mut stores = TenantTodoStore::default();
+        stores.get_mut(&alice).add_todo(sample_todo("Buy milk"));
+        stores.persist_to(dir.path()).await.unwrap();
+        assert!(dir.path().join(TenantTodoStore::filename(&alice)).exists());
+        std::fs::write(dir.path().join("todo_store.json"), "[]").unwrap();
+
+        let stores = TenantTodoStore::load(dir.path()).await.unwrap();
+        assert_eq!(stores.count(), 1);
+        assert_eq!(stores.get(&alice).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn filenames() {
+        let tenant = TenantId::new("alice").unwrap();
+        assert_eq!(TenantTodoStore::filename(&tenant), "todo_store.alice.json");
+    }
+}
@@ -0,0 +1,135 @@
+// JavaScript bindings for running the todo logic in the browser
+//
+// Build with wasm-pack (e.g. wasm-pack build todo-logic --features wasm). Todo
+// items are passed to and from JavaScript as plain objects. They are converted
+// with the same serde models that the web APIs use for JSON.
+//
+// Persisting to disk is not available in WebAssembly. The tests run with
+// wasm-pack test --node todo-logic --features wasm.
+
+use validator::Validate;
+use wasm_bindgen::prelude::*;
+
+use crate::{Pagination, TodoItem, TodoStore, UpdateTodoItem};
+
+/// Todo items store usable from JavaScript (class TodoStore)
+#[wasm_bindgen(js_name = TodoStore)]
+#[derive(Default)]
+pub struct WasmTodoStore {
+    store: TodoStore,
+}
+
+#[wasm_bindgen(js_class = TodoStore)]
+impl WasmTodoStore {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmTodoStore {
+        WasmTodoStore::default()
+    }
+
+    /// Get list of todo items
+    ///
+    /// Supports pagination.
+    #[wasm_bindgen(js_name = getTodos)]
+    pub fn get_todos(&self, offset: Option<usize>, limit: Option<usize>) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self.store.get_todos(Pagination::new(offset, limit)),
+        )?)
+    }
+
+    /// Get a single todo item by id; undefined if it does not exist
+    #[wasm_bindgen(js_name = getTodo)]
+    pub fn get_todo(&self, id: usize) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.store.get_todo(id))?)
+    }
+
+    /// Create a new todo item
+    ///
+    /// Throws if the object is not a valid todo item.
+    #[wasm_bindgen(js_name = addTodo)]
+    pub fn add_todo(&mut self, todo: JsValue) -> Result<JsValue, JsError> {
+        let todo: TodoItem = serde_wasm_bindgen::from_value(todo)?;
+        todo.validate()?;
+        Ok(serde_wasm_bindgen::to_value(&self.store.add_todo(todo))?)
+    }
+
+    /// Patch a todo item by id; undefined if it does not exist
+    #[wasm_bindgen(js_name = updateTodo)]
+    pub fn update_todo(&mut self, id: usize, todo: JsValue) -> Result<JsValue, JsError> {
+        let todo: UpdateTodoItem = serde_wasm_bindgen::from_value(todo)?;
+        todo.validate()?;
+        Ok(serde_wasm_bindgen::to_value(&self.store.update_todo(&id, todo))?)
+    }
+
+    /// Remove a todo item by id; returns the removed item, undefined if it does not exist
+    #[wasm_bindgen(js_name = removeTodo)]
+    pub fn remove_todo(&mut self, id: usize) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.store.remove_todo(id))?)
+    }
+
+    /// Get number of todo items
+    pub fn count(&self) -> usize {
+        self.store.count()
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use crate::IdentifyableTodoItem;
+    use serde::Serialize;
+    use serde_json::json;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn to_js(value: serde_json::Value) -> JsValue {
+        value
+            .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .unwrap()
+    }
+
+    fn sample_todo(title: &str) -> JsValue {
+        to_js(json!({ "title": title, "notes": "", "assigned_to": "Rainer", "completed": false }))
+    }
+
+    fn from_js<T: serde::de::DeserializeOwned>(value: JsValue) -> T {
+        serde_wasm_bindgen::from_value(value).unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn add_and_get_todos() {
+        let mut store = WasmTodoStore::new();
+        let todo: IdentifyableTodoItem = from_js(store.add_todo(sample_todo("Buy milk")).unwrap());
+        store.add_todo(sample_todo("Learn Rust")).unwrap();
+        assert_eq!(store.count(), 2);
+
+        let found: Option<IdentifyableTodoItem> = from_js(store.get_todo(todo.id).unwrap());
+        assert_eq!(found.unwrap().item.title, "Buy milk");
+        assert!(store.get_todo(42).unwrap().is_undefined());
+
+        let todos: Vec<IdentifyableTodoItem> = from_js(store.get_todos(None, Some(1)).unwrap());
+        assert_eq!(todos.len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn invalid_todo() {
+        let mut store = WasmTodoStore::new();
+        assert!(store.add_todo(sample_todo("")).is_err());
+        assert!(store.add_todo(to_js(json!({ "title": "Buy milk" }))).is_err());
+        assert_eq!(store.count(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn update_and_remove_todo() {
+        let mut store = WasmTodoStore::new();
+        let todo: IdentifyableTodoItem = from_js(store.add_todo(sample_todo("Buy milk")).unwrap());
+
+        let updated: IdentifyableTodoItem =
+            from_js(store.update_todo(todo.id, to_js(json!({ "completed": true }))).unwrap());
+        assert_eq!(updated.item.title, "Buy milk");
+        assert!(updated.item.completed);
+        assert!(store.update_todo(42, to_js(json!({}))).unwrap().is_undefined());
+
+        assert!(!store.remove_todo(todo.id).unwrap().is_undefined());
+        assert!(store.remove_todo(todo.id).unwrap().is_undefined());
+        assert_eq!(store.count(), 0);
+    }
+}
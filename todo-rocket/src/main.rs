@@ -1,15 +1,23 @@
 #[macro_use]
 extern crate rocket;
 
-use log::{debug, LevelFilter};
+use app_config::AppConfig;
+use log::{debug, info, LevelFilter};
+use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
 use rocket::response::status::Created;
 use rocket::serde::json::Json;
+use rocket::serde::Serialize;
 use rocket::tokio::sync::RwLock;
-use rocket::{uri, State};
+use rocket::{uri, Build, Data, Request, Response, Rocket, State};
 use simplelog::{Config, SimpleLogger};
+use std::fmt::Display;
+use std::path::PathBuf;
 use std::sync::Arc;
 use todo_logic::{IdentifyableTodoItem, Pagination, TodoItem, TodoStore, TodoStoreError, UpdateTodoItem};
+use uuid::Uuid;
+use validator::{Validate, ValidationErrors};
 
 /// Type for our shared state
 ///
@@ -17,10 +25,13 @@ use todo_logic::{IdentifyableTodoItem, Pagination, TodoItem, TodoStore, TodoStor
 /// between concurrently running web requests, we need to make it thread-safe.
 type Db = Arc<RwLock<TodoStore>>;
 
+/// File to which the todo items are persisted
+struct PersistPath(PathBuf);
+
 /// Rocket relies heavily on macros. The launch macro will generate a
 /// tokio main function for us.
 #[launch]
-fn rocket() -> _ {
+async fn rocket() -> _ {
     // Initialize logging.
     // Rocket uses the log crate (https://crates.io/crates/log) to log requests. You can use any
     // compatible logger, but for this example we'll use simplelog. Enhancements in terms
@@ -28,19 +39,94 @@ fn rocket() -> _ {
     // (https://github.com/SergioBenitez/Rocket/issues/21).
     SimpleLogger::init(LevelFilter::Debug, Config::default()).unwrap();
 
-    // Create shared data store
-    let db = Db::default();
+    // Settings from app.toml, APP_* environment variables and command-line args
+    let config = AppConfig::load_from_args().expect("configuration is valid");
+
+    // Create shared data store with the todo items persisted by a previous run
+    let persist_path = config.persist_path;
+    let db = Arc::new(RwLock::new(
+        TodoStore::load(&persist_path)
+            .await
+            .expect("can load persisted todo items"),
+    ));
+
+    build_rocket(db, persist_path)
+}
 
+/// Build the Rocket instance with all routes and the given shared state
+///
+/// Separated from the launch function so that tests can create a Rocket
+/// instance without initializing logging.
+fn build_rocket(db: Db, persist_path: PathBuf) -> Rocket<Build> {
     rocket::build()
         // Here we mount our routes. More details about route mounting
         // at https://rocket.rs/v0.5-rc/guide/overview/#mounting.
         .mount(
             "/",
-            routes![get_todos, get_todo, add_todo, update_todo, delete_todo, persist],
+            routes![get_todos, get_todo, add_todo, update_todo, delete_todo, persist, health],
         )
         // Register our shared state.
         // More about using shared state at https://rocket.rs/v0.5-rc/guide/state/.
         .manage(db)
+        .manage(PersistPath(persist_path))
+        // Fairings are Rocket's approach to middleware.
+        // More about fairings at https://rocket.rs/v0.5-rc/guide/fairings/.
+        .attach(RequestIdFairing)
+}
+
+/// Name of the header carrying the correlation id
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Correlation id of a request
+///
+/// The id is stored in Rocket's request-local state. As it implements
+/// the FromRequest trait, handlers can use it as a request guard.
+struct RequestId(String);
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RequestId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(req.local_cache(|| RequestId(Uuid::new_v4().to_string())))
+    }
+}
+
+/// Fairing for correlation ids
+///
+/// Takes the id from the incoming X-Request-Id header or generates a new one.
+/// The id is logged and sent back to the client in the response.
+struct RequestIdFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = match req.headers().get_one(REQUEST_ID_HEADER) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => Uuid::new_v4().to_string(),
+        };
+        let id = req.local_cache(|| RequestId(id));
+        info!("[{}] {} {}", id, req.method(), req.uri());
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let id = req.local_cache(|| RequestId(Uuid::new_v4().to_string()));
+        info!("[{}] {}", id, res.status());
+        res.set_raw_header(REQUEST_ID_HEADER, id.0.clone());
+    }
 }
 
 /// Get list of todo items
@@ -77,14 +163,18 @@ async fn get_todo(id: usize, db: &State<Db>) -> Option<Json<IdentifyableTodoItem
 /// (https://rocket.rs/v0.5-rc/guide/requests/#custom-guards). Many things that you
 /// would do with middlewares in other frameworks are done with request guards in Rocket.
 #[post("/todos", format = "json", data = "<todo>")]
-async fn add_todo(todo: Json<TodoItem>, db: &State<Db>) -> Created<Json<IdentifyableTodoItem>> {
+async fn add_todo(todo: Json<TodoItem>, db: &State<Db>) -> Result<Created<Json<IdentifyableTodoItem>>, AppError> {
+    // Json only ensures that the body can be deserialized. Business rules
+    // are checked with the validator crate.
+    todo.validate()?;
+
     let mut todos = db.write().await;
     let todo = todos.add_todo(todo.0);
 
     // Nice detail here: The uri macro helps you to generate URIs for your routes.
     // Very useful for building the location header.
     let location = uri!("/", get_todo(todo.id));
-    Created::new(location.to_string()).body(Json(todo))
+    Ok(Created::new(location.to_string()).body(Json(todo)))
 }
 
 /// Delete a todo item
@@ -101,10 +191,16 @@ async fn delete_todo(id: usize, db: &State<Db>) -> Status {
 
 /// Update a todo item
 #[patch("/todos/<id>", format = "json", data = "<input>")]
-async fn update_todo(id: usize, input: Json<UpdateTodoItem>, db: &State<Db>) -> Option<Json<IdentifyableTodoItem>> {
+async fn update_todo(
+    id: usize,
+    input: Json<UpdateTodoItem>,
+    db: &State<Db>,
+) -> Result<Option<Json<IdentifyableTodoItem>>, AppError> {
+    input.validate()?;
+
     let mut todos = db.write().await;
     let res = todos.update_todo(&id, input.0);
-    res.map(|todo| Json(todo.clone()))
+    Ok(res.map(|todo| Json(todo.clone())))
 }
 
 /// Application-level error object
@@ -115,18 +211,143 @@ async fn update_todo(id: usize, input: Json<UpdateTodoItem>, db: &State<Db>) ->
 enum AppError {
     #[response(status = 500)]
     InternalError(String),
+    #[response(status = 422)]
+    UnprocessableEntity(Json<ValidationErrors>),
 }
 impl From<TodoStoreError> for AppError {
     fn from(inner: TodoStoreError) -> Self {
         AppError::InternalError(Json(inner).to_string())
     }
 }
+impl From<ValidationErrors> for AppError {
+    fn from(inner: ValidationErrors) -> Self {
+        AppError::UnprocessableEntity(Json(inner))
+    }
+}
+
+/// Response of the health route
+///
+/// Note that Rocket re-exports serde. Therefore, we have to tell serde's
+/// derive macros where to find the crate.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct HealthResponse {
+    version: &'static str,
+    count: usize,
+}
+
+/// Report version and number of todo items
+///
+/// Can be used e.g. for container probes.
+#[get("/health")]
+async fn health(db: &State<Db>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        count: db.read().await.count(),
+    })
+}
 
 /// Persist the todo store to disk
 #[post("/todos/persist")]
-async fn persist(db: &State<Db>) -> Result<(), AppError> {
-    debug!("Persisting todos");
+async fn persist(db: &State<Db>, path: &State<PersistPath>, request_id: &RequestId) -> Result<(), AppError> {
+    debug!("[{}] Persisting todos", request_id);
     let todos = db.read().await;
-    todos.persist().await?;
+    todos.persist_to(&path.0).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value};
+
+    fn client(db: Db) -> Client {
+        Client::tracked(build_rocket(db, PathBuf::from(TodoStore::DEFAULT_FILENAME))).unwrap()
+    }
+
+    #[test]
+    fn add_todo_with_empty_title() {
+        let client = client(Db::default());
+        let response = client
+            .post("/todos")
+            .header(ContentType::JSON)
+            .body(r#"{ "title": "", "notes": "", "assigned_to": "Rainer", "completed": false }"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn update_todo_with_empty_title() {
+        let db = Db::default();
+        let client = client(db);
+        client
+            .post("/todos")
+            .header(ContentType::JSON)
+            .body(r#"{ "title": "Learn Rust", "notes": "", "assigned_to": "Rainer", "completed": false }"#)
+            .dispatch();
+
+        let response = client
+            .patch("/todos/0")
+            .header(ContentType::JSON)
+            .body(r#"{ "title": "" }"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn request_id_round_trip() {
+        let client = client(Db::default());
+        let response = client
+            .get("/todos")
+            .header(Header::new(REQUEST_ID_HEADER, "my-id"))
+            .dispatch();
+
+        assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("my-id"));
+    }
+
+    #[test]
+    fn request_id_generated() {
+        let client = client(Db::default());
+        let response = client.get("/todos").dispatch();
+
+        let id = response.headers().get_one(REQUEST_ID_HEADER).unwrap();
+        assert!(Uuid::parse_str(id).is_ok());
+    }
+
+    #[test]
+    fn health() {
+        let client = client(Db::default());
+        client
+            .post("/todos")
+            .header(ContentType::JSON)
+            .body(r#"{ "title": "Learn Rust", "notes": "", "assigned_to": "Rainer", "completed": false }"#)
+            .dispatch();
+
+        let response = client.get("/health").dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body, json!({ "version": env!("CARGO_PKG_VERSION"), "count": 1 }));
+    }
+
+    #[test]
+    fn persist_to_configured_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let client = Client::tracked(build_rocket(Db::default(), file.path().to_path_buf())).unwrap();
+        client
+            .post("/todos")
+            .header(ContentType::JSON)
+            .body(r#"{ "title": "Learn Rust", "notes": "", "assigned_to": "Rainer", "completed": false }"#)
+            .dispatch();
+
+        let response = client.post("/todos/persist").dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let saved: Value = rocket::serde::json::from_str(&std::fs::read_to_string(file.path()).unwrap()).unwrap();
+        assert_eq!(saved[0]["title"], "Learn Rust");
+    }
+}
@@ -1,44 +1,93 @@
-use base64::{Engine, engine::general_purpose};
+use http::{header, StatusCode};
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use spin_sdk::{
     http::Request,
 };
-use todo_logic::{Pagination, TodoItem, TodoStore};
+use todo_logic::{Pagination, TodoItem, UpdateTodoItem};
+use uuid::Uuid;
+use validator::{Validate, ValidationErrors};
+
+use crate::{responders::Problem, router::Params, session::verify_session};
 
 // Rather naive, manual extractors. Anybody wants to write a framework for that? 😉
 
-pub fn extract_db(req: &Request) -> TodoStore {
-    if let Some(db) = req
+/// Error of an extractor caused by an invalid request
+#[derive(thiserror::Error, Debug)]
+pub enum ExtractError {
+    #[error("request body must be JSON")]
+    UnsupportedMediaType,
+    #[error("request body is missing")]
+    MissingBody,
+    #[error("request body is not valid UTF-8")]
+    InvalidEncoding(#[from] std::str::Utf8Error),
+    #[error("request body is not valid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("request body is not a valid todo item: {0}")]
+    InvalidTodo(#[from] ValidationErrors),
+    #[error("session cookie has an invalid signature")]
+    InvalidSession,
+    #[error("path does not contain a valid todo id")]
+    InvalidId,
+}
+
+impl ExtractError {
+    /// Gets the HTTP status code that is returned to the client
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidId => StatusCode::NOT_FOUND,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::InvalidTodo(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl From<&ExtractError> for Problem {
+    fn from(e: &ExtractError) -> Self {
+        Problem::new(e.status_code()).detail(e.to_string())
+    }
+}
+
+/// Gets the session id from the signed session cookie with the given name
+///
+/// If the client does not send a session cookie yet, a new session id is generated.
+/// Cookies with an invalid signature are rejected.
+pub fn extract_session(req: &Request, cookie_name: &str, key: &str) -> Result<String, ExtractError> {
+    let re = Regex::new(&format!(r"(?:^|;\s*){}=([^;\s]+)", regex::escape(cookie_name))).unwrap();
+    let cookie = req
         .headers()
         .get_all("cookie")
         .into_iter()
-        .find(|c| c.to_str().unwrap().starts_with("db="))
-    {
-        let db = db.to_str().unwrap();
-        let re = Regex::new(r"db=([a-zA-Z0-9]+)").unwrap();
-        let mut cap = re.captures_iter(db);
-        if let Some(re) = cap.next() {
-            let re = &re[1];
-            let db = general_purpose::STANDARD_NO_PAD.decode(re).unwrap();
-            return TodoStore::from_hashmap(serde_json::from_str(std::str::from_utf8(&db).unwrap()).unwrap());
-        }
-    }
+        .filter_map(|c| c.to_str().ok())
+        .find_map(|c| re.captures(c).map(|cap| cap[1].to_string()));
 
-    TodoStore::default()
+    match cookie {
+        Some(cookie) => verify_session(&cookie, key)
+            .map(str::to_string)
+            .ok_or(ExtractError::InvalidSession),
+        None => Ok(Uuid::new_v4().to_string()),
+    }
 }
 
+/// Maximum number of todos returned by a list request
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Gets the pagination data out of the query string
+///
+/// Malformed parameters are ignored. The limit is clamped to MAX_PAGE_SIZE.
 pub fn extract_pagination(req: &Request) -> Pagination {
     let query = req.uri().query().unwrap_or("");
     let mut pagination = Pagination::default();
 
     for pair in query.split('&').filter(|s| !s.is_empty()) {
-        let mut parts = pair.split('=');
-        let key = parts.next().unwrap();
-        let value = parts.next().unwrap();
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
 
         match key {
-            "offset" => pagination.offset = value.parse().map(Some).unwrap_or(None),
-            "limit" => pagination.limit = value.parse().map(Some).unwrap_or(None),
+            "offset" => pagination.offset = value.parse().ok(),
+            "limit" => pagination.limit = value.parse().ok().map(|limit: usize| limit.min(MAX_PAGE_SIZE)),
             _ => {},
         }
     }
@@ -46,16 +95,146 @@ pub fn extract_pagination(req: &Request) -> Pagination {
     pagination
 }
 
-pub fn extract_todo_item(req: &Request) -> TodoItem {
-    let body = req.body().as_ref().unwrap();
-    serde_json::from_str(std::str::from_utf8(body.as_ref()).unwrap()).unwrap()
+/// Gets the origin of a cross-origin request if it is allowed to use the API
+///
+/// Returns "*" if the origin is only allowed because all origins are (see
+/// add_cors_headers); such clients must not get access to the session.
+pub fn extract_cors_origin(req: &Request, allowed_origins: &[String]) -> Option<String> {
+    let origin = req.headers().get(header::ORIGIN)?.to_str().ok()?;
+    if allowed_origins.iter().any(|allowed| allowed == origin) {
+        Some(origin.to_string())
+    } else if allowed_origins.iter().any(|allowed| allowed == "*") {
+        Some("*".to_string())
+    } else {
+        None
+    }
+}
+
+/// Checks whether the If-Match header of a request matches the current ETag
+///
+/// Requests without If-Match always match, "*" matches every ETag.
+pub fn matches_if_match(req: &Request, etag: &str) -> bool {
+    let Some(if_match) = req.headers().get(header::IF_MATCH) else {
+        return true;
+    };
+
+    if_match
+        .to_str()
+        .map(|if_match| if_match.split(',').map(str::trim).any(|tag| tag == "*" || tag == etag))
+        .unwrap_or(false)
+}
+
+/// Gets the search text (q parameter) out of the query string
+pub fn extract_search(req: &Request) -> Option<String> {
+    let query = req.uri().query().unwrap_or("");
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "q")
+        .map(|(_, value)| value.into_owned())
+        .filter(|value| !value.is_empty())
 }
 
-pub fn extract_id(req: &Request) -> usize {
-    let path = req.uri().path().to_string();
-    let re = Regex::new(r"/todos/([0-9]+)").unwrap();
-    let mut cap = re.captures_iter(&path);
-    let re = cap.next().unwrap();
-    let re = &re[1];
-    re.parse().unwrap()
+pub fn extract_todo_item(req: &Request) -> Result<TodoItem, ExtractError> {
+    extract_json(req)
+}
+
+pub fn extract_update_todo_item(req: &Request) -> Result<UpdateTodoItem, ExtractError> {
+    extract_json(req)
+}
+
+/// Gets the JSON body, which has to pass the validation rules of its type
+fn extract_json<T: DeserializeOwned + Validate>(req: &Request) -> Result<T, ExtractError> {
+    // Content type may contain parameters (e.g. application/json; charset=utf-8)
+    let is_json = req
+        .headers()
+        .get("content-type")
+        .and_then(|c| c.to_str().ok())
+        .and_then(|c| c.split(';').next())
+        .is_some_and(|c| c.trim().eq_ignore_ascii_case("application/json"));
+    if !is_json {
+        return Err(ExtractError::UnsupportedMediaType);
+    }
+
+    let body = req.body().as_ref().ok_or(ExtractError::MissingBody)?;
+    let value: T = serde_json::from_str(std::str::from_utf8(body.as_ref())?)?;
+    value.validate()?;
+    Ok(value)
+}
+
+/// Gets the todo id from the path parameters
+///
+/// Only plain decimal numbers are valid ids (parse would also accept e.g. +1).
+pub fn extract_id(params: &Params) -> Result<usize, ExtractError> {
+    params
+        .get("id")
+        .filter(|id| id.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|id| id.parse().ok())
+        .ok_or(ExtractError::InvalidId)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str) -> Request {
+        http::Request::builder().uri(uri).body(None).unwrap()
+    }
+
+    #[test]
+    fn pagination() {
+        let pagination = extract_pagination(&request("/todos?offset=5&limit=10"));
+        assert_eq!((pagination.offset, pagination.limit), (Some(5), Some(10)));
+
+        let pagination = extract_pagination(&request("/todos"));
+        assert_eq!((pagination.offset, pagination.limit), (None, None));
+    }
+
+    #[test]
+    fn malformed_pagination() {
+        for uri in [
+            "/todos?offset",
+            "/todos?offset&limit=",
+            "/todos?=&&limit=x",
+            "/todos?offset=-1",
+        ] {
+            let pagination = extract_pagination(&request(uri));
+            assert_eq!((pagination.offset, pagination.limit), (None, None));
+        }
+
+        let pagination = extract_pagination(&request("/todos?offset&limit=3"));
+        assert_eq!((pagination.offset, pagination.limit), (None, Some(3)));
+    }
+
+    #[test]
+    fn pagination_limit_clamped() {
+        let pagination = extract_pagination(&request("/todos?limit=10000"));
+        assert_eq!(pagination.limit, Some(MAX_PAGE_SIZE));
+    }
+
+    #[test]
+    fn search() {
+        assert_eq!(
+            extract_search(&request("/todos?q=buy%20milk&limit=1")).as_deref(),
+            Some("buy milk")
+        );
+        assert_eq!(extract_search(&request("/todos?q=")), None);
+        assert_eq!(extract_search(&request("/todos")), None);
+    }
+
+    fn id_params(id: &str) -> Params {
+        Params::from([("id".to_string(), id.to_string())])
+    }
+
+    #[test]
+    fn valid_id() {
+        assert_eq!(extract_id(&id_params("42")).unwrap(), 42);
+    }
+
+    #[test]
+    fn invalid_id() {
+        for id in ["abc", "", "+1", "-1", "1.5", "99999999999999999999999"] {
+            assert!(matches!(extract_id(&id_params(id)), Err(ExtractError::InvalidId)));
+        }
+
+        assert!(matches!(extract_id(&Params::new()), Err(ExtractError::InvalidId)));
+    }
 }
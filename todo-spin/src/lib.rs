@@ -1,84 +1,676 @@
 use anyhow::Result;
-use http::{Method, StatusCode};
+use http::{header, Method, StatusCode};
 use spin_sdk::{
     http::{Request, Response},
     http_component,
+    key_value::Store,
 };
-use todo_logic::{IdentifyableTodoItem, Pagination, TodoItem, TodoStore};
+use todo_logic::{IdentifyableTodoItem, TodoStore};
 
 mod extractors;
 mod responders;
-use crate::{extractors::{extract_db, extract_pagination, extract_todo_item, extract_id}, responders::to_response};
+mod router;
+mod session;
+mod settings;
+mod storage;
+use crate::{
+    extractors::{
+        extract_cors_origin, extract_id, extract_pagination, extract_search, extract_session, extract_todo_item,
+        extract_update_todo_item, matches_if_match, ExtractError,
+    },
+    responders::{add_cors_headers, to_preflight_response, to_problem_response, to_response, Problem},
+    router::{Params, Router},
+    session::sign_session,
+    settings::Settings,
+    storage::{load_todos, save_todos, todos_key, RedisStorage, Storage, StoreTooLarge},
+};
 
 #[http_component]
 fn todo_manager(req: Request) -> Result<Response> {
-    let path = req.uri().path().to_string();
-
-    // In Spin, we cannot store data in memory. We have to persist or round-trip it anywhere.
-    // In this simple example, we use cookies to store the todos. We use a hand-written
-    // "extractor" to get the store from Spin's session cookie.
-    let mut db = extract_db(&req);
-
-    // In Spin, we don't have a fancy router yet. We have to manually match the path.
-    if path.ends_with("/todos") || path.ends_with("/todos/") {
-        match *req.method() {
-            Method::GET => {
-                // In Spin, there are no "extractors" yet. We have to manually get the
-                // pagination data out of the query string.
-                let pagination = extract_pagination(&req);
-                let result = get_todos(pagination, &db);
-
-                // In Spin, there are no "responders" yet. We have to manually turn
-                // our result into a HTTP response.
-                to_response(StatusCode::OK, Some(result), None)
-            },
-            Method::POST => {
-                let todo = extract_todo_item(&req);
-                let result = add_todo(todo, &mut db);
-                to_response(StatusCode::OK, Some(result), Some(db))
-            },
-            _ => to_response(StatusCode::METHOD_NOT_ALLOWED, None::<IdentifyableTodoItem>, None),
-        }
-    } else if path.starts_with("/todos/") {
-        let id = extract_id(&req);
-        match *req.method() {
-            Method::GET => {
-                let result = get_todo(id, &db);
-                to_response(match result {
-                    Some(_) => StatusCode::OK,
-                    None => StatusCode::NOT_FOUND,
-                }, result, None)
-            },
-            Method::DELETE => {
-                let res = delete_todo(id, &mut db);
-                to_response(
-                    match res {
-                        Some(_) => StatusCode::NO_CONTENT,
-                        None => StatusCode::NOT_FOUND,
-                    },
-                    None::<IdentifyableTodoItem>,
-                    Some(db),
-                )
-            },
-            _ => to_response(StatusCode::METHOD_NOT_ALLOWED, None::<IdentifyableTodoItem>, None),
-        }
-    } else {
-        to_response(StatusCode::NOT_FOUND, None::<IdentifyableTodoItem>, None)
+    let settings = Settings::from_spin_config()?;
+    match &settings.redis_address {
+        Some(address) => handle_request(req, &RedisStorage::new(address), &settings),
+        None => handle_request(req, &Store::open_default()?, &settings),
+    }
+}
+
+fn handle_request(req: Request, storage: &dyn Storage, settings: &Settings) -> Result<Response> {
+    // Browsers send the origin of clients from other origins (CORS)
+    let origin = extract_cors_origin(&req, &settings.allowed_origins);
+    if req.method() == Method::OPTIONS && req.headers().contains_key("Access-Control-Request-Method") {
+        // Preflight requests are answered without touching the todos
+        return to_preflight_response(origin.as_deref());
+    }
+
+    let mut response = match route_request(req, storage, settings) {
+        // Invalid requests are the client's fault, all other errors lead to 500
+        Err(e) => {
+            let problem = if let Some(e) = e.downcast_ref::<ExtractError>() {
+                Problem::from(e)
+            } else if let Some(e) = e.downcast_ref::<StoreTooLarge>() {
+                Problem::new(StatusCode::PAYLOAD_TOO_LARGE).detail(e.to_string())
+            } else {
+                e.downcast::<Problem>()?
+            };
+            to_problem_response(&problem)
+        },
+        response => response,
+    }?;
+
+    if let Some(origin) = origin {
+        add_cors_headers(&mut response, &origin)?;
+    }
+
+    Ok(response)
+}
+
+/// Owner of the todos if they are shared by all clients
+const SHARED_TODOS: &str = "shared";
+
+/// State shared by all handlers of a request
+struct Context<'a> {
+    storage: &'a dyn Storage,
+    /// Key of the todos in the storage (depends on the session id or SHARED_TODOS)
+    key: String,
+    /// Session cookie (name=signed session id) that is sent to the client
+    session_cookie: String,
+    db: TodoStore,
+    /// Version of the todos, incremented on every change (sent as ETag)
+    version: u64,
+}
+
+impl Context<'_> {
+    /// Saves the todos; has to be called after they have been changed
+    fn save(&mut self) -> Result<()> {
+        self.version += 1;
+        save_todos(self.storage, &self.key, std::mem::take(&mut self.db), self.version)
+    }
+
+    fn etag(&self) -> String {
+        format!("\"{}\"", self.version)
+    }
+}
+
+fn route_request(req: Request, storage: &dyn Storage, settings: &Settings) -> Result<Response> {
+    // In Spin, we cannot store data in memory. We have to persist it anywhere.
+    // In this simple example, we use Spin's key-value store. We use a hand-written
+    // "extractor" to get the session id from a cookie. It is the key for the todos.
+    let session = extract_session(&req, &settings.cookie_name, &settings.session_key)?;
+    let session_cookie = format!(
+        "{}={}",
+        settings.cookie_name,
+        sign_session(&session, &settings.session_key)
+    );
+
+    // With an external store, all clients share the same todos
+    let owner = match settings.redis_address {
+        Some(_) => SHARED_TODOS.to_string(),
+        None => session,
+    };
+    let key = todos_key(&settings.store_name, &owner);
+    let (db, version) = load_todos(storage, &key)?;
+    let mut context = Context {
+        storage,
+        key,
+        session_cookie,
+        db,
+        version,
+    };
+
+    // Changes based on an outdated version would overwrite changes of others
+    if req.method() != Method::GET && !matches_if_match(&req, &context.etag()) {
+        return Err(Problem::new(StatusCode::PRECONDITION_FAILED)
+            .detail("todos have been changed in the meantime")
+            .into());
+    }
+
+    // In Spin, we don't have a fancy router yet. We use a hand-written one.
+    let mut response = Router::new()
+        .route(Method::GET, "/todos", get_todos)
+        .route(Method::POST, "/todos", add_todo)
+        .route(Method::DELETE, "/todos", delete_todos)
+        .route(Method::GET, "/todos/{id}", get_todo)
+        .route(Method::PATCH, "/todos/{id}", update_todo)
+        .route(Method::PUT, "/todos/{id}", replace_todo)
+        .route(Method::DELETE, "/todos/{id}", delete_todo)
+        .handle(&req, &mut context)?;
+
+    if response.status().is_success() {
+        response.headers_mut().insert(header::ETAG, context.etag().parse()?);
     }
+
+    Ok(response)
+}
+
+/// Problem returned if a todo does not exist
+fn todo_not_found(id: usize) -> Problem {
+    Problem::new(StatusCode::NOT_FOUND)
+        .type_url("https://example.com/errors/not-found")
+        .title("Todo not found")
+        .detail(format!("todo {id} does not exist"))
+}
+
+fn get_todos(req: &Request, _: &Params, context: &mut Context) -> Result<Response> {
+    // In Spin, there are no "extractors" yet. We have to manually get the
+    // pagination data out of the query string.
+    let pagination = extract_pagination(req);
+    let result = match extract_search(req) {
+        Some(query) => context.db.search(&query, pagination),
+        None => context.db.get_todos(pagination),
+    };
+
+    // In Spin, there are no "responders" yet. We have to manually turn
+    // our result into a HTTP response.
+    to_response(StatusCode::OK, Some(result), None)
+}
+
+fn add_todo(req: &Request, _: &Params, context: &mut Context) -> Result<Response> {
+    let todo = extract_todo_item(req)?;
+    let result = context.db.add_todo(todo);
+    context.save()?;
+    to_response(StatusCode::OK, Some(result), Some(&context.session_cookie))
 }
 
-fn get_todos(pagination: Pagination, todos: &TodoStore) -> Vec<IdentifyableTodoItem> {
-    todos.get_todos(pagination)
+fn delete_todos(_: &Request, _: &Params, context: &mut Context) -> Result<Response> {
+    context.db = TodoStore::default();
+    context.save()?;
+    to_response(
+        StatusCode::NO_CONTENT,
+        None::<IdentifyableTodoItem>,
+        Some(&context.session_cookie),
+    )
 }
 
-fn add_todo(todo: TodoItem, todos: &mut TodoStore) -> IdentifyableTodoItem {
-    todos.add_todo(todo)
+fn get_todo(_: &Request, params: &Params, context: &mut Context) -> Result<Response> {
+    let id = extract_id(params)?;
+    let result = context.db.get_todo(id).ok_or_else(|| todo_not_found(id))?;
+    to_response(StatusCode::OK, Some(result), None)
 }
 
-fn delete_todo(id: usize, todos: &mut TodoStore) -> Option<IdentifyableTodoItem> {
-    todos.remove_todo(id)
+fn update_todo(req: &Request, params: &Params, context: &mut Context) -> Result<Response> {
+    let id = extract_id(params)?;
+    let todo = extract_update_todo_item(req)?;
+    let result = context
+        .db
+        .update_todo(&id, todo)
+        .cloned()
+        .ok_or_else(|| todo_not_found(id))?;
+    context.save()?;
+    to_response(StatusCode::OK, Some(result), Some(&context.session_cookie))
 }
 
-fn get_todo(id: usize, todos: &TodoStore) -> Option<&IdentifyableTodoItem> {
-    todos.get_todo(id)
+fn replace_todo(req: &Request, params: &Params, context: &mut Context) -> Result<Response> {
+    let id = extract_id(params)?;
+    let todo = extract_todo_item(req)?;
+    let result = context
+        .db
+        .replace_todo(id, todo)
+        .cloned()
+        .ok_or_else(|| todo_not_found(id))?;
+    context.save()?;
+    to_response(StatusCode::OK, Some(result), Some(&context.session_cookie))
+}
+
+fn delete_todo(_: &Request, params: &Params, context: &mut Context) -> Result<Response> {
+    let id = extract_id(params)?;
+    context.db.remove_todo(id).ok_or_else(|| todo_not_found(id))?;
+    context.save()?;
+    to_response(
+        StatusCode::NO_CONTENT,
+        None::<IdentifyableTodoItem>,
+        Some(&context.session_cookie),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryStorage, MAX_STORE_SIZE};
+    use serde_json::Value;
+
+    fn settings() -> Settings {
+        Settings {
+            session_key: "secret".to_string(),
+            redis_address: None,
+            cookie_name: "session".to_string(),
+            store_name: "todos".to_string(),
+            allowed_origins: vec!["https://example.com".to_string()],
+        }
+    }
+
+    /// Sends a request to the handler
+    fn send(storage: &MemoryStorage, method: Method, uri: &str, cookie: Option<&str>, body: Option<&str>) -> Response {
+        let mut builder = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Content-Type", "application/json");
+        if let Some(cookie) = cookie {
+            builder = builder.header("Cookie", cookie);
+        }
+        let req = builder.body(body.map(|body| body.to_string().into())).unwrap();
+        handle_request(req, storage, &settings()).unwrap()
+    }
+
+    fn json_body(response: &Response) -> Value {
+        serde_json::from_slice(response.body().as_ref().unwrap()).unwrap()
+    }
+
+    /// Adds a todo item in a new session and returns the session cookie
+    fn add_sample_todo(storage: &MemoryStorage) -> String {
+        let body = r#"{ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }"#;
+        let response = send(storage, Method::POST, "/todos", None, Some(body));
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response.headers()["Set-Cookie"].to_str().unwrap();
+        cookie.split(';').next().unwrap().to_string()
+    }
+
+    #[test]
+    fn todos_survive_across_requests() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        let response = send(&storage, Method::GET, "/todos", Some(&session), None);
+        assert_eq!(response.status(), StatusCode::OK);
+        let todos = json_body(&response);
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["title"], "Buy milk");
+
+        // Other sessions do not see the todos
+        let response = send(&storage, Method::GET, "/todos", None, None);
+        assert_eq!(json_body(&response).as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn patch_todo() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        let body = r#"{ "completed": true }"#;
+        let response = send(&storage, Method::PATCH, "/todos/0", Some(&session), Some(body));
+        assert_eq!(response.status(), StatusCode::OK);
+        let todo = json_body(&response);
+        assert_eq!(todo["title"], "Buy milk");
+        assert_eq!(todo["completed"], true);
+
+        let response = send(&storage, Method::GET, "/todos/0", Some(&session), None);
+        assert_eq!(json_body(&response)["completed"], true);
+
+        let response = send(&storage, Method::PATCH, "/todos/1", Some(&session), Some(body));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn put_todo() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        let body = r#"{ "title": "Buy bread", "notes": "Whole grain", "assigned_to": "Karin", "completed": true }"#;
+        let response = send(&storage, Method::PUT, "/todos/0", Some(&session), Some(body));
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(&storage, Method::GET, "/todos/0", Some(&session), None);
+        let todo = json_body(&response);
+        assert_eq!(todo["id"], 0);
+        assert_eq!(todo["title"], "Buy bread");
+        assert_eq!(todo["notes"], "Whole grain");
+        assert_eq!(todo["assigned_to"], "Karin");
+        assert_eq!(todo["completed"], true);
+    }
+
+    #[test]
+    fn put_missing_todo() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        let body = r#"{ "title": "Buy bread", "notes": "", "assigned_to": "Karin", "completed": false }"#;
+        let response = send(&storage, Method::PUT, "/todos/1", Some(&session), Some(body));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // PUT must not create todos
+        let response = send(&storage, Method::GET, "/todos", Some(&session), None);
+        assert_eq!(json_body(&response).as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn post_invalid_json() {
+        let storage = MemoryStorage::default();
+
+        let response = send(&storage, Method::POST, "/todos", None, Some(r#"{ "title": "#));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Required fields are missing
+        let body = r#"{ "title": "Buy milk" }"#;
+        let response = send(&storage, Method::POST, "/todos", None, Some(body));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = send(&storage, Method::POST, "/todos", None, None);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(&response)["detail"], "request body is missing");
+    }
+
+    #[test]
+    fn invalid_todos() {
+        let storage = MemoryStorage::default();
+
+        let long_title = "x".repeat(201);
+        for title in ["", long_title.as_str()] {
+            let body = serde_json::json!({ "title": title, "notes": "", "assigned_to": "Rainer", "completed": false });
+            let response = send(&storage, Method::POST, "/todos", None, Some(&body.to_string()));
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let session = add_sample_todo(&storage);
+        let body = r#"{ "title": "" }"#;
+        let response = send(&storage, Method::PATCH, "/todos/0", Some(&session), Some(body));
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = send(&storage, Method::GET, "/todos/0", Some(&session), None);
+        assert_eq!(json_body(&response)["title"], "Buy milk");
+    }
+
+    #[test]
+    fn post_wrong_content_type() {
+        let storage = MemoryStorage::default();
+
+        let body = r#"{ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }"#;
+        let req = http::Request::builder()
+            .method(Method::POST)
+            .uri("/todos")
+            .header("Content-Type", "text/plain")
+            .body(Some(body.to_string().into()))
+            .unwrap();
+        let response = handle_request(req, &storage, &settings()).unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn non_numeric_id() {
+        let storage = MemoryStorage::default();
+
+        let response = send(&storage, Method::GET, "/todos/abc", None, None);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn not_found_problem() {
+        let storage = MemoryStorage::default();
+        let response = send(&storage, Method::GET, "/todos/42", None, None);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+
+        let problem = json_body(&response);
+        assert_eq!(problem["type"], "https://example.com/errors/not-found");
+        assert_eq!(problem["title"], "Todo not found");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["detail"], "todo 42 does not exist");
+    }
+
+    #[test]
+    fn todo_paths() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        let response = send(&storage, Method::GET, "/todos/0", Some(&session), None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(&response)["id"], 0);
+
+        // Trailing slashes are ignored
+        let response = send(&storage, Method::GET, "/todos/0/", Some(&session), None);
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&storage, Method::GET, "/todos/", Some(&session), None);
+        assert_eq!(json_body(&response).as_array().unwrap().len(), 1);
+
+        for uri in ["/todos/+0", "/todos/0/notes"] {
+            let response = send(&storage, Method::GET, uri, Some(&session), None);
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let storage = MemoryStorage::default();
+        let response = send(&storage, Method::PATCH, "/todos", None, None);
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["Allow"], "GET, POST, DELETE");
+
+        let response = send(&storage, Method::POST, "/todos/0", None, None);
+        assert_eq!(response.headers()["Allow"], "GET, PATCH, PUT, DELETE");
+    }
+
+    #[test]
+    fn store_too_large() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        // Notes of a single todo are limited, so it takes many todos to exceed the limit
+        let notes = "x".repeat(2000);
+        let body = format!(r#"{{ "title": "Buy milk", "notes": "{notes}", "assigned_to": "", "completed": false }}"#);
+        let mut saved = 1;
+        let status = loop {
+            let response = send(&storage, Method::POST, "/todos", Some(&session), Some(&body));
+            if response.status() != StatusCode::OK {
+                break response.status();
+            }
+            saved += 1;
+            assert!(saved * notes.len() <= MAX_STORE_SIZE);
+        };
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Previously saved todos are kept
+        let response = send(&storage, Method::GET, "/todos", Some(&session), None);
+        assert_eq!(json_body(&response).as_array().unwrap().len(), saved);
+    }
+
+    #[test]
+    fn tampered_session_cookie() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+        let (_, signature) = session.rsplit_once('.').unwrap();
+
+        let cookies = [
+            format!("session=0815.{signature}"),
+            "session=0815".to_string(),
+            // Signature is not hex encoded
+            "session=0815.%%%".to_string(),
+        ];
+        for cookie in cookies {
+            let response = send(&storage, Method::GET, "/todos", Some(&cookie), None);
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn delete_all_todos() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+
+        let response = send(&storage, Method::DELETE, "/todos", Some(&session), None);
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = send(&storage, Method::GET, "/todos", Some(&session), None);
+        assert_eq!(json_body(&response).as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn shared_todos() {
+        // Stands in for Redis
+        let storage = MemoryStorage::default();
+        let settings = Settings {
+            redis_address: Some("redis://localhost:6379".to_string()),
+            ..settings()
+        };
+
+        let body = r#"{ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }"#;
+        let req = http::Request::builder()
+            .method(Method::POST)
+            .uri("/todos")
+            .header("Content-Type", "application/json")
+            .body(Some(body.to_string().into()))
+            .unwrap();
+        let response = handle_request(req, &storage, &settings).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A client with a different session sees the todo
+        let req = http::Request::builder().uri("/todos").body(None).unwrap();
+        let response = handle_request(req, &storage, &settings).unwrap();
+        assert_eq!(json_body(&response)[0]["title"], "Buy milk");
+    }
+
+    #[test]
+    fn configured_names() {
+        let storage = MemoryStorage::default();
+        let settings = Settings {
+            cookie_name: "todo-session".to_string(),
+            store_name: "my-todos".to_string(),
+            ..settings()
+        };
+
+        let body = r#"{ "title": "Buy milk", "notes": "", "assigned_to": "Rainer", "completed": false }"#;
+        let req = http::Request::builder()
+            .method(Method::POST)
+            .uri("/todos")
+            .header("Content-Type", "application/json")
+            .body(Some(body.to_string().into()))
+            .unwrap();
+        let response = handle_request(req, &storage, &settings).unwrap();
+        let cookie = response.headers()["Set-Cookie"].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap();
+        let (name, session) = cookie.split_once('=').unwrap();
+        assert_eq!(name, "todo-session");
+        let (session, _) = session.rsplit_once('.').unwrap();
+        assert!(storage.get(&format!("my-todos/{session}")).unwrap().is_some());
+
+        // The session is read from the configured cookie
+        let req = http::Request::builder()
+            .uri("/todos")
+            .header("Cookie", cookie)
+            .body(None)
+            .unwrap();
+        let response = handle_request(req, &storage, &settings).unwrap();
+        assert_eq!(json_body(&response)[0]["title"], "Buy milk");
+    }
+
+    #[test]
+    fn if_match() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+        let patch = |etag: &str| {
+            let req = http::Request::builder()
+                .method(Method::PATCH)
+                .uri("/todos/0")
+                .header("Content-Type", "application/json")
+                .header("Cookie", &session)
+                .header("If-Match", etag)
+                .body(Some(r#"{ "completed": true }"#.to_string().into()))
+                .unwrap();
+            handle_request(req, &storage, &settings()).unwrap()
+        };
+
+        let response = send(&storage, Method::GET, "/todos", Some(&session), None);
+        let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+        assert_eq!(etag, "\"1\"");
+
+        // Matching version
+        let response = patch(&etag);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ETag"], "\"2\"");
+
+        // Stale version
+        let response = patch(&etag);
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let response = send(&storage, Method::GET, "/todos", Some(&session), None);
+        assert_eq!(response.headers()["ETag"], "\"2\"");
+    }
+
+    #[test]
+    fn search_todos() {
+        let storage = MemoryStorage::default();
+        let session = add_sample_todo(&storage);
+        let body = r#"{ "title": "Learn Rust", "notes": "Read the book", "assigned_to": "", "completed": false }"#;
+        send(&storage, Method::POST, "/todos", Some(&session), Some(body));
+
+        let response = send(&storage, Method::GET, "/todos?q=MILK", Some(&session), None);
+        let todos = json_body(&response);
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["title"], "Buy milk");
+
+        // Notes are searched, too
+        let response = send(&storage, Method::GET, "/todos?q=the%20book", Some(&session), None);
+        assert_eq!(json_body(&response)[0]["title"], "Learn Rust");
+
+        let response = send(&storage, Method::GET, "/todos?q=bread", Some(&session), None);
+        assert_eq!(json_body(&response).as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn cors_preflight() {
+        let storage = MemoryStorage::default();
+        let preflight = |origin: &str| {
+            let req = http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/todos")
+                .header("Origin", origin)
+                .header("Access-Control-Request-Method", "POST")
+                .body(None)
+                .unwrap();
+            handle_request(req, &storage, &settings()).unwrap()
+        };
+
+        let response = preflight("https://example.com");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], "https://example.com");
+        assert_eq!(
+            response.headers()["Access-Control-Allow-Methods"],
+            "GET, POST, PUT, PATCH, DELETE"
+        );
+        assert_eq!(
+            response.headers()["Access-Control-Allow-Headers"],
+            "Content-Type, If-Match"
+        );
+
+        let response = preflight("https://evil.example.com");
+        assert!(!response.headers().contains_key("Access-Control-Allow-Origin"));
+    }
+
+    #[test]
+    fn cors_headers() {
+        let storage = MemoryStorage::default();
+        let req = http::Request::builder()
+            .uri("/todos")
+            .header("Origin", "https://example.com")
+            .body(None)
+            .unwrap();
+        let response = handle_request(req, &storage, &settings()).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], "https://example.com");
+        assert_eq!(response.headers()["Access-Control-Allow-Credentials"], "true");
+    }
+
+    #[test]
+    fn cors_wildcard_without_credentials() {
+        let storage = MemoryStorage::default();
+        let settings = Settings {
+            allowed_origins: vec!["https://example.com".to_string(), "*".to_string()],
+            ..settings()
+        };
+        let get = |origin: &str| {
+            let req = http::Request::builder()
+                .uri("/todos")
+                .header("Origin", origin)
+                .body(None)
+                .unwrap();
+            handle_request(req, &storage, &settings).unwrap()
+        };
+
+        // Every origin may read the response, but the browser does not send cookies
+        let response = get("https://evil.example.com");
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], "*");
+        assert!(!response
+            .headers()
+            .contains_key("Access-Control-Allow-Credentials"));
+
+        // Explicitly listed origins still get credentials
+        let response = get("https://example.com");
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], "https://example.com");
+        assert_eq!(response.headers()["Access-Control-Allow-Credentials"], "true");
+    }
 }
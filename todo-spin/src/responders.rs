@@ -1,18 +1,77 @@
-use std::collections::HashMap;
-
 use anyhow::Result;
-use base64::{engine::general_purpose, Engine};
-use http::StatusCode;
+use http::{header, HeaderValue, StatusCode};
 use serde::Serialize;
 use spin_sdk::http::Response;
-use todo_logic::{IdentifyableTodoItem, TodoStore};
 
 // Rather naive, manual responders. Anybody wants to write a framework for that? 😉
 
-pub fn to_response<T>(status: StatusCode, result: Option<T>, todos: Option<TodoStore>) -> Result<Response>
+/// Problem details (RFC 7807) describing an error
+///
+/// Handlers return it as an error to send a problem+json response to the client
+/// (like the errors of the hero manager).
+#[derive(thiserror::Error, Serialize, Debug)]
+#[error("{title}")]
+pub struct Problem {
+    #[serde(rename = "type")]
+    type_url: String,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl Problem {
+    /// Creates a problem with the status code's reason as title
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            type_url: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Unknown Error").to_string(),
+            status: status.as_u16(),
+            detail: None,
+        }
+    }
+
+    pub fn type_url(mut self, type_url: impl Into<String>) -> Self {
+        self.type_url = type_url.into();
+        self
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// Turns a problem into a HTTP response with content type application/problem+json
+pub fn to_problem_response(problem: &Problem) -> Result<Response> {
+    let body = serde_json::to_string_pretty(problem)?.as_bytes().to_vec();
+    Ok(http::Response::builder()
+        .status(problem.status_code())
+        .header("Content-Type", "application/problem+json")
+        .body(Some(body.into()))?)
+}
+
+/// Turns a result into a HTTP response
+///
+/// If a session cookie (name=value) is given, it is sent to the client. Error responses
+/// without a result get a problem+json body (like in the hero manager).
+pub fn to_response<T>(status: StatusCode, result: Option<T>, session_cookie: Option<&str>) -> Result<Response>
 where
     T: Serialize,
 {
+    if result.is_none() && (status.is_client_error() || status.is_server_error()) {
+        return to_problem_response(&Problem::new(status));
+    }
+
     let mut builder = http::Response::builder();
     let mut body = None;
 
@@ -22,11 +81,96 @@ where
         body = Some(response);
     }
 
-    if let Some(todos) = todos {
-        let db = serde_json::to_string(&Into::<HashMap<usize, IdentifyableTodoItem>>::into(todos))?;
-        let db = format!("db={}", general_purpose::STANDARD_NO_PAD.encode(db));
-        builder = builder.header("Set-Cookie", format!("{}; SameSite=Strict; Path=/", db));
+    if let Some(session_cookie) = session_cookie {
+        builder = builder.header(
+            "Set-Cookie",
+            format!("{}; SameSite=Strict; Path=/; HttpOnly", session_cookie),
+        );
     }
 
     Ok(builder.status(status).body(body.map(|body| body.into()))?)
 }
+
+/// Methods that browser clients from other origins may use
+const CORS_METHODS: &str = "GET, POST, PUT, PATCH, DELETE";
+
+/// Adds the headers allowing a browser client from another origin to read the response
+///
+/// Credentials are allowed for explicitly listed origins because the todos belong
+/// to the session in the cookie. For "*" (all origins), credentials are not allowed.
+/// Otherwise, every website could read the todos of its visitors.
+pub fn add_cors_headers(response: &mut Response, origin: &str) -> Result<()> {
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.parse()?);
+    if origin != "*" {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+    headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static("ETag"));
+    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    Ok(())
+}
+
+/// Answers a CORS preflight request
+///
+/// If the origin is not allowed, the response has no CORS headers. Therefore,
+/// the browser will not send the actual request.
+pub fn to_preflight_response(origin: Option<&str>) -> Result<Response> {
+    let mut response = to_response(StatusCode::NO_CONTENT, None::<()>, None)?;
+    if let Some(origin) = origin {
+        add_cors_headers(&mut response, origin)?;
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(CORS_METHODS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("Content-Type, If-Match"),
+        );
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("600"));
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn problem_body(response: &Response) -> Value {
+        assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+        serde_json::from_slice(response.body().as_ref().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn problem_with_details() {
+        let problem = Problem::new(StatusCode::CONFLICT)
+            .type_url("https://example.com/errors/conflict")
+            .title("Conflict")
+            .detail("todo has been changed");
+        let response = to_problem_response(&problem).unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let body = problem_body(&response);
+        assert_eq!(body["type"], "https://example.com/errors/conflict");
+        assert_eq!(body["title"], "Conflict");
+        assert_eq!(body["status"], 409);
+        assert_eq!(body["detail"], "todo has been changed");
+    }
+
+    #[test]
+    fn problem_without_details() {
+        let response = to_response(StatusCode::METHOD_NOT_ALLOWED, None::<()>, None).unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let body = problem_body(&response);
+        assert_eq!(body["type"], "about:blank");
+        assert_eq!(body["title"], "Method Not Allowed");
+        assert_eq!(body["status"], 405);
+        assert!(body.get("detail").is_none());
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use http::{header, Method, StatusCode};
+use spin_sdk::http::{Request, Response};
+use todo_logic::IdentifyableTodoItem;
+
+use crate::responders::to_response;
+
+// Rather naive, manual router. Just enough to register our routes declaratively.
+
+/// Values of the parameters in a route's path pattern (e.g. id in /todos/{id})
+pub type Params = HashMap<String, String>;
+
+/// Function handling requests of a route
+///
+/// The context contains state shared by all handlers (e.g. the todo store).
+pub type Handler<C> = fn(&Request, &Params, &mut C) -> Result<Response>;
+
+/// Segment of a path pattern
+enum Segment {
+    /// Segment that has to match exactly
+    Literal(String),
+    /// Segment matching any value, which is passed to the handler (e.g. {id})
+    Param(String),
+}
+
+struct Route<C> {
+    method: Method,
+    pattern: Vec<Segment>,
+    handler: Handler<C>,
+}
+
+/// Dispatches requests to handlers based on method and path
+pub struct Router<C> {
+    routes: Vec<Route<C>>,
+}
+
+impl<C> Default for Router<C> {
+    fn default() -> Self {
+        Router { routes: Vec::new() }
+    }
+}
+
+impl<C> Router<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for a method and a path pattern
+    ///
+    /// Segments in curly braces (e.g. /todos/{id}) are parameters.
+    pub fn route(mut self, method: Method, pattern: &str, handler: Handler<C>) -> Self {
+        let pattern = segments(pattern)
+            .map(|s| match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => Segment::Param(name.to_string()),
+                None => Segment::Literal(s.to_string()),
+            })
+            .collect();
+        self.routes.push(Route {
+            method,
+            pattern,
+            handler,
+        });
+        self
+    }
+
+    /// Calls the handler of the route matching the request
+    ///
+    /// Returns 404 if no route matches the path and 405 if routes match
+    /// the path, but none of them matches the method. 405 responses list the
+    /// methods of the matching routes in the Allow header.
+    pub fn handle(&self, req: &Request, context: &mut C) -> Result<Response> {
+        let path = segments(req.uri().path()).collect::<Vec<_>>();
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            if let Some(params) = match_path(&route.pattern, &path) {
+                if route.method == req.method() {
+                    return (route.handler)(req, &params, context);
+                }
+
+                allowed.push(route.method.as_str());
+            }
+        }
+
+        if allowed.is_empty() {
+            return to_response(StatusCode::NOT_FOUND, None::<IdentifyableTodoItem>, None);
+        }
+
+        let mut response = to_response(StatusCode::METHOD_NOT_ALLOWED, None::<IdentifyableTodoItem>, None)?;
+        response
+            .headers_mut()
+            .insert(header::ALLOW, allowed.join(", ").parse()?);
+        Ok(response)
+    }
+}
+
+/// Splits a path into its segments, ignoring leading and trailing slashes
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+/// Matches a path against a pattern; returns the parameter values if it matches
+fn match_path(pattern: &[Segment], path: &[&str]) -> Option<Params> {
+    if pattern.len() != path.len() {
+        return None;
+    }
+
+    let mut params = Params::new();
+    for (segment, value) in pattern.iter().zip(path) {
+        match segment {
+            Segment::Literal(literal) if literal == value => {},
+            Segment::Literal(_) => return None,
+            Segment::Param(name) => {
+                params.insert(name.clone(), value.to_string());
+            },
+        }
+    }
+
+    Some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: Method, uri: &str) -> Request {
+        http::Request::builder().method(method).uri(uri).body(None).unwrap()
+    }
+
+    fn list(_: &Request, _: &Params, calls: &mut Vec<String>) -> Result<Response> {
+        calls.push("list".to_string());
+        to_response(StatusCode::OK, None::<IdentifyableTodoItem>, None)
+    }
+
+    fn get(_: &Request, params: &Params, calls: &mut Vec<String>) -> Result<Response> {
+        calls.push(format!("get {}", params["id"]));
+        to_response(StatusCode::OK, None::<IdentifyableTodoItem>, None)
+    }
+
+    fn router() -> Router<Vec<String>> {
+        Router::new()
+            .route(Method::GET, "/todos", list)
+            .route(Method::POST, "/todos", list)
+            .route(Method::GET, "/todos/{id}", get)
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut calls = Vec::new();
+        for uri in ["/todos", "/todos/", "/todos?offset=1", "/todos/42"] {
+            let response = router().handle(&request(Method::GET, uri), &mut calls).unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        assert_eq!(calls, ["list", "list", "list", "get 42"]);
+    }
+
+    #[test]
+    fn unmatched_path() {
+        let mut calls = Vec::new();
+        for uri in ["/", "/heroes", "/todos/42/notes"] {
+            let response = router().handle(&request(Method::GET, uri), &mut calls).unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn unmatched_method() {
+        let mut calls = Vec::new();
+        let response = router().handle(&request(Method::DELETE, "/todos"), &mut calls).unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET, POST");
+        assert!(calls.is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// The session id is the key for the todos in the key-value store. To prevent clients
+// from tampering with it (e.g. guessing other sessions), the cookie contains the
+// session id together with its HMAC signature: <session id>.<hex encoded signature>
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Creates the value of the session cookie by signing the session id
+pub fn sign_session(session: &str, key: &str) -> String {
+    format!("{session}.{}", hex::encode(mac(session, key).finalize().into_bytes()))
+}
+
+/// Gets the session id from the value of the session cookie
+///
+/// Returns None if the signature is missing or does not match.
+pub fn verify_session<'a>(cookie: &'a str, key: &str) -> Option<&'a str> {
+    let (session, signature) = cookie.rsplit_once('.')?;
+    let signature = hex::decode(signature).ok()?;
+
+    // verify_slice compares in constant time
+    mac(session, key).verify_slice(&signature).ok()?;
+    Some(session)
+}
+
+fn mac(session: &str, key: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(session.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let cookie = sign_session("1234", "secret");
+        assert!(cookie.starts_with("1234."));
+        assert_eq!(verify_session(&cookie, "secret"), Some("1234"));
+    }
+
+    #[test]
+    fn reject_tampered_cookie() {
+        let cookie = sign_session("1234", "secret");
+        let (_, signature) = cookie.split_once('.').unwrap();
+
+        assert_eq!(verify_session(&format!("1235.{signature}"), "secret"), None);
+        assert_eq!(verify_session(&format!("1234.{}", &signature[1..]), "secret"), None);
+        assert_eq!(verify_session("1234", "secret"), None);
+        assert_eq!(verify_session(&cookie, "other secret"), None);
+    }
+}
@@ -0,0 +1,41 @@
+use anyhow::Result;
+use spin_sdk::config;
+
+/// Settings of the component, read from Spin's configuration variables
+pub struct Settings {
+    /// Secret key for signing the session cookie
+    pub session_key: String,
+    /// Address of a Redis server (e.g. redis://localhost:6379)
+    ///
+    /// If set, todos are stored in Redis and shared by all clients instead of
+    /// being stored per session in Spin's key-value store.
+    pub redis_address: Option<String>,
+    /// Name of the session cookie
+    pub cookie_name: String,
+    /// Name under which the todos are stored in the key-value store or Redis
+    ///
+    /// Allows multiple apps to share a store without overwriting each other's todos.
+    pub store_name: String,
+    /// Origins of browser clients that may use the API (CORS)
+    ///
+    /// "*" allows every origin, but without credentials (i.e. without the session cookie).
+    pub allowed_origins: Vec<String>,
+}
+
+impl Settings {
+    pub fn from_spin_config() -> Result<Self> {
+        Ok(Settings {
+            session_key: config::get("session_key")?,
+            redis_address: Some(config::get("redis_address")?).filter(|address| !address.is_empty()),
+            cookie_name: config::get("cookie_name")?,
+            store_name: config::get("store_name")?,
+            // Comma-separated list, e.g. https://example.com,http://localhost:8080
+            allowed_origins: config::get("allowed_origins")?
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
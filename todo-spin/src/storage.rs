@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use spin_sdk::{
+    key_value::{self, Store},
+    redis,
+};
+use todo_logic::{IdentifyableTodoItem, TodoStore};
+
+// In Spin, we cannot store data in memory between requests. Therefore, we keep the
+// todos of every session in Spin's key-value store. Alternatively, todos can be
+// stored in an external Redis server.
+
+/// Maximum size of the serialized todos of a session
+///
+/// Protects the key-value store from sessions growing without bounds.
+pub const MAX_STORE_SIZE: usize = 64 * 1024;
+
+/// Error when the todos of a session exceed the maximum size
+#[derive(thiserror::Error, Debug)]
+#[error("todos exceed the maximum size of {MAX_STORE_SIZE} bytes")]
+pub struct StoreTooLarge;
+
+/// Abstraction of the key-value store so that handlers can be tested without Spin
+pub trait Storage {
+    /// Gets the value of a key; None if the key does not exist
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Sets the value of a key
+    fn set(&self, key: &str, value: &[u8]) -> Result<()>;
+}
+
+impl Storage for Store {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match Store::get(self, key) {
+            Ok(value) => Ok(Some(value)),
+            Err(key_value::Error::NoSuchKey) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+        Ok(Store::set(self, key, value)?)
+    }
+}
+
+/// Storage in an external Redis server, accessed through Spin's outbound Redis API
+pub struct RedisStorage {
+    address: String,
+}
+
+impl RedisStorage {
+    pub fn new(address: &str) -> Self {
+        RedisStorage {
+            address: address.to_string(),
+        }
+    }
+}
+
+impl Storage for RedisStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        // Redis returns an empty value for keys that do not exist
+        let value = redis::get(&self.address, key)?;
+        Ok(Some(value).filter(|value| !value.is_empty()))
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+        Ok(redis::set(&self.address, key, value)?)
+    }
+}
+
+/// Todos as they are stored, with a version that is incremented on every save
+#[derive(Serialize, Deserialize)]
+struct StoredTodos {
+    version: u64,
+    todos: HashMap<usize, IdentifyableTodoItem>,
+}
+
+/// Gets the key under which the todos of an owner (e.g. a session) are stored
+pub fn todos_key(store_name: &str, owner: &str) -> String {
+    format!("{store_name}/{owner}")
+}
+
+/// Loads the todos stored under a key together with their version
+///
+/// A new session starts with an empty store and version 0. Stored todos that
+/// cannot be read (e.g. written by an incompatible version) are discarded. The
+/// session starts over with an empty store instead of failing every request.
+pub fn load_todos(storage: &dyn Storage, key: &str) -> Result<(TodoStore, u64)> {
+    let Some(db) = storage.get(key)? else {
+        return Ok((TodoStore::default(), 0));
+    };
+
+    let stored = serde_json::from_slice::<StoredTodos>(&db).or_else(|e| {
+        // Todos saved before versioning was introduced are a plain map
+        serde_json::from_slice(&db)
+            .map(|todos| StoredTodos { version: 0, todos })
+            .map_err(|_| e)
+    });
+    match stored {
+        Ok(stored) => Ok((TodoStore::from_hashmap(stored.todos), stored.version)),
+        Err(e) => {
+            eprintln!("Discarding unreadable todos in {key}: {e}");
+            Ok((TodoStore::default(), 0))
+        },
+    }
+}
+
+/// Saves the todos under a key with the given version
+///
+/// Fails with StoreTooLarge if the serialized todos exceed MAX_STORE_SIZE.
+/// In that case, the previously saved todos remain unchanged.
+pub fn save_todos(storage: &dyn Storage, key: &str, todos: TodoStore, version: u64) -> Result<()> {
+    let db = serde_json::to_vec(&StoredTodos {
+        version,
+        todos: todos.into(),
+    })?;
+    if db.len() > MAX_STORE_SIZE {
+        return Err(StoreTooLarge.into());
+    }
+
+    storage.set(key, &db)
+}
+
+/// In-memory storage replacing Spin's key-value store in tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStorage(std::cell::RefCell<HashMap<String, Vec<u8>>>);
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.borrow().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.0.borrow_mut().insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_corrupted_todos() {
+        let storage = MemoryStorage::default();
+        let key = todos_key("todos", "1234");
+        storage.set(&key, b"{ not json").unwrap();
+
+        let (todos, version) = load_todos(&storage, &key).unwrap();
+        assert_eq!(todos.count(), 0);
+        assert_eq!(version, 0);
+    }
+
+    #[test]
+    fn save_and_load_version() {
+        let storage = MemoryStorage::default();
+        let key = todos_key("todos", "1234");
+        save_todos(&storage, &key, TodoStore::default(), 42).unwrap();
+
+        let (_, version) = load_todos(&storage, &key).unwrap();
+        assert_eq!(version, 42);
+    }
+
+    #[test]
+    fn load_unversioned_todos() {
+        let storage = MemoryStorage::default();
+        let key = todos_key("todos", "1234");
+        let todos = r#"{ "0": { "id": 0, "title": "Buy milk", "notes": "", "assigned_to": "", "completed": false } }"#;
+        storage.set(&key, todos.as_bytes()).unwrap();
+
+        let (todos, version) = load_todos(&storage, &key).unwrap();
+        assert_eq!(todos.count(), 1);
+        assert_eq!(version, 0);
+    }
+}
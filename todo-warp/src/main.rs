@@ -1,9 +1,11 @@
-use std::{convert::Infallible, sync::Arc};
+use std::{convert::Infallible, path::Path, sync::Arc};
 
+use app_config::AppConfig;
 use log::{debug, LevelFilter};
 use simplelog::{Config, SimpleLogger};
 use todo_logic::{Pagination, TodoItem, TodoStore, TodoStoreError, UpdateTodoItem};
 use tokio::sync::RwLock;
+use validator::{Validate, ValidationErrors};
 use warp::http::StatusCode;
 use warp::{reject, reply};
 use warp::{Filter, Rejection, Reply};
@@ -11,6 +13,9 @@ use warp::{Filter, Rejection, Reply};
 /// Type for our shared state
 type Db = Arc<RwLock<TodoStore>>;
 
+/// File to which the todo items are persisted
+type PersistPath = Arc<Path>;
+
 #[tokio::main]
 async fn main() {
     // Initialize logging.
@@ -18,8 +23,16 @@ async fn main() {
     // compatible logger, but for this example we'll use simplelog.
     SimpleLogger::init(LevelFilter::Debug, Config::default()).unwrap();
 
-    // Create shared data store
-    let db = Db::default();
+    // Settings from app.toml, APP_* environment variables and command-line args
+    let config = AppConfig::load_from_args().expect("configuration is valid");
+
+    // Create shared data store with the todo items persisted by a previous run
+    let persist_path: PersistPath = Arc::from(config.persist_path.as_path());
+    let db = Arc::new(RwLock::new(
+        TodoStore::load(&persist_path)
+            .await
+            .expect("can load persisted todo items"),
+    ));
 
     // Note that you would probably create dedicated functions for each filter.
     // However, to make Warp's approach more obvious, we'll inline the filters.
@@ -67,6 +80,7 @@ async fn main() {
     let persist = warp::path!("todos" / "persist")
         .and(warp::post())
         .and(warp::any().map(move || persist_db.clone()))
+        .and(warp::any().map(move || persist_path.clone()))
         .and_then(persist)
         // The persist can handler can return a Rejection in case of an error.
         // Rejections are handled by the `recover` filter. It turns the error
@@ -107,10 +121,16 @@ async fn get_todo(id: usize, db: Db) -> Result<impl warp::Reply, Infallible> {
 }
 
 /// Add a new todo item
+///
+/// Invalid todo items (e.g. empty title) are rejected with 422.
 async fn add_todo(todo: TodoItem, db: Db) -> Result<impl warp::Reply, Infallible> {
+    if let Err(errors) = todo.validate() {
+        return Ok(invalid_todo(errors));
+    }
+
     let mut todos = db.write().await;
     let todo = todos.add_todo(todo.clone());
-    Ok(reply::json(&todo))
+    Ok(reply::json(&todo).into_response())
 }
 
 /// Delete a todo item
@@ -124,6 +144,10 @@ async fn delete_todo(id: usize, db: Db) -> Result<impl warp::Reply, Infallible>
 
 /// Update a todo item
 async fn update_todo(id: usize, input: UpdateTodoItem, db: Db) -> Result<impl warp::Reply, Infallible> {
+    if let Err(errors) = input.validate() {
+        return Ok(invalid_todo(errors));
+    }
+
     let mut todos = db.write().await;
     let res = todos.update_todo(&id, input);
     match res {
@@ -132,6 +156,11 @@ async fn update_todo(id: usize, input: UpdateTodoItem, db: Db) -> Result<impl wa
     }
 }
 
+/// Response for todo items violating validation rules; lists the failed rules per field
+fn invalid_todo(errors: ValidationErrors) -> warp::reply::Response {
+    reply::with_status(reply::json(&errors), StatusCode::UNPROCESSABLE_ENTITY).into_response()
+}
+
 /// Application-level error object
 #[derive(Debug)]
 enum AppError {
@@ -146,13 +175,13 @@ impl From<TodoStoreError> for AppError {
 /// Add marker trait to AppError for custom rejections
 impl reject::Reject for AppError {}
 
-async fn persist(db: Db) -> Result<impl warp::Reply, Rejection> {
+async fn persist(db: Db, path: PersistPath) -> Result<impl warp::Reply, Rejection> {
     // Write a log message
     debug!("Persisting todos");
 
     let todos = db.read().await;
     todos
-        .persist()
+        .persist_to(&path)
         .await
         // In case of an error, we return a custom rejection. It will be handled
         // by teh `recover` filter.
@@ -164,13 +193,22 @@ async fn persist(db: Db) -> Result<impl warp::Reply, Rejection> {
 async fn handle_rejection(err: Rejection) -> Result<impl Reply, std::convert::Infallible> {
     if let Some(e) = err.find::<AppError>() {
         return match e {
-            AppError::UserRepo(e) => Ok(reply::with_status(
-                match e {
-                    TodoStoreError::FileAccessError(_) => "Error while writing to file",
-                    TodoStoreError::SerializationError(_) => "Error during serialization",
+            AppError::UserRepo(e) => Ok(match e {
+                TodoStoreError::FileAccessError(_) => {
+                    reply::with_status("Error while writing to file", StatusCode::INTERNAL_SERVER_ERROR)
+                },
+                TodoStoreError::SerializationError(_) => {
+                    reply::with_status("Error during serialization", StatusCode::INTERNAL_SERVER_ERROR)
+                },
+                TodoStoreError::RepositoryError(_) => reply::with_status(
+                    "Error while accessing the repository",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+                TodoStoreError::Conflict(_) => {
+                    reply::with_status("Conflict with an existing todo item", StatusCode::CONFLICT)
                 },
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )),
+                TodoStoreError::ValidationError(_) => reply::with_status("Invalid todo item", StatusCode::BAD_REQUEST),
+            }),
         };
     }
     Ok(reply::with_status(